@@ -0,0 +1,32 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    generate_grpc_service();
+}
+
+/// Generates the `Generator` service stubs around the hand-written messages in
+/// `src/grpc.rs`, so building needs no `protoc`. `proto/generator.proto`
+/// describes the same service for clients.
+#[cfg(feature = "grpc")]
+fn generate_grpc_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let generate = Method::builder()
+        .name("generate")
+        .route_name("Generate")
+        .input_type("crate::grpc::GenerateRequest")
+        .output_type("crate::grpc::EncodedBatch")
+        .codec_path("tonic::codec::ProstCodec")
+        .server_streaming()
+        .build();
+
+    let service = Service::builder()
+        .name("Generator")
+        .package("fastjsongen.v1")
+        .method(generate)
+        .build();
+
+    Builder::new()
+        .build_client(false)
+        .build_transport(false)
+        .compile(&[service]);
+}
@@ -0,0 +1,218 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use crc32fast::Hasher;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const END: u32 = 0x0605_4b50;
+
+/// ZIP 4.5, the first version with ZIP64.
+const VERSION: u16 = 45;
+/// Sizes and CRC follow the data, and names are UTF-8.
+const FLAGS: u16 = 0x0008 | 0x0800;
+/// 1980-01-01 00:00, the earliest DOS date, so archives are reproducible.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+struct Entry {
+    name: String,
+    offset: u64,
+    size: u64,
+    crc: u32,
+}
+
+/// Writes a ZIP archive as it is generated, without knowing any entry's size
+/// up front. Entries are stored uncompressed, with their sizes and CRC in a
+/// data descriptor after the data and ZIP64 fields throughout, so no entry or
+/// archive is too large.
+///
+/// Each method returns the bytes to send next; the entry data itself is sent
+/// as it is, after passing it to [`ZipWriter::data`].
+pub(crate) struct ZipWriter {
+    entries: Vec<Entry>,
+    offset: u64,
+    hasher: Hasher,
+    size: u64,
+}
+
+impl ZipWriter {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            offset: 0,
+            hasher: Hasher::new(),
+            size: 0,
+        }
+    }
+
+    /// The local header that starts entry `name`.
+    pub(crate) fn start(&mut self, name: &str) -> Bytes {
+        self.hasher = Hasher::new();
+        self.size = 0;
+        self.entries.push(Entry {
+            name: name.to_owned(),
+            offset: self.offset,
+            size: 0,
+            crc: 0,
+        });
+
+        let mut header = BytesMut::with_capacity(30 + name.len() + 20);
+        header.put_u32_le(LOCAL_HEADER);
+        header.put_u16_le(VERSION);
+        header.put_u16_le(FLAGS);
+        header.put_u16_le(0); // stored
+        header.put_u16_le(DOS_TIME);
+        header.put_u16_le(DOS_DATE);
+        header.put_u32_le(0); // CRC, in the data descriptor
+        header.put_u32_le(u32::MAX);
+        header.put_u32_le(u32::MAX);
+        header.put_u16_le(name.len() as u16);
+        header.put_u16_le(20);
+        header.put_slice(name.as_bytes());
+        header.put_u16_le(ZIP64_EXTRA);
+        header.put_u16_le(16);
+        header.put_u64_le(0);
+        header.put_u64_le(0);
+        self.offset += header.len() as u64;
+        header.freeze()
+    }
+
+    /// Counts `data` into the current entry.
+    pub(crate) fn data(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        self.offset += data.len() as u64;
+    }
+
+    /// The data descriptor that ends the current entry.
+    pub(crate) fn finish_entry(&mut self) -> Bytes {
+        let crc = std::mem::take(&mut self.hasher).finalize();
+        let entry = self
+            .entries
+            .last_mut()
+            .expect("an entry is started before it is finished");
+        entry.crc = crc;
+        entry.size = self.size;
+
+        let mut descriptor = BytesMut::with_capacity(24);
+        descriptor.put_u32_le(DATA_DESCRIPTOR);
+        descriptor.put_u32_le(crc);
+        descriptor.put_u64_le(self.size);
+        descriptor.put_u64_le(self.size);
+        self.offset += descriptor.len() as u64;
+        descriptor.freeze()
+    }
+
+    /// The central directory and end records that close the archive.
+    pub(crate) fn finish(self) -> Bytes {
+        let mut end = BytesMut::new();
+        for entry in &self.entries {
+            end.put_u32_le(CENTRAL_HEADER);
+            end.put_u16_le(VERSION);
+            end.put_u16_le(VERSION);
+            end.put_u16_le(FLAGS);
+            end.put_u16_le(0);
+            end.put_u16_le(DOS_TIME);
+            end.put_u16_le(DOS_DATE);
+            end.put_u32_le(entry.crc);
+            end.put_u32_le(u32::MAX);
+            end.put_u32_le(u32::MAX);
+            end.put_u16_le(entry.name.len() as u16);
+            end.put_u16_le(28);
+            end.put_u16_le(0); // comment
+            end.put_u16_le(0); // disk
+            end.put_u16_le(0); // internal attributes
+            end.put_u32_le(0); // external attributes
+            end.put_u32_le(u32::MAX);
+            end.put_slice(entry.name.as_bytes());
+            end.put_u16_le(ZIP64_EXTRA);
+            end.put_u16_le(24);
+            end.put_u64_le(entry.size);
+            end.put_u64_le(entry.size);
+            end.put_u64_le(entry.offset);
+        }
+        let directory_size = end.len() as u64;
+        let entries = self.entries.len() as u64;
+
+        let zip64_end = self.offset + directory_size;
+        end.put_u32_le(ZIP64_END);
+        end.put_u64_le(44);
+        end.put_u16_le(VERSION);
+        end.put_u16_le(VERSION);
+        end.put_u32_le(0);
+        end.put_u32_le(0);
+        end.put_u64_le(entries);
+        end.put_u64_le(entries);
+        end.put_u64_le(directory_size);
+        end.put_u64_le(self.offset);
+
+        end.put_u32_le(ZIP64_LOCATOR);
+        end.put_u32_le(0);
+        end.put_u64_le(zip64_end);
+        end.put_u32_le(1);
+
+        end.put_u32_le(END);
+        end.put_u16_le(0);
+        end.put_u16_le(0);
+        end.put_u16_le(u16::MAX);
+        end.put_u16_le(u16::MAX);
+        end.put_u32_le(u32::MAX);
+        end.put_u32_le(u32::MAX);
+        end.put_u16_le(0);
+        end.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn entries_are_described_after_their_data() {
+        let mut zip = ZipWriter::new();
+        let mut archive = Vec::new();
+        for (name, parts) in [("a.csv", &["id\n", "1\n"][..]), ("b.csv", &[][..])] {
+            archive.extend_from_slice(&zip.start(name));
+            for part in parts {
+                zip.data(part.as_bytes());
+                archive.extend_from_slice(part.as_bytes());
+            }
+            archive.extend_from_slice(&zip.finish_entry());
+        }
+        let directory_offset = archive.len() as u64;
+        archive.extend_from_slice(&zip.finish());
+
+        assert_eq!(u32_at(&archive, 0), LOCAL_HEADER);
+        assert_eq!(&archive[30..35], b"a.csv");
+        // The descriptor follows the header, its ZIP64 extra field and the data.
+        let descriptor = 30 + 5 + 20 + 5;
+        assert_eq!(u32_at(&archive, descriptor), DATA_DESCRIPTOR);
+        assert_eq!(
+            u32_at(&archive, descriptor + 4),
+            crc32fast::hash(b"id\n1\n")
+        );
+        assert_eq!(u64_at(&archive, descriptor + 8), 5);
+
+        // The end record points through the ZIP64 locator to the directory.
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(&archive, end), END);
+        let locator = end - 20;
+        assert_eq!(u32_at(&archive, locator), ZIP64_LOCATOR);
+        let zip64_end = u64_at(&archive, locator + 8) as usize;
+        assert_eq!(u32_at(&archive, zip64_end), ZIP64_END);
+        assert_eq!(u64_at(&archive, zip64_end + 32), 2);
+        assert_eq!(u64_at(&archive, zip64_end + 48), directory_offset);
+        assert_eq!(u32_at(&archive, directory_offset as usize), CENTRAL_HEADER);
+    }
+}
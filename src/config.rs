@@ -0,0 +1,893 @@
+#[cfg(feature = "cli")]
+use crate::gaps::Gap;
+use crate::gaps::GapRates;
+use crate::logging::LogFormat;
+use crate::numeric::NumericOptions;
+#[cfg(feature = "cli")]
+use crate::numeric::{parse_correlation, NumericField, RevenueType};
+use crate::pipeline::GenerationRequest;
+use crate::pools::{Distribution, PoolField, PoolSource};
+use crate::processing::{Locale, OutputFormat, OPTIMAL_CHUNK_SIZE, POOL_SIZE};
+use crate::raw::parse_params;
+use crate::util::parse_size;
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "cli")]
+use clap::Parser;
+#[cfg(feature = "cli")]
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+#[cfg(feature = "cli")]
+use std::path::Path;
+use std::path::PathBuf;
+
+pub const DEFAULT_BIND: &str = "127.0.0.1:8080";
+pub const DEFAULT_CHUNK_SIZE: u64 = 256 * 1024 * 1024;
+pub const DEFAULT_CHANNEL_DEPTH: usize = 16;
+pub const DEFAULT_MAX_REQUEST_SIZE: u64 = 1024_u64.pow(4);
+pub const DEFAULT_MAX_CONCURRENT: usize = 4;
+pub const DEFAULT_MAX_QUEUED: usize = 16;
+pub const DEFAULT_QUEUE_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+pub const DEFAULT_LOG_FILTER: &str = "info";
+pub const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
+
+/// Command line arguments. Every flag can also be set through its `FJG_*`
+/// environment variable or the TOML file passed with `--config`.
+/// Precedence is CLI > environment > config file > built-in default.
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug, Default)]
+#[command(
+    name = "fast-json-gen",
+    version,
+    about = "Streaming synthetic data server"
+)]
+pub struct Cli {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "FJG_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, repeatable. `host:port`, `[v6]:port` or `unix:/path`
+    #[arg(short, long = "bind", env = "FJG_BIND", value_delimiter = ',')]
+    pub bind: Vec<String>,
+
+    /// Number of HTTP worker threads
+    #[arg(short, long, env = "FJG_WORKERS")]
+    pub workers: Option<usize>,
+
+    /// Total generation threads, split evenly between concurrent generations.
+    /// Each generation gets `generation_threads / max_concurrent` threads even
+    /// when it runs alone
+    #[arg(short = 't', long, env = "FJG_GENERATION_THREADS")]
+    pub generation_threads: Option<usize>,
+
+    /// Bytes generated per parallel generation task, e.g. `256mb`
+    #[arg(long, env = "FJG_CHUNK_SIZE", value_parser = parse_byte_size)]
+    pub chunk_size: Option<u64>,
+
+    /// Target size of each chunk pushed to the response stream, e.g. `16kb`
+    #[arg(long, env = "FJG_STREAM_CHUNK_SIZE", value_parser = parse_byte_size)]
+    pub stream_chunk_size: Option<u64>,
+
+    /// Number of values sampled into each data pool
+    #[arg(long, env = "FJG_POOL_SIZE")]
+    pub pool_size: Option<usize>,
+
+    /// Pool file replacing a field's sampled values, repeatable. `field=path`, or `field=path#column` for CSV
+    #[arg(long = "pool", env = "FJG_POOLS", value_delimiter = ',')]
+    pub pools: Vec<String>,
+
+    /// Directory that per-request `pool.<field>=<file>` parameters are read from; unset, they are refused
+    #[arg(long, env = "FJG_POOL_DIR")]
+    pub pool_dir: Option<PathBuf>,
+
+    /// How often each value of a field's pool comes up, repeatable. `field=uniform`, `field=weighted`, `field=zipf[:exponent]` or `field=normal[:mean[:std_dev]]`
+    #[arg(
+        long = "distribution",
+        env = "FJG_DISTRIBUTIONS",
+        value_delimiter = ','
+    )]
+    pub distributions: Vec<String>,
+
+    /// How revenue is written: `f32`, `f64`, either with `:places` to round, `decimal[:places]` or `cents`
+    #[arg(long, env = "FJG_REVENUE_TYPE", value_parser = parse_revenue_type)]
+    pub revenue_type: Option<RevenueType>,
+
+    /// Correlation between employees and revenue, from -1 to 1
+    #[arg(long, env = "FJG_CORRELATION", value_parser = parse_correlation)]
+    pub correlation: Option<f64>,
+
+    /// Fraction of records where a field is `null`, repeatable. `field=rate`, or `*=rate` for every field
+    #[arg(long = "null", env = "FJG_NULLS", value_delimiter = ',')]
+    pub nulls: Vec<String>,
+
+    /// Fraction of records where a field's key is left out, repeatable. `field=rate` or `*=rate`
+    #[arg(long = "missing", env = "FJG_MISSING", value_delimiter = ',')]
+    pub missing: Vec<String>,
+
+    /// Fraction of records where a field is an empty string, repeatable. `field=rate` or `*=rate`
+    #[arg(long = "empty", env = "FJG_EMPTY", value_delimiter = ',')]
+    pub empty: Vec<String>,
+
+    /// Locales whose pools are built at startup, repeatable. The first is used when a request has no `locale`
+    #[arg(long = "locale", env = "FJG_LOCALES", value_delimiter = ',', value_parser = parse_locale)]
+    pub locales: Vec<Locale>,
+
+    /// Number of chunks buffered between the generator and the response
+    #[arg(long, env = "FJG_CHANNEL_DEPTH")]
+    pub channel_depth: Option<usize>,
+
+    /// Largest `size` a single request may ask for, e.g. `100gb`
+    #[arg(long, env = "FJG_MAX_REQUEST_SIZE", value_parser = parse_byte_size)]
+    pub max_request_size: Option<u64>,
+
+    /// Format used when a request has no `format` parameter
+    #[arg(long, env = "FJG_DEFAULT_FORMAT", value_parser = parse_format)]
+    pub default_format: Option<OutputFormat>,
+
+    /// Number of generations allowed to run at the same time. Lower it to give
+    /// each generation more threads, raise it to serve more requests at once
+    #[arg(long, env = "FJG_MAX_CONCURRENT")]
+    pub max_concurrent: Option<usize>,
+
+    /// Number of requests allowed to wait for a generation slot
+    #[arg(long, env = "FJG_MAX_QUEUED")]
+    pub max_queued: Option<usize>,
+
+    /// Seconds a queued request waits for a slot before getting a 429
+    #[arg(long, env = "FJG_QUEUE_TIMEOUT")]
+    pub queue_timeout: Option<u64>,
+
+    /// Seconds sent in the `Retry-After` header of a 429
+    #[arg(long, env = "FJG_RETRY_AFTER")]
+    pub retry_after: Option<u64>,
+
+    /// Show the terminal dashboard of active jobs. Ignored when stdout is not a TTY
+    #[arg(long, env = "FJG_DASHBOARD")]
+    pub dashboard: Option<bool>,
+
+    /// Log filter, e.g. `debug` or `fast_json_gen=debug,actix_server=warn`
+    #[arg(long, env = "FJG_LOG")]
+    pub log_level: Option<String>,
+
+    /// Log output format: `text` or `json`
+    #[arg(long, env = "FJG_LOG_FORMAT", value_parser = parse_log_format)]
+    pub log_format: Option<LogFormat>,
+
+    /// Seconds in-flight generations get to finish on shutdown before being truncated
+    #[arg(long, env = "FJG_SHUTDOWN_GRACE")]
+    pub shutdown_grace: Option<u64>,
+
+    /// Largest body frame handed to the connection, e.g. `16kb`; unset sends whole chunks
+    #[arg(long, env = "FJG_FRAME_SIZE", value_parser = parse_byte_size)]
+    pub frame_size: Option<u64>,
+
+    /// PEM certificate chain; serves HTTPS and HTTP/2 on TCP binds (needs the `tls` feature)
+    #[arg(long, env = "FJG_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key matching `--tls-cert`
+    #[arg(long, env = "FJG_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Serve HTTPS with a generated self-signed certificate (needs the `tls` feature)
+    #[arg(long, env = "FJG_TLS_SELF_SIGNED")]
+    pub tls_self_signed: Option<bool>,
+
+    /// Address for the gRPC `Generator` service, e.g. `127.0.0.1:50051` (needs the `grpc` feature)
+    #[arg(long, env = "FJG_GRPC_BIND")]
+    pub grpc_bind: Option<SocketAddr>,
+
+    /// Address for the raw socket producer, repeatable. `host:port` or `unix:/path`
+    #[arg(long = "raw-bind", env = "FJG_RAW_BIND", value_delimiter = ',')]
+    pub raw_bind: Vec<String>,
+
+    /// Parameters for every raw socket client, e.g. `size=1gb&format=csv`; unset, clients send a handshake line
+    #[arg(long, env = "FJG_RAW_DEFAULTS")]
+    pub raw_defaults: Option<String>,
+}
+
+/// Shape of the TOML config file. All keys are optional.
+#[cfg(feature = "cli")]
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub bind: Option<Vec<String>>,
+    pub workers: Option<usize>,
+    pub generation_threads: Option<usize>,
+    pub chunk_size: Option<String>,
+    pub stream_chunk_size: Option<String>,
+    pub pool_size: Option<usize>,
+    pub pools: Option<Vec<String>>,
+    pub pool_dir: Option<PathBuf>,
+    pub distributions: Option<Vec<String>>,
+    pub revenue_type: Option<String>,
+    pub correlation: Option<f64>,
+    pub nulls: Option<Vec<String>>,
+    pub missing: Option<Vec<String>>,
+    pub empty: Option<Vec<String>>,
+    pub locales: Option<Vec<String>>,
+    pub channel_depth: Option<usize>,
+    pub max_request_size: Option<String>,
+    pub default_format: Option<String>,
+    pub max_concurrent: Option<usize>,
+    pub max_queued: Option<usize>,
+    pub queue_timeout: Option<u64>,
+    pub retry_after: Option<u64>,
+    pub dashboard: Option<bool>,
+    pub log_level: Option<String>,
+    pub log_format: Option<String>,
+    pub shutdown_grace: Option<u64>,
+    pub frame_size: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_self_signed: Option<bool>,
+    pub grpc_bind: Option<SocketAddr>,
+    pub raw_bind: Option<Vec<String>>,
+    pub raw_defaults: Option<String>,
+}
+
+#[cfg(feature = "cli")]
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl BindAddress {
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(BindAddress::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            bail!("Unix sockets are not supported on this platform: {}", path);
+        }
+
+        s.parse()
+            .map(BindAddress::Tcp)
+            .with_context(|| format!("Invalid bind address '{}'", s))
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(addr) => write!(f, "{}", addr),
+            #[cfg(unix)]
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Effective server configuration after merging all sources.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind: Vec<BindAddress>,
+    pub workers: usize,
+    pub generation_threads: usize,
+    pub chunk_size: u64,
+    pub stream_chunk_size: u64,
+    pub pool_size: usize,
+    pub pools: Vec<PoolSource>,
+    pub pool_dir: Option<PathBuf>,
+    pub distributions: Vec<(PoolField, Distribution)>,
+    /// Distributions and types of revenue and employees.
+    pub numbers: NumericOptions,
+    /// How often fields are null, missing or empty.
+    pub gaps: GapRates,
+    pub locales: Vec<Locale>,
+    pub channel_depth: usize,
+    pub max_request_size: u64,
+    pub default_format: OutputFormat,
+    pub max_concurrent: usize,
+    pub max_queued: usize,
+    pub queue_timeout: u64,
+    pub retry_after: u64,
+    pub dashboard: bool,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub shutdown_grace: u64,
+    pub frame_size: Option<u64>,
+    pub tls: Option<TlsSource>,
+    pub grpc_bind: Option<SocketAddr>,
+    pub raw_bind: Vec<BindAddress>,
+    pub raw_defaults: Option<String>,
+}
+
+/// Where the TLS certificate comes from, if TLS is enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TlsSource {
+    Files { cert: PathBuf, key: PathBuf },
+    SelfSigned,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let num_cpus = num_cpus::get();
+        Self {
+            bind: vec![BindAddress::parse(DEFAULT_BIND).unwrap()],
+            workers: num_cpus,
+            generation_threads: num_cpus,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            stream_chunk_size: OPTIMAL_CHUNK_SIZE,
+            pool_size: POOL_SIZE,
+            pools: Vec::new(),
+            pool_dir: None,
+            distributions: Vec::new(),
+            numbers: NumericOptions::default(),
+            gaps: GapRates::default(),
+            locales: vec![Locale::En],
+            channel_depth: DEFAULT_CHANNEL_DEPTH,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            default_format: OutputFormat::JSON,
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+            max_queued: DEFAULT_MAX_QUEUED,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT_SECS,
+            retry_after: DEFAULT_RETRY_AFTER_SECS,
+            dashboard: true,
+            log_level: DEFAULT_LOG_FILTER.to_owned(),
+            log_format: LogFormat::Text,
+            shutdown_grace: DEFAULT_SHUTDOWN_GRACE_SECS,
+            frame_size: None,
+            tls: None,
+            grpc_bind: None,
+            raw_bind: Vec::new(),
+            raw_defaults: None,
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ServerConfig {
+    /// Parses the process arguments and environment, then layers them over
+    /// the config file (if any) and the defaults.
+    pub fn load() -> Result<Self> {
+        Self::from_cli(Cli::parse())
+    }
+
+    pub fn from_cli(cli: Cli) -> Result<Self> {
+        let file = match &cli.config {
+            Some(path) => FileConfig::load(path)?,
+            None => FileConfig::default(),
+        };
+
+        Self::merge(cli, file)
+    }
+
+    pub fn merge(cli: Cli, file: FileConfig) -> Result<Self> {
+        let defaults = Self::default();
+
+        let bind = if !cli.bind.is_empty() {
+            cli.bind
+        } else {
+            file.bind.unwrap_or_default()
+        };
+        let bind = if bind.is_empty() {
+            defaults.bind
+        } else {
+            bind.iter()
+                .map(|s| BindAddress::parse(s))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let raw_bind = if !cli.raw_bind.is_empty() {
+            cli.raw_bind
+        } else {
+            file.raw_bind.unwrap_or_default()
+        };
+        let raw_bind = raw_bind
+            .iter()
+            .map(|s| BindAddress::parse(s))
+            .collect::<Result<Vec<_>>>()?;
+
+        let pools = if !cli.pools.is_empty() {
+            cli.pools
+        } else {
+            file.pools.unwrap_or_default()
+        };
+        let pools = pools
+            .iter()
+            .map(|s| PoolSource::parse(s))
+            .collect::<Result<Vec<_>>>()?;
+
+        let distributions = if !cli.distributions.is_empty() {
+            cli.distributions
+        } else {
+            file.distributions.unwrap_or_default()
+        };
+        let mut numbers = defaults.numbers;
+        let mut pool_distributions = Vec::new();
+        for spec in &distributions {
+            let (field, distribution) = spec
+                .split_once('=')
+                .with_context(|| format!("Expected field=distribution, got '{}'", spec))?;
+            match field.trim().parse::<NumericField>() {
+                Ok(field) => {
+                    let distribution = distribution
+                        .trim()
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("{}: {}", field, e))?;
+                    numbers.set_distribution(field, distribution);
+                }
+                Err(_) => pool_distributions.push(Distribution::parse_for_field(spec)?),
+            }
+        }
+        let file_revenue_type = file
+            .revenue_type
+            .map(|s| parse_revenue_type(&s).map_err(|e| anyhow::anyhow!("revenue_type: {}", e)))
+            .transpose()?;
+        if let Some(revenue_type) = cli.revenue_type.or(file_revenue_type) {
+            numbers.revenue_type = revenue_type;
+        }
+        if let Some(correlation) = cli.correlation.or(file.correlation) {
+            numbers.correlation = correlation;
+        }
+
+        let gap_specs = [
+            (Gap::Null, cli.nulls, file.nulls),
+            (Gap::Missing, cli.missing, file.missing),
+            (Gap::Empty, cli.empty, file.empty),
+        ];
+        let mut gaps = GapRates::default();
+        for (gap, cli_specs, file_specs) in gap_specs {
+            let specs = if !cli_specs.is_empty() {
+                cli_specs
+            } else {
+                file_specs.unwrap_or_default()
+            };
+            for spec in &specs {
+                gaps.set_spec(gap, spec)
+                    .map_err(|e| anyhow::anyhow!("{}: {}", gap, e))?;
+            }
+        }
+
+        let locales = if !cli.locales.is_empty() {
+            cli.locales
+        } else {
+            file.locales
+                .unwrap_or_default()
+                .iter()
+                .map(|s| parse_locale(s).map_err(|e| anyhow::anyhow!("locales: {}", e)))
+                .collect::<Result<Vec<_>>>()?
+        };
+        let locales = if locales.is_empty() {
+            defaults.locales
+        } else {
+            locales
+        };
+
+        let file_size = |value: Option<String>, key: &str| -> Result<Option<u64>> {
+            value
+                .map(|s| parse_byte_size(&s).map_err(|e| anyhow::anyhow!("{}: {}", key, e)))
+                .transpose()
+        };
+
+        let file_format = file
+            .default_format
+            .map(|s| parse_format(&s).map_err(|e| anyhow::anyhow!("default_format: {}", e)))
+            .transpose()?;
+
+        let file_log_format = file
+            .log_format
+            .map(|s| parse_log_format(&s).map_err(|e| anyhow::anyhow!("log_format: {}", e)))
+            .transpose()?;
+
+        let tls_cert = cli.tls_cert.or(file.tls_cert);
+        let tls_key = cli.tls_key.or(file.tls_key);
+        let tls = match (tls_cert, tls_key) {
+            (Some(cert), Some(key)) => Some(TlsSource::Files { cert, key }),
+            (None, None) => cli
+                .tls_self_signed
+                .or(file.tls_self_signed)
+                .unwrap_or(false)
+                .then_some(TlsSource::SelfSigned),
+            _ => bail!("tls_cert and tls_key must be set together"),
+        };
+
+        let config = Self {
+            bind,
+            workers: cli.workers.or(file.workers).unwrap_or(defaults.workers),
+            generation_threads: cli
+                .generation_threads
+                .or(file.generation_threads)
+                .unwrap_or(defaults.generation_threads),
+            chunk_size: cli
+                .chunk_size
+                .or(file_size(file.chunk_size, "chunk_size")?)
+                .unwrap_or(defaults.chunk_size),
+            stream_chunk_size: cli
+                .stream_chunk_size
+                .or(file_size(file.stream_chunk_size, "stream_chunk_size")?)
+                .unwrap_or(defaults.stream_chunk_size),
+            pool_size: cli
+                .pool_size
+                .or(file.pool_size)
+                .unwrap_or(defaults.pool_size),
+            pools,
+            pool_dir: cli.pool_dir.or(file.pool_dir),
+            distributions: pool_distributions,
+            numbers,
+            gaps,
+            locales,
+            channel_depth: cli
+                .channel_depth
+                .or(file.channel_depth)
+                .unwrap_or(defaults.channel_depth),
+            max_request_size: cli
+                .max_request_size
+                .or(file_size(file.max_request_size, "max_request_size")?)
+                .unwrap_or(defaults.max_request_size),
+            default_format: cli
+                .default_format
+                .or(file_format)
+                .unwrap_or(defaults.default_format),
+            max_concurrent: cli
+                .max_concurrent
+                .or(file.max_concurrent)
+                .unwrap_or(defaults.max_concurrent),
+            max_queued: cli
+                .max_queued
+                .or(file.max_queued)
+                .unwrap_or(defaults.max_queued),
+            queue_timeout: cli
+                .queue_timeout
+                .or(file.queue_timeout)
+                .unwrap_or(defaults.queue_timeout),
+            retry_after: cli
+                .retry_after
+                .or(file.retry_after)
+                .unwrap_or(defaults.retry_after),
+            dashboard: cli
+                .dashboard
+                .or(file.dashboard)
+                .unwrap_or(defaults.dashboard),
+            log_level: cli
+                .log_level
+                .or(file.log_level)
+                .unwrap_or(defaults.log_level),
+            log_format: cli
+                .log_format
+                .or(file_log_format)
+                .unwrap_or(defaults.log_format),
+            shutdown_grace: cli
+                .shutdown_grace
+                .or(file.shutdown_grace)
+                .unwrap_or(defaults.shutdown_grace),
+            frame_size: cli.frame_size.or(file_size(file.frame_size, "frame_size")?),
+            tls,
+            grpc_bind: cli.grpc_bind.or(file.grpc_bind),
+            raw_bind,
+            raw_defaults: cli.raw_defaults.or(file.raw_defaults),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl ServerConfig {
+    /// The locale of requests that do not ask for one.
+    pub fn default_locale(&self) -> Locale {
+        self.locales.first().copied().unwrap_or(Locale::En)
+    }
+
+    /// Checks settings that are each valid on their own but not usable, such
+    /// as zero sizes or a feature this build does not include.
+    pub fn validate(&self) -> Result<()> {
+        if self.workers == 0 {
+            bail!("workers must be at least 1");
+        }
+        if self.generation_threads == 0 {
+            bail!("generation_threads must be at least 1");
+        }
+        if self.chunk_size == 0 || self.stream_chunk_size == 0 {
+            bail!("chunk sizes must be greater than zero");
+        }
+        if self.pool_size < 100 {
+            bail!("pool_size must be at least 100");
+        }
+        if let Some(dir) = &self.pool_dir {
+            if !dir.is_dir() {
+                bail!("pool_dir {} is not a directory", dir.display());
+            }
+        }
+        if !(-1.0..=1.0).contains(&self.numbers.correlation) {
+            bail!("correlation must be between -1 and 1");
+        }
+        if self.channel_depth == 0 {
+            bail!("channel_depth must be at least 1");
+        }
+        if self.max_concurrent == 0 {
+            bail!("max_concurrent must be at least 1");
+        }
+        if self.frame_size == Some(0) {
+            bail!("frame_size must be greater than zero");
+        }
+        if self.tls.is_some() && !cfg!(feature = "tls") {
+            bail!("TLS was requested but this build does not include the `tls` feature");
+        }
+        if self.grpc_bind.is_some() && !cfg!(feature = "grpc") {
+            bail!("gRPC was requested but this build does not include the `grpc` feature");
+        }
+        if let Some(defaults) = &self.raw_defaults {
+            GenerationRequest::from_query(&parse_params(defaults)?, self)
+                .context("raw_defaults")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Effective configuration:")?;
+        writeln!(f, "------------------------------------")?;
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        for addr in &self.bind {
+            match addr {
+                BindAddress::Tcp(_) => writeln!(f, "Listening on:       {}://{}", scheme, addr)?,
+                #[cfg(unix)]
+                BindAddress::Unix(_) => writeln!(f, "Listening on:       {}", addr)?,
+            }
+        }
+        writeln!(f, "HTTP workers:       {}", self.workers)?;
+        writeln!(f, "Generation threads: {}", self.generation_threads)?;
+        writeln!(f, "Chunk size:         {} bytes", self.chunk_size)?;
+        writeln!(f, "Stream chunk size:  {} bytes", self.stream_chunk_size)?;
+        writeln!(f, "Pool size:          {}", self.pool_size)?;
+        for source in &self.pools {
+            writeln!(f, "Pool:               {}", source)?;
+        }
+        match &self.pool_dir {
+            Some(dir) => writeln!(f, "Pool directory:     {}", dir.display())?,
+            None => writeln!(f, "Pool directory:     off")?,
+        }
+        for (field, distribution) in &self.distributions {
+            writeln!(f, "Distribution:       {}={}", field, distribution)?;
+        }
+        writeln!(
+            f,
+            "Revenue:            {} as {}",
+            self.numbers.revenue, self.numbers.revenue_type
+        )?;
+        writeln!(f, "Employees:          {}", self.numbers.employees)?;
+        writeln!(f, "Correlation:        {}", self.numbers.correlation)?;
+        writeln!(f, "Gaps:               {}", self.gaps)?;
+        let locales: Vec<_> = self.locales.iter().map(Locale::as_str).collect();
+        writeln!(f, "Locales:            {}", locales.join(", "))?;
+        writeln!(f, "Channel depth:      {}", self.channel_depth)?;
+        writeln!(f, "Max request size:   {} bytes", self.max_request_size)?;
+        writeln!(f, "Default format:     {}", self.default_format.to_string())?;
+        writeln!(f, "Max concurrent:     {}", self.max_concurrent)?;
+        writeln!(f, "Max queued:         {}", self.max_queued)?;
+        writeln!(f, "Queue timeout:      {}s", self.queue_timeout)?;
+        writeln!(f, "Retry-After:        {}s", self.retry_after)?;
+        writeln!(f, "Dashboard:          {}", self.dashboard)?;
+        writeln!(f, "Log level:          {}", self.log_level)?;
+        writeln!(f, "Log format:         {}", self.log_format)?;
+        writeln!(f, "Shutdown grace:     {}s", self.shutdown_grace)?;
+        match self.frame_size {
+            Some(size) => writeln!(f, "Frame size:         {} bytes", size)?,
+            None => writeln!(f, "Frame size:         whole chunks")?,
+        }
+        match &self.tls {
+            Some(TlsSource::Files { cert, .. }) => {
+                writeln!(f, "TLS:                {}", cert.display())?
+            }
+            Some(TlsSource::SelfSigned) => writeln!(f, "TLS:                self-signed")?,
+            None => writeln!(f, "TLS:                off")?,
+        }
+        match self.grpc_bind {
+            Some(addr) => writeln!(f, "gRPC:               {}", addr)?,
+            None => writeln!(f, "gRPC:               off")?,
+        }
+        for addr in &self.raw_bind {
+            writeln!(f, "Raw producer:       {}", addr)?;
+        }
+        match (&self.raw_defaults, self.raw_bind.is_empty()) {
+            (_, true) => write!(f, "Raw producer:       off"),
+            (Some(defaults), false) => write!(f, "Raw defaults:       {}", defaults),
+            (None, false) => write!(f, "Raw defaults:       none, clients send a handshake"),
+        }
+    }
+}
+
+/// Accepts either a plain byte count or a size with a unit, as in `?size=`.
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if let Ok(bytes) = s.parse::<u64>() {
+        return Ok(bytes);
+    }
+    parse_size(s).map(|info| info.total_size)
+}
+
+#[cfg(feature = "cli")]
+fn parse_format(s: &str) -> Result<OutputFormat, String> {
+    s.parse()
+}
+
+#[cfg(feature = "cli")]
+fn parse_locale(s: &str) -> Result<Locale, String> {
+    s.parse()
+}
+
+#[cfg(feature = "cli")]
+fn parse_revenue_type(s: &str) -> Result<RevenueType, String> {
+    s.parse()
+}
+
+#[cfg(feature = "cli")]
+fn parse_log_format(s: &str) -> Result<LogFormat, String> {
+    s.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn bind_addresses_take_ipv4_ipv6_and_unix_paths() {
+        assert_eq!(
+            BindAddress::parse("0.0.0.0:8080").unwrap(),
+            BindAddress::Tcp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 8080)))
+        );
+        assert_eq!(
+            BindAddress::parse("[::1]:9000").unwrap(),
+            BindAddress::Tcp(SocketAddr::from((Ipv6Addr::LOCALHOST, 9000)))
+        );
+        #[cfg(unix)]
+        {
+            let unix = BindAddress::parse("unix:/tmp/fjg.sock").unwrap();
+            assert_eq!(unix, BindAddress::Unix(PathBuf::from("/tmp/fjg.sock")));
+            assert_eq!(unix.to_string(), "unix:/tmp/fjg.sock");
+        }
+
+        for address in ["[::1]:9000", "127.0.0.1:8080"] {
+            assert_eq!(BindAddress::parse(address).unwrap().to_string(), address);
+        }
+        for invalid in ["8080", "localhost:8080", "::1:9000", "127.0.0.1"] {
+            assert!(BindAddress::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_unusable_settings() {
+        let rejected = |config: ServerConfig, message: &str| {
+            let error = format!("{:#}", config.validate().unwrap_err());
+            assert!(error.contains(message), "{}", error);
+        };
+        let defaults = ServerConfig::default;
+
+        defaults().validate().unwrap();
+        rejected(
+            ServerConfig {
+                workers: 0,
+                ..defaults()
+            },
+            "workers",
+        );
+        rejected(
+            ServerConfig {
+                generation_threads: 0,
+                ..defaults()
+            },
+            "generation_threads",
+        );
+        rejected(
+            ServerConfig {
+                stream_chunk_size: 0,
+                ..defaults()
+            },
+            "chunk sizes",
+        );
+        rejected(
+            ServerConfig {
+                pool_size: 99,
+                ..defaults()
+            },
+            "pool_size",
+        );
+        rejected(
+            ServerConfig {
+                pool_dir: Some(PathBuf::from("/nonexistent/fast-json-gen/pools")),
+                ..defaults()
+            },
+            "not a directory",
+        );
+        rejected(
+            ServerConfig {
+                channel_depth: 0,
+                ..defaults()
+            },
+            "channel_depth",
+        );
+        rejected(
+            ServerConfig {
+                max_concurrent: 0,
+                ..defaults()
+            },
+            "max_concurrent",
+        );
+        rejected(
+            ServerConfig {
+                frame_size: Some(0),
+                ..defaults()
+            },
+            "frame_size",
+        );
+        rejected(
+            ServerConfig {
+                raw_defaults: Some("size=lots".to_owned()),
+                ..defaults()
+            },
+            "raw_defaults",
+        );
+
+        let mut config = defaults();
+        config.numbers.correlation = 1.5;
+        rejected(config, "correlation");
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn cli_beats_environment_beats_file_beats_default() {
+        let path =
+            std::env::temp_dir().join(format!("fast-json-gen-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "bind = [\"127.0.0.1:9001\"]\nworkers = 2\npool_size = 300\nchannel_depth = 4\n\
+             max_concurrent = 2\nmax_queued = 3\nqueue_timeout = 4\n",
+        )
+        .unwrap();
+
+        // The only test that sets `FJG_*` variables, so nothing else sees them.
+        std::env::set_var("FJG_WORKERS", "7");
+        std::env::set_var("FJG_POOL_SIZE", "500");
+        std::env::set_var("FJG_MAX_CONCURRENT", "7");
+        std::env::set_var("FJG_MAX_QUEUED", "5");
+        let cli = Cli::try_parse_from([
+            "fast-json-gen",
+            "--config",
+            path.to_str().unwrap(),
+            "--workers",
+            "8",
+            "--max-concurrent",
+            "8",
+        ]);
+        std::env::remove_var("FJG_WORKERS");
+        std::env::remove_var("FJG_POOL_SIZE");
+        std::env::remove_var("FJG_MAX_CONCURRENT");
+        std::env::remove_var("FJG_MAX_QUEUED");
+
+        let config = ServerConfig::from_cli(cli.unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.workers, 8);
+        assert_eq!(config.pool_size, 500);
+        assert_eq!(config.channel_depth, 4);
+        assert_eq!(config.max_request_size, DEFAULT_MAX_REQUEST_SIZE);
+        assert_eq!(config.max_concurrent, 8);
+        assert_eq!(config.max_queued, 5);
+        assert_eq!(config.queue_timeout, 4);
+        assert_eq!(config.retry_after, DEFAULT_RETRY_AFTER_SECS);
+        assert_eq!(config.bind, [BindAddress::parse("127.0.0.1:9001").unwrap()]);
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn unknown_file_keys_are_refused() {
+        assert!(toml::from_str::<FileConfig>("workrs = 2").is_err());
+        assert!(toml::from_str::<FileConfig>("workers = 2").is_ok());
+        assert!(toml::from_str::<FileConfig>("max_concurent = 2").is_err());
+        assert!(toml::from_str::<FileConfig>("max_concurrent = 2").is_ok());
+    }
+}
@@ -0,0 +1,1095 @@
+use crate::generator::CSV_HEADER;
+use crate::logs;
+use crate::numeric::{NumericDistribution, RevenueBuffer, RevenueType};
+use crate::processing::{put_csv_field, put_json_string, DataPools, OutputFormat};
+use crate::temporal::{TimeBuffer, TimeFormat, TimeKind, TimeOptions, TimeSeries};
+use bytes::{BufMut, BytesMut};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// The kind of record a stream is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Dataset {
+    /// Companies, the records every other option shapes.
+    #[default]
+    Business,
+    /// People signing up, with a contact address.
+    Users,
+    /// Shop orders with their line items.
+    Orders,
+    /// Clickstream events, grouped into sessions.
+    Events,
+    /// Readings from a fleet of IoT sensors.
+    Iot,
+    /// Card and account transactions.
+    Transactions,
+    /// Apache or Nginx access log lines in the `combined` format.
+    AccessLog,
+    /// RFC 5424 syslog lines from a handful of hosts.
+    Syslog,
+    /// JSON structured application log lines, with levels and trace ids.
+    AppLog,
+    /// Container log lines as Kubernetes nodes store them, wrapping
+    /// application and access log lines.
+    ContainerLog,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 10] = [
+        Dataset::Business,
+        Dataset::Users,
+        Dataset::Orders,
+        Dataset::Events,
+        Dataset::Iot,
+        Dataset::Transactions,
+        Dataset::AccessLog,
+        Dataset::Syslog,
+        Dataset::AppLog,
+        Dataset::ContainerLog,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dataset::Business => "business",
+            Dataset::Users => "users",
+            Dataset::Orders => "orders",
+            Dataset::Events => "events",
+            Dataset::Iot => "iot",
+            Dataset::Transactions => "transactions",
+            Dataset::AccessLog => "access_log",
+            Dataset::Syslog => "syslog",
+            Dataset::AppLog => "app_log",
+            Dataset::ContainerLog => "container_log",
+        }
+    }
+
+    /// True for the log datasets. Their records are lines of text, the same
+    /// whatever the format, with timestamps that always rise.
+    pub fn is_log(&self) -> bool {
+        matches!(
+            self,
+            Dataset::AccessLog | Dataset::Syslog | Dataset::AppLog | Dataset::ContainerLog
+        )
+    }
+
+    /// The CSV header row. Business records add nested and time columns to
+    /// theirs as their options ask, and log lines have none.
+    pub fn csv_header(&self) -> &'static [u8] {
+        match self {
+            Dataset::Business => CSV_HEADER,
+            Dataset::Users => b"id,name,email,phone,address.street,address.city,address.state,address.postcode,address.country,created_at\n",
+            Dataset::Orders => b"id,user_id,status,currency,total,items,ordered_at\n",
+            Dataset::Events => {
+                b"id,session_id,user_id,type,page,referrer,device,browser,country,timestamp\n"
+            }
+            Dataset::Iot => b"device_id,sensor,value,unit,battery,status,recorded_at\n",
+            Dataset::Transactions => {
+                b"id,account_id,type,amount,currency,merchant,category,status,timestamp\n"
+            }
+            Dataset::AccessLog | Dataset::Syslog | Dataset::AppLog | Dataset::ContainerLog => b"",
+        }
+    }
+
+    /// Draws record `index` of a built-in dataset, `key` being its key across
+    /// the whole output. Business records are drawn as locations instead.
+    pub(crate) fn draw<'a>(
+        &self,
+        rng: &mut ChaCha8Rng,
+        index: u64,
+        key: u64,
+        pools: &'a DataPools,
+        times: &TimeOptions,
+    ) -> DatasetRecord<'a> {
+        // Logs are a series even when no series is asked for.
+        let series = times.series.or(self.is_log().then_some(LOG_SERIES));
+        let at = times.instant(series, index, rng);
+        match self {
+            Dataset::Business => unreachable!("business records are drawn as locations"),
+            Dataset::Users => DatasetRecord::User(User::draw(rng, key, pools, at)),
+            Dataset::Orders => DatasetRecord::Order(Order::draw(rng, key, at)),
+            Dataset::Events => DatasetRecord::Event(Event::draw(rng, index, pools, at)),
+            Dataset::Iot => DatasetRecord::Reading(Reading::draw(rng, at)),
+            Dataset::Transactions => DatasetRecord::Transaction(Transaction::draw(rng, at)),
+            Dataset::AccessLog | Dataset::Syslog | Dataset::AppLog | Dataset::ContainerLog => {
+                DatasetRecord::Line(logs::draw(*self, rng, index, at))
+            }
+        }
+    }
+}
+
+impl FromStr for Dataset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        Dataset::ALL
+            .into_iter()
+            .find(|dataset| dataset.as_str() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Dataset::ALL.iter().map(Dataset::as_str).collect();
+                format!("Unknown dataset '{}', expected {}", s, names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Log lines per second of simulated time, unless a series says otherwise.
+const LOG_SERIES: TimeSeries = TimeSeries {
+    rate: 20.0,
+    jitter: 1.0,
+};
+
+/// Picks an entry of `table` in proportion to its weight.
+pub(crate) fn weighted<T: Copy>(rng: &mut ChaCha8Rng, table: &[(T, u32)]) -> T {
+    let total: u32 = table.iter().map(|(_, weight)| weight).sum();
+    let mut roll = rng.gen_range(0..total);
+    for (entry, weight) in table {
+        if roll < *weight {
+            return *entry;
+        }
+        roll -= weight;
+    }
+    unreachable!("the roll is below the total weight")
+}
+
+/// Short text built on the stack, such as an id or a phone number.
+#[derive(Clone, Copy)]
+struct Inline {
+    bytes: [u8; 36],
+    len: usize,
+}
+
+impl Inline {
+    fn new() -> Self {
+        Self {
+            bytes: [0; 36],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.bytes[self.len..self.len + text.len()].copy_from_slice(text.as_bytes());
+        self.len += text.len();
+    }
+
+    /// Appends `value` in decimal, padded with zeros to `width` digits.
+    fn push_padded(&mut self, value: u64, width: usize) {
+        let mut digits = itoa::Buffer::new();
+        let digits = digits.format(value);
+        for _ in digits.len()..width {
+            self.push("0");
+        }
+        self.push(digits);
+    }
+
+    /// A random version 4 UUID.
+    fn uuid(rng: &mut ChaCha8Rng) -> Self {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let mut random: [u8; 16] = rng.gen();
+        random[6] = random[6] & 0x0f | 0x40;
+        random[8] = random[8] & 0x3f | 0x80;
+        let mut uuid = Self::new();
+        for (i, byte) in random.into_iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                uuid.push("-");
+            }
+            uuid.bytes[uuid.len] = HEX[(byte >> 4) as usize];
+            uuid.bytes[uuid.len + 1] = HEX[(byte & 0xf) as usize];
+            uuid.len += 2;
+        }
+        uuid
+    }
+
+    fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len]).expect("only ASCII is pushed")
+    }
+}
+
+const STREETS: [&str; 20] = [
+    "Main",
+    "Oak",
+    "Maple",
+    "Cedar",
+    "Elm",
+    "Pine",
+    "Washington",
+    "Lake",
+    "Hill",
+    "Park",
+    "River",
+    "Church",
+    "Mill",
+    "Spring",
+    "Highland",
+    "Sunset",
+    "Forest",
+    "Meadow",
+    "Bridge",
+    "Station",
+];
+const STREET_TYPES: [&str; 8] = ["St", "Ave", "Rd", "Blvd", "Ln", "Way", "Dr", "Ct"];
+const CALLING_CODES: [(&str, u32); 10] = [
+    ("+1", 40),
+    ("+44", 10),
+    ("+91", 8),
+    ("+49", 8),
+    ("+33", 7),
+    ("+81", 6),
+    ("+86", 6),
+    ("+55", 5),
+    ("+61", 5),
+    ("+34", 5),
+];
+
+pub(crate) struct User<'a> {
+    id: u64,
+    name: &'a str,
+    email: String,
+    phone: Inline,
+    street: Inline,
+    city: &'a str,
+    state: &'a str,
+    postcode: Inline,
+    country: &'a str,
+    created_at: i64,
+}
+
+impl<'a> User<'a> {
+    fn draw(rng: &mut ChaCha8Rng, key: u64, pools: &'a DataPools, at: i64) -> Self {
+        let [name, email, city, state, country]: [u32; 5] = rng.gen();
+        let id = key + 1;
+        // The id keeps every email distinct: `ann@example.com` becomes
+        // `ann.42@example.com`.
+        let email = pools.emails.pick(email);
+        let (local, domain) = email.split_at(email.rfind('@').unwrap_or(email.len()));
+        let mut unique = itoa::Buffer::new();
+        let email = [local, ".", unique.format(id), domain].concat();
+
+        let mut phone = Inline::new();
+        phone.push(weighted(rng, &CALLING_CODES));
+        for (width, max) in [(3, 1000), (3, 1000), (4, 10_000)] {
+            phone.push(" ");
+            phone.push_padded(rng.gen_range(0..max), width);
+        }
+        let mut street = Inline::new();
+        let mut number = itoa::Buffer::new();
+        street.push(number.format(rng.gen_range(1..=9999)));
+        street.push(" ");
+        street.push(STREETS[rng.gen_range(0..STREETS.len())]);
+        street.push(" ");
+        street.push(STREET_TYPES[rng.gen_range(0..STREET_TYPES.len())]);
+        let mut postcode = Inline::new();
+        postcode.push_padded(rng.gen_range(1000..100_000), 5);
+
+        Self {
+            id,
+            name: pools.contacts.pick(name),
+            email,
+            phone,
+            street,
+            city: pools.cities.pick(city),
+            state: pools.states.pick(state),
+            postcode,
+            country: pools.countries.pick(country),
+            created_at: at,
+        }
+    }
+
+    fn write(&self, writer: &mut FieldWriter, buffers: &mut Buffers) {
+        writer.number("id", buffers.integer.format(self.id));
+        writer.text("name", self.name);
+        writer.text("email", &self.email);
+        writer.text("phone", self.phone.as_str());
+        writer.open("address");
+        writer.text("street", self.street.as_str());
+        writer.text("city", self.city);
+        writer.text("state", self.state);
+        writer.text("postcode", self.postcode.as_str());
+        writer.text("country", self.country);
+        writer.close();
+        writer.time("created_at", self.created_at, TimeKind::DateTime, buffers);
+    }
+}
+
+struct Product {
+    sku: &'static str,
+    name: &'static str,
+    category: &'static str,
+    cents: u64,
+}
+
+const fn product(
+    sku: &'static str,
+    name: &'static str,
+    category: &'static str,
+    cents: u64,
+) -> Product {
+    Product {
+        sku,
+        name,
+        category,
+        cents,
+    }
+}
+
+const PRODUCTS: [Product; 24] = [
+    product("EL-1001", "Wireless Earbuds", "Electronics", 7999),
+    product("EL-1002", "USB-C Charger 65W", "Electronics", 3499),
+    product("EL-1003", "Bluetooth Speaker", "Electronics", 5999),
+    product("EL-1004", "Mechanical Keyboard", "Electronics", 12999),
+    product("EL-1005", "27\" Monitor", "Electronics", 27999),
+    product("HM-2001", "Ceramic Mug", "Home", 1499),
+    product("HM-2002", "Cotton Bath Towel", "Home", 2499),
+    product("HM-2003", "LED Desk Lamp", "Home", 3999),
+    product("HM-2004", "Scented Candle", "Home", 1899),
+    product("HM-2005", "Cast Iron Skillet", "Home", 4499),
+    product("AP-3001", "Crew Neck T-Shirt", "Apparel", 1999),
+    product("AP-3002", "Slim Fit Jeans", "Apparel", 5999),
+    product("AP-3003", "Running Shoes", "Apparel", 8999),
+    product("AP-3004", "Wool Beanie", "Apparel", 2299),
+    product("BK-4001", "Paperback Novel", "Books", 1299),
+    product("BK-4002", "Cookbook", "Books", 2999),
+    product("BK-4003", "Children's Picture Book", "Books", 999),
+    product("SP-5001", "Yoga Mat", "Sports", 3499),
+    product("SP-5002", "Water Bottle", "Sports", 1999),
+    product("SP-5003", "Adjustable Dumbbells", "Sports", 19999),
+    product("BT-6001", "Moisturizing Cream", "Beauty", 2499),
+    product("BT-6002", "Shampoo 500ml", "Beauty", 1199),
+    product("GR-7001", "Ground Coffee 1kg", "Grocery", 1699),
+    product("GR-7002", "Green Tea, 100 bags", "Grocery", 899),
+];
+const ORDER_STATUSES: [(&str, u32); 6] = [
+    ("delivered", 55),
+    ("shipped", 15),
+    ("processing", 10),
+    ("pending", 8),
+    ("cancelled", 7),
+    ("returned", 5),
+];
+const CURRENCIES: [(&str, u32); 6] = [
+    ("USD", 50),
+    ("EUR", 25),
+    ("GBP", 10),
+    ("CAD", 6),
+    ("AUD", 5),
+    ("CHF", 4),
+];
+/// Registered users that orders, events and transactions refer to.
+const USERS: u64 = 100_000;
+
+pub(crate) struct Order {
+    id: u64,
+    user_id: u64,
+    status: &'static str,
+    currency: &'static str,
+    items: Vec<(&'static Product, u64)>,
+    ordered_at: i64,
+}
+
+impl Order {
+    fn draw(rng: &mut ChaCha8Rng, key: u64, at: i64) -> Self {
+        // Most orders hold one or two products, a few hold many.
+        let lines = weighted(rng, &[(1, 45), (2, 25), (3, 15), (4, 8), (5, 4), (6, 3)]);
+        Self {
+            id: key + 1,
+            user_id: rng.gen_range(1..=USERS),
+            status: weighted(rng, &ORDER_STATUSES),
+            currency: weighted(rng, &CURRENCIES),
+            items: rand::seq::index::sample(rng, PRODUCTS.len(), lines)
+                .into_iter()
+                .map(|product| {
+                    let quantity = weighted(rng, &[(1, 70), (2, 20), (3, 7), (4, 3)]);
+                    (&PRODUCTS[product], quantity)
+                })
+                .collect(),
+            ordered_at: at,
+        }
+    }
+
+    fn write(&self, writer: &mut FieldWriter, buffers: &mut Buffers) {
+        let total = self
+            .items
+            .iter()
+            .map(|(product, quantity)| product.cents * quantity)
+            .sum();
+        writer.number("id", buffers.integer.format(self.id));
+        writer.number("user_id", buffers.integer.format(self.user_id));
+        writer.text("status", self.status);
+        writer.text("currency", self.currency);
+        writer.money("total", total, buffers);
+        // CSV has no arrays, so the items go in one cell as compact JSON.
+        if writer.csv {
+            let mut scratch = std::mem::take(&mut buffers.scratch);
+            scratch.clear();
+            let mut items = FieldWriter::new(&mut scratch, OutputFormat::JSON, false, b'[');
+            self.write_items(&mut items, buffers);
+            items.finish();
+            writer.text(
+                "items",
+                std::str::from_utf8(&scratch).expect("JSON is written as UTF-8"),
+            );
+            buffers.scratch = scratch;
+        } else {
+            writer.open_array("items");
+            self.write_items(writer, buffers);
+            writer.close();
+        }
+        writer.time("ordered_at", self.ordered_at, TimeKind::DateTime, buffers);
+    }
+
+    fn write_items(&self, writer: &mut FieldWriter, buffers: &mut Buffers) {
+        for (product, quantity) in &self.items {
+            writer.open_element();
+            writer.text("sku", product.sku);
+            writer.text("product", product.name);
+            writer.text("category", product.category);
+            writer.number("quantity", buffers.integer.format(*quantity));
+            writer.money("unit_price", product.cents, buffers);
+            writer.close();
+        }
+    }
+}
+
+const EVENT_TYPES: [(&str, u32); 9] = [
+    ("page_view", 55),
+    ("click", 20),
+    ("scroll", 10),
+    ("add_to_cart", 6),
+    ("begin_checkout", 3),
+    ("remove_from_cart", 2),
+    ("purchase", 2),
+    ("login", 1),
+    ("sign_up", 1),
+];
+const PAGES: [(&str, u32); 14] = [
+    ("/", 20),
+    ("/products", 12),
+    ("/products/wireless-earbuds", 6),
+    ("/products/running-shoes", 5),
+    ("/products/yoga-mat", 4),
+    ("/search?q=headphones", 5),
+    ("/search?q=gifts", 3),
+    ("/cart", 8),
+    ("/checkout", 4),
+    ("/account", 4),
+    ("/login", 5),
+    ("/blog/holiday-gift-guide", 3),
+    ("/pricing", 3),
+    ("/about", 2),
+];
+/// Where visitors come from; `None` is a direct visit.
+const REFERRERS: [(Option<&str>, u32); 9] = [
+    (None, 35),
+    (Some("https://www.google.com/"), 30),
+    (Some("https://www.facebook.com/"), 8),
+    (Some("https://www.bing.com/"), 5),
+    (Some("https://www.instagram.com/"), 5),
+    (Some("https://www.reddit.com/"), 4),
+    (Some("https://t.co/"), 4),
+    (Some("https://duckduckgo.com/"), 3),
+    (Some("https://news.ycombinator.com/"), 2),
+];
+const DEVICES: [(&str, u32); 3] = [("desktop", 50), ("mobile", 42), ("tablet", 8)];
+const BROWSERS: [(&str, u32); 6] = [
+    ("Chrome", 64),
+    ("Safari", 19),
+    ("Edge", 5),
+    ("Firefox", 4),
+    ("Samsung Internet", 3),
+    ("Opera", 2),
+];
+/// Events in a session.
+const SESSION_EVENTS: u64 = 8;
+/// Where session draws start in their stream.
+const SESSION_WORDS: u128 = 1 << 40;
+
+pub(crate) struct Event<'a> {
+    id: Inline,
+    session: Inline,
+    user_id: Option<u64>,
+    kind: &'static str,
+    page: &'static str,
+    referrer: Option<&'static str>,
+    device: &'static str,
+    browser: &'static str,
+    country: &'a str,
+    at: i64,
+}
+
+impl<'a> Event<'a> {
+    fn draw(rng: &mut ChaCha8Rng, index: u64, pools: &'a DataPools, at: i64) -> Self {
+        // Neighbouring records share a session, drawn from a stream of its
+        // own far past any record's draws, so its events agree on who is
+        // browsing and how.
+        let mut session = ChaCha8Rng::from_seed(rng.get_seed());
+        session.set_stream(index / SESSION_EVENTS);
+        session.set_word_pos(SESSION_WORDS);
+        Self {
+            session: Inline::uuid(&mut session),
+            user_id: session.gen_bool(0.6).then(|| session.gen_range(1..=USERS)),
+            referrer: weighted(&mut session, &REFERRERS),
+            device: weighted(&mut session, &DEVICES),
+            browser: weighted(&mut session, &BROWSERS),
+            country: pools.countries.pick(session.gen()),
+            id: Inline::uuid(rng),
+            kind: weighted(rng, &EVENT_TYPES),
+            page: weighted(rng, &PAGES),
+            at,
+        }
+    }
+
+    fn write(&self, writer: &mut FieldWriter, buffers: &mut Buffers) {
+        writer.text("id", self.id.as_str());
+        writer.text("session_id", self.session.as_str());
+        match self.user_id {
+            Some(user_id) => writer.number("user_id", buffers.integer.format(user_id)),
+            None => writer.null("user_id"),
+        }
+        writer.text("type", self.kind);
+        writer.text("page", self.page);
+        match self.referrer {
+            Some(referrer) => writer.text("referrer", referrer),
+            None => writer.null("referrer"),
+        }
+        writer.text("device", self.device);
+        writer.text("browser", self.browser);
+        writer.text("country", self.country);
+        writer.time("timestamp", self.at, TimeKind::Timestamp, buffers);
+    }
+}
+
+struct Sensor {
+    name: &'static str,
+    unit: &'static str,
+    min: f64,
+    max: f64,
+    places: u32,
+}
+
+const SENSORS: [Sensor; 6] = [
+    Sensor {
+        name: "temperature",
+        unit: "°C",
+        min: -10.0,
+        max: 40.0,
+        places: 1,
+    },
+    Sensor {
+        name: "humidity",
+        unit: "%",
+        min: 15.0,
+        max: 95.0,
+        places: 1,
+    },
+    Sensor {
+        name: "pressure",
+        unit: "hPa",
+        min: 970.0,
+        max: 1045.0,
+        places: 1,
+    },
+    Sensor {
+        name: "co2",
+        unit: "ppm",
+        min: 400.0,
+        max: 2000.0,
+        places: 0,
+    },
+    Sensor {
+        name: "light",
+        unit: "lx",
+        min: 0.0,
+        max: 1000.0,
+        places: 0,
+    },
+    Sensor {
+        name: "vibration",
+        unit: "mm/s",
+        min: 0.0,
+        max: 25.0,
+        places: 2,
+    },
+];
+const READING_STATUSES: [(&str, u32); 3] = [("ok", 96), ("warning", 3), ("error", 1)];
+/// Sensors in the fleet.
+const DEVICES_IN_FLEET: u64 = 1000;
+
+pub(crate) struct Reading {
+    device: Inline,
+    sensor: &'static Sensor,
+    /// `None` when the sensor reports an error.
+    value: Option<f64>,
+    battery: u64,
+    status: &'static str,
+    at: i64,
+}
+
+impl Reading {
+    fn draw(rng: &mut ChaCha8Rng, at: i64) -> Self {
+        let number = rng.gen_range(0..DEVICES_IN_FLEET);
+        let mut device = Inline::new();
+        device.push("sensor-");
+        device.push_padded(number, 4);
+        // Each device always measures the same thing around its own level,
+        // drawn from its number.
+        let mut baseline = ChaCha8Rng::seed_from_u64(number);
+        let sensor = &SENSORS[number as usize % SENSORS.len()];
+        let span = sensor.max - sensor.min;
+        let level = sensor.min + span * baseline.gen_range(0.2..0.8);
+        let status = weighted(rng, &READING_STATUSES);
+        let noise = NumericDistribution::Normal {
+            mean: 0.0,
+            std_dev: span * 0.03,
+            min: -span,
+            max: span,
+        };
+        Self {
+            device,
+            sensor,
+            value: (status != "error")
+                .then(|| (level + noise.sample(rng)).clamp(sensor.min, sensor.max)),
+            battery: baseline.gen_range(5..=100),
+            status,
+            at,
+        }
+    }
+
+    fn write(&self, writer: &mut FieldWriter, buffers: &mut Buffers) {
+        writer.text("device_id", self.device.as_str());
+        writer.text("sensor", self.sensor.name);
+        match self.value {
+            Some(value) => {
+                let places = RevenueType::Decimal {
+                    places: self.sensor.places,
+                };
+                writer.number("value", buffers.decimal.format(value, places));
+            }
+            None => writer.null("value"),
+        }
+        writer.text("unit", self.sensor.unit);
+        writer.number("battery", buffers.integer.format(self.battery));
+        writer.text("status", self.status);
+        writer.time("recorded_at", self.at, TimeKind::Timestamp, buffers);
+    }
+}
+
+const TRANSACTION_TYPES: [(&str, u32); 6] = [
+    ("purchase", 70),
+    ("transfer", 10),
+    ("deposit", 7),
+    ("withdrawal", 6),
+    ("refund", 5),
+    ("fee", 2),
+];
+const MERCHANTS: [(&str, &str); 20] = [
+    ("Whole Foods Market", "groceries"),
+    ("Trader Joe's", "groceries"),
+    ("Safeway", "groceries"),
+    ("Amazon", "shopping"),
+    ("Target", "shopping"),
+    ("Best Buy", "electronics"),
+    ("IKEA", "home"),
+    ("Starbucks", "restaurants"),
+    ("McDonald's", "restaurants"),
+    ("Chipotle", "restaurants"),
+    ("Uber", "transport"),
+    ("Lyft", "transport"),
+    ("Shell", "fuel"),
+    ("Chevron", "fuel"),
+    ("Delta Air Lines", "travel"),
+    ("Marriott", "travel"),
+    ("Netflix", "subscriptions"),
+    ("Spotify", "subscriptions"),
+    ("CVS Pharmacy", "health"),
+    ("Walgreens", "health"),
+];
+const TRANSACTION_STATUSES: [(&str, u32); 4] = [
+    ("completed", 92),
+    ("pending", 5),
+    ("failed", 2),
+    ("reversed", 1),
+];
+/// Accounts that transactions belong to.
+const ACCOUNTS: u64 = 50_000;
+
+pub(crate) struct Transaction {
+    id: Inline,
+    account_id: u64,
+    kind: &'static str,
+    cents: u64,
+    currency: &'static str,
+    merchant: Option<&'static str>,
+    category: &'static str,
+    status: &'static str,
+    at: i64,
+}
+
+impl Transaction {
+    fn draw(rng: &mut ChaCha8Rng, at: i64) -> Self {
+        let kind = weighted(rng, &TRANSACTION_TYPES);
+        let (merchant, category, median) = match kind {
+            "purchase" | "refund" => {
+                let (merchant, category) = MERCHANTS[rng.gen_range(0..MERCHANTS.len())];
+                (Some(merchant), category, 35.0)
+            }
+            "transfer" => (None, "transfer", 250.0),
+            "deposit" => (None, "income", 1200.0),
+            "withdrawal" => (None, "cash", 80.0),
+            _ => (None, "fees", 5.0),
+        };
+        // Amounts spread over orders of magnitude, most of them small.
+        let amount = NumericDistribution::LogNormal { median, sigma: 1.0 }.sample(rng);
+        Self {
+            id: Inline::uuid(rng),
+            account_id: rng.gen_range(1..=ACCOUNTS),
+            kind,
+            cents: ((amount * 100.0).round() as u64).max(1),
+            currency: weighted(rng, &CURRENCIES),
+            merchant,
+            category,
+            status: weighted(rng, &TRANSACTION_STATUSES),
+            at,
+        }
+    }
+
+    fn write(&self, writer: &mut FieldWriter, buffers: &mut Buffers) {
+        writer.text("id", self.id.as_str());
+        writer.number("account_id", buffers.integer.format(self.account_id));
+        writer.text("type", self.kind);
+        writer.money("amount", self.cents, buffers);
+        writer.text("currency", self.currency);
+        match self.merchant {
+            Some(merchant) => writer.text("merchant", merchant),
+            None => writer.null("merchant"),
+        }
+        writer.text("category", self.category);
+        writer.text("status", self.status);
+        writer.time("timestamp", self.at, TimeKind::Timestamp, buffers);
+    }
+}
+
+/// One drawn record of a built-in dataset.
+pub(crate) enum DatasetRecord<'a> {
+    User(User<'a>),
+    Order(Order),
+    Event(Event<'a>),
+    Reading(Reading),
+    Transaction(Transaction),
+    /// A log line, without its newline.
+    Line(BytesMut),
+}
+
+impl DatasetRecord<'_> {
+    /// Writes the record as a JSON object or as a CSV row with its newline.
+    /// A log line is written as it is, with a newline in CSV, so it stands in
+    /// for either.
+    pub(crate) fn write(
+        &self,
+        format: OutputFormat,
+        pretty: bool,
+        buffers: &mut Buffers,
+        buffer: &mut BytesMut,
+    ) {
+        if let DatasetRecord::Line(line) = self {
+            buffer.put_slice(line);
+            if format == OutputFormat::CSV {
+                buffer.put_u8(b'\n');
+            }
+            return;
+        }
+        let mut writer = FieldWriter::new(buffer, format, pretty, b'{');
+        match self {
+            DatasetRecord::User(user) => user.write(&mut writer, buffers),
+            DatasetRecord::Order(order) => order.write(&mut writer, buffers),
+            DatasetRecord::Event(event) => event.write(&mut writer, buffers),
+            DatasetRecord::Reading(reading) => reading.write(&mut writer, buffers),
+            DatasetRecord::Transaction(transaction) => transaction.write(&mut writer, buffers),
+            DatasetRecord::Line(_) => unreachable!("lines are written as they are"),
+        }
+        writer.finish();
+    }
+}
+
+/// Formatting state reused from record to record.
+#[derive(Default)]
+pub(crate) struct Buffers {
+    integer: itoa::Buffer,
+    decimal: RevenueBuffer,
+    time: TimeBuffer,
+    /// JSON on its way into a CSV cell.
+    scratch: BytesMut,
+}
+
+/// Deepest a record's containers nest, counting the record itself.
+const MAX_LEVELS: usize = 3;
+
+/// Writes the fields of one record in order, as a JSON object or a CSV row.
+/// Objects nest in JSON and are flattened in CSV, where the dataset's header
+/// names their fields `object.field`.
+struct FieldWriter<'b> {
+    buffer: &'b mut BytesMut,
+    csv: bool,
+    pretty: bool,
+    /// The closing bracket of each open JSON container, the record's first.
+    brackets: [u8; MAX_LEVELS],
+    /// Whether each open container has an entry yet.
+    filled: [bool; MAX_LEVELS],
+    level: usize,
+}
+
+impl<'b> FieldWriter<'b> {
+    /// Starts a record, or in JSON any container opened with `bracket`.
+    fn new(buffer: &'b mut BytesMut, format: OutputFormat, pretty: bool, bracket: u8) -> Self {
+        let csv = format == OutputFormat::CSV;
+        if !csv {
+            buffer.put_u8(bracket);
+        }
+        Self {
+            buffer,
+            csv,
+            pretty,
+            brackets: [closing(bracket); MAX_LEVELS],
+            filled: [false; MAX_LEVELS],
+            level: 0,
+        }
+    }
+
+    /// Starts the next entry of the current container, with its key in an
+    /// object.
+    fn entry(&mut self, key: Option<&str>) {
+        let buffer = &mut *self.buffer;
+        if self.csv {
+            if self.filled[0] {
+                buffer.put_u8(b',');
+            }
+            self.filled[0] = true;
+            return;
+        }
+        if self.filled[self.level] {
+            buffer.put_u8(b',');
+        }
+        self.filled[self.level] = true;
+        if self.pretty {
+            put_indent(buffer, self.level + 1);
+        }
+        if let Some(key) = key {
+            buffer.put_u8(b'"');
+            buffer.put_slice(key.as_bytes());
+            buffer.put_slice(b"\": ");
+        }
+    }
+
+    fn text(&mut self, key: &str, value: &str) {
+        self.entry(Some(key));
+        if self.csv {
+            put_csv_field(self.buffer, value);
+        } else {
+            put_json_string(self.buffer, value);
+        }
+    }
+
+    fn number(&mut self, key: &str, value: &str) {
+        self.entry(Some(key));
+        self.buffer.put_slice(value.as_bytes());
+    }
+
+    /// `null` in JSON, an empty cell in CSV.
+    fn null(&mut self, key: &str) {
+        self.entry(Some(key));
+        if !self.csv {
+            self.buffer.put_slice(b"null");
+        }
+    }
+
+    /// An amount of money, given in cents, with two decimal places.
+    fn money(&mut self, key: &str, cents: u64, buffers: &mut Buffers) {
+        let mut cents_digits = itoa::Buffer::new();
+        let cents_digits = cents_digits.format(cents % 100);
+        self.number(key, buffers.integer.format(cents / 100));
+        if cents_digits.len() == 1 {
+            self.buffer.put_slice(b".0");
+        } else {
+            self.buffer.put_u8(b'.');
+        }
+        self.buffer.put_slice(cents_digits.as_bytes());
+    }
+
+    /// An RFC 3339 time at the precision of `kind`.
+    fn time(&mut self, key: &str, millis: i64, kind: TimeKind, buffers: &mut Buffers) {
+        let value = buffers.time.format(millis, kind, &TimeFormat::Rfc3339);
+        self.text(key, value);
+    }
+
+    /// Opens an object; in CSV its fields are written as they come.
+    fn open(&mut self, key: &str) {
+        if !self.csv {
+            self.entry(Some(key));
+            self.push(b'{');
+        }
+    }
+
+    /// Opens an array, which only JSON has.
+    fn open_array(&mut self, key: &str) {
+        debug_assert!(!self.csv, "CSV has no arrays");
+        self.entry(Some(key));
+        self.push(b'[');
+    }
+
+    /// Opens an object inside the current array.
+    fn open_element(&mut self) {
+        self.entry(None);
+        self.push(b'{');
+    }
+
+    fn push(&mut self, bracket: u8) {
+        self.buffer.put_u8(bracket);
+        self.level += 1;
+        self.brackets[self.level] = closing(bracket);
+        self.filled[self.level] = false;
+    }
+
+    /// Closes the innermost object or array.
+    fn close(&mut self) {
+        if self.csv {
+            return;
+        }
+        if self.pretty && self.filled[self.level] {
+            put_indent(self.buffer, self.level);
+        }
+        self.buffer.put_u8(self.brackets[self.level]);
+        self.level -= 1;
+    }
+
+    fn finish(self) {
+        if self.csv {
+            self.buffer.put_u8(b'\n');
+        } else {
+            debug_assert_eq!(self.level, 0, "containers are closed");
+            if self.pretty {
+                put_indent(self.buffer, 0);
+            }
+            self.buffer.put_u8(self.brackets[0]);
+        }
+    }
+}
+
+fn closing(bracket: u8) -> u8 {
+    match bracket {
+        b'[' => b']',
+        _ => b'}',
+    }
+}
+
+/// Starts a new line indented for entries at `level`, the record's own
+/// fields being at level 1.
+fn put_indent(buffer: &mut BytesMut, level: usize) {
+    buffer.put_u8(b'\n');
+    buffer.put_bytes(b' ', 2 + 2 * level);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(dataset: Dataset, format: OutputFormat, pretty: bool) -> Vec<u8> {
+        let pools = DataPools::with_seed(100, 1);
+        let times = TimeOptions::default();
+        let mut buffers = Buffers::default();
+        let mut output = Vec::new();
+        for index in 0..200 {
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            rng.set_stream(index);
+            let mut buffer = BytesMut::new();
+            dataset.draw(&mut rng, index, index, &pools, &times).write(
+                format,
+                pretty,
+                &mut buffers,
+                &mut buffer,
+            );
+            if format == OutputFormat::JSON {
+                buffer.put_u8(b'\n');
+            }
+            output.extend_from_slice(&buffer);
+        }
+        output
+    }
+
+    #[test]
+    fn json_records_parse_with_every_field() {
+        for dataset in Dataset::ALL[1..].iter().filter(|dataset| !dataset.is_log()) {
+            let header = std::str::from_utf8(dataset.csv_header()).unwrap();
+            let top_level: std::collections::HashSet<_> = header
+                .trim_end()
+                .split(',')
+                .map(|column| column.split('.').next().unwrap())
+                .collect();
+            for pretty in [false, true] {
+                let output = records(*dataset, OutputFormat::JSON, pretty);
+                let parsed: Vec<serde_json::Map<String, serde_json::Value>> =
+                    serde_json::Deserializer::from_slice(&output)
+                        .into_iter()
+                        .collect::<Result<_, _>>()
+                        .unwrap();
+                assert_eq!(parsed.len(), 200, "{}", dataset);
+                for record in parsed {
+                    let keys: std::collections::HashSet<_> =
+                        record.keys().map(String::as_str).collect();
+                    assert_eq!(keys, top_level, "{}", dataset);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn csv_rows_match_the_header() {
+        for dataset in Dataset::ALL[1..].iter().filter(|dataset| !dataset.is_log()) {
+            let columns = dataset.csv_header().split(|&b| b == b',').count();
+            let output = records(*dataset, OutputFormat::CSV, false);
+            let mut rows = 0;
+            for line in std::str::from_utf8(&output).unwrap().lines() {
+                let row = crate::pools::split_csv_row(line);
+                assert_eq!(row.len(), columns, "{}: {:?}", dataset, row);
+                rows += 1;
+            }
+            assert_eq!(rows, 200, "{}", dataset);
+        }
+    }
+
+    #[test]
+    fn order_totals_add_up_their_items() {
+        let output = records(Dataset::Orders, OutputFormat::JSON, false);
+        for line in output
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let order: serde_json::Value = serde_json::from_slice(line).unwrap();
+            let items = order["items"].as_array().unwrap();
+            assert!(!items.is_empty());
+            let sum: f64 = items
+                .iter()
+                .map(|item| {
+                    item["quantity"].as_f64().unwrap() * item["unit_price"].as_f64().unwrap()
+                })
+                .sum();
+            assert!((sum - order["total"].as_f64().unwrap()).abs() < 0.005);
+        }
+    }
+
+    #[test]
+    fn uuids_are_version_4() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let uuid = Inline::uuid(&mut rng);
+        let uuid = uuid.as_str();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+        assert_eq!(uuid.matches('-').count(), 4);
+    }
+}
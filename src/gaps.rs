@@ -0,0 +1,205 @@
+use crate::processing::RecordField;
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// A way a record field can be left without its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gap {
+    /// `null` in JSON, an empty cell in CSV.
+    Null,
+    /// No key at all in JSON, an empty cell in CSV.
+    Missing,
+    /// `""` in JSON and a quoted `""` in CSV, so it can be told from null.
+    Empty,
+}
+
+impl Gap {
+    pub const ALL: [Gap; 3] = [Gap::Null, Gap::Missing, Gap::Empty];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Gap::Null => "null",
+            Gap::Missing => "missing",
+            Gap::Empty => "empty",
+        }
+    }
+}
+
+impl FromStr for Gap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        Gap::ALL
+            .into_iter()
+            .find(|gap| gap.as_str() == name)
+            .ok_or_else(|| format!("Unknown gap '{}'", s))
+    }
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The gap, if any, of each field of one record, indexed by `RecordField`.
+pub type Gaps = [Option<Gap>; RecordField::ALL.len()];
+
+/// How often each field of a record is null, missing or empty.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GapRates {
+    rates: [[f64; Gap::ALL.len()]; RecordField::ALL.len()],
+}
+
+impl GapRates {
+    pub fn rate(&self, field: RecordField, gap: Gap) -> f64 {
+        self.rates[field as usize][gap as usize]
+    }
+
+    /// Leaves `field` without its value in `gap`'s way in a fraction `rate`
+    /// of records. A field's rates must add up to at most 1.
+    pub fn set(&mut self, field: RecordField, gap: Gap, rate: f64) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(format!(
+                "{} rate must be between 0 and 1, got {}",
+                gap, rate
+            ));
+        }
+        let mut rates = self.rates[field as usize];
+        rates[gap as usize] = rate;
+        if rates.iter().sum::<f64>() > 1.0 + f64::EPSILON * 4.0 {
+            return Err(format!("{} rates add up to more than 1", field));
+        }
+        self.rates[field as usize] = rates;
+        Ok(())
+    }
+
+    /// Reads `field=rate`, or `*=rate` for every field, e.g. `name=0.1`.
+    pub fn set_spec(&mut self, gap: Gap, spec: &str) -> Result<(), String> {
+        let (field, rate) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("Expected field=rate, got '{}'", spec))?;
+        self.set_rate(gap, field.trim(), rate.trim())
+    }
+
+    /// Sets the rate of `field`, or of every field for `*`, from strings.
+    pub fn set_rate(&mut self, gap: Gap, field: &str, rate: &str) -> Result<(), String> {
+        let rate = rate
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid {} rate '{}'", gap, rate))?;
+        if field == "*" {
+            RecordField::ALL
+                .into_iter()
+                .try_for_each(|field| self.set(field, gap, rate))
+        } else {
+            self.set(field.parse()?, gap, rate)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.iter().flatten().all(|rate| *rate == 0.0)
+    }
+
+    /// Draws which fields of a record have gaps. Fields with no rates use no
+    /// randomness, so records without gaps come out as they always have.
+    pub(crate) fn draw<R: Rng>(&self, rng: &mut R) -> Gaps {
+        let mut gaps = [None; RecordField::ALL.len()];
+        for (gap, rates) in gaps.iter_mut().zip(&self.rates) {
+            if rates.iter().all(|rate| *rate == 0.0) {
+                continue;
+            }
+            let mut draw = rng.gen::<f64>();
+            for (kind, rate) in Gap::ALL.into_iter().zip(rates) {
+                if draw < *rate {
+                    *gap = Some(kind);
+                    break;
+                }
+                draw -= rate;
+            }
+        }
+        gaps
+    }
+}
+
+impl fmt::Display for GapRates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut any = false;
+        for field in RecordField::ALL {
+            for gap in Gap::ALL {
+                let rate = self.rate(field, gap);
+                if rate > 0.0 {
+                    if any {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}.{}={}", gap, field, rate)?;
+                    any = true;
+                }
+            }
+        }
+        if !any {
+            f.write_str("none")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn rates_of_a_field_add_up_to_at_most_one() {
+        let mut rates = GapRates::default();
+        rates.set(RecordField::City, Gap::Null, 0.6).unwrap();
+        assert!(rates.set(RecordField::City, Gap::Missing, 0.5).is_err());
+        assert_eq!(rates.rate(RecordField::City, Gap::Missing), 0.0);
+        rates.set(RecordField::City, Gap::Missing, 0.4).unwrap();
+        assert!(rates.set(RecordField::Name, Gap::Empty, 1.5).is_err());
+        assert!(rates.set_rate(Gap::Null, "city", "lots").is_err());
+    }
+
+    #[test]
+    fn star_sets_every_field() {
+        let mut rates = GapRates::default();
+        rates.set_rate(Gap::Empty, "*", "0.25").unwrap();
+        for field in RecordField::ALL {
+            assert_eq!(rates.rate(field, Gap::Empty), 0.25);
+        }
+    }
+
+    #[test]
+    fn draw_follows_the_rates() {
+        let mut rates = GapRates::default();
+        rates.set(RecordField::Name, Gap::Null, 1.0).unwrap();
+        rates.set(RecordField::City, Gap::Null, 0.3).unwrap();
+        rates.set(RecordField::City, Gap::Missing, 0.2).unwrap();
+
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let draws = 10_000;
+        let mut counts = [0; Gap::ALL.len()];
+        for _ in 0..draws {
+            let gaps = rates.draw(&mut rng);
+            assert_eq!(gaps[RecordField::Name as usize], Some(Gap::Null));
+            assert_eq!(gaps[RecordField::Revenue as usize], None);
+            if let Some(gap) = gaps[RecordField::City as usize] {
+                counts[gap as usize] += 1;
+            }
+        }
+        let share = |gap: Gap| counts[gap as usize] as f64 / draws as f64;
+        assert!((share(Gap::Null) - 0.3).abs() < 0.03);
+        assert!((share(Gap::Missing) - 0.2).abs() < 0.03);
+        assert_eq!(counts[Gap::Empty as usize], 0);
+    }
+
+    #[test]
+    fn no_rates_draw_no_randomness() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut untouched = rng.clone();
+        assert_eq!(GapRates::default().draw(&mut rng), Gaps::default());
+        assert_eq!(rng.gen::<u64>(), untouched.gen::<u64>());
+    }
+}
@@ -0,0 +1,767 @@
+use crate::datasets::Dataset;
+use crate::gaps::GapRates;
+use crate::nesting::NestingOptions;
+use crate::numeric::NumericOptions;
+use crate::pools::{Distribution, Pool, PoolField, PoolOptions};
+use crate::processing::{
+    DataPools, Locale, OutputFormat, StreamGenerator, OPTIMAL_CHUNK_SIZE, POOL_SIZE,
+};
+use crate::temporal::TimeOptions;
+use crate::unique::{KeySpace, UniqueFields};
+use anyhow::{bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::Stream;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+
+pub(crate) const CSV_HEADER: &[u8] = b"id,name,industry,revenue,employees,city,state,country\n";
+
+/// Bytes generated by each parallel task before its output is handed on.
+const TASK_SIZE: u64 = 1024 * 1024;
+/// Chunks buffered between the generation thread and the consumer.
+const CHANNEL_DEPTH: usize = 16;
+
+/// How records are wrapped on the wire.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// One document: a JSON array, or CSV with a header row.
+    Document,
+    /// One record per line: newline-delimited JSON, or CSV with a header row.
+    /// Pretty printing is ignored so every record stays on its own line.
+    Lines,
+}
+
+impl Framing {
+    /// Bytes that go before the first record. The CSV header depends on the
+    /// dataset and, for business records, on how they are nested and which
+    /// time fields they carry. Log lines have nothing before them.
+    pub fn opening(
+        self,
+        format: OutputFormat,
+        dataset: Dataset,
+        nesting: &NestingOptions,
+        times: &TimeOptions,
+    ) -> Option<Bytes> {
+        match (format, self) {
+            _ if dataset.is_log() => None,
+            (OutputFormat::CSV, _) if dataset != Dataset::Business => {
+                Some(Bytes::from_static(dataset.csv_header()))
+            }
+            (OutputFormat::CSV, _) => Some(csv_header(nesting, times)),
+            (OutputFormat::JSON, Framing::Document) => Some(Bytes::from_static(b"[ ")),
+            (OutputFormat::JSON, Framing::Lines) | (OutputFormat::PROTOBUF, _) => None,
+        }
+    }
+
+    /// Bytes that go after the last record.
+    pub fn closing(self, format: OutputFormat) -> Option<Bytes> {
+        match (format, self) {
+            (OutputFormat::JSON, Framing::Document) => Some(Bytes::from_static(b"  ]")),
+            _ => None,
+        }
+    }
+}
+
+/// The CSV header row: the nested columns, then one per time field.
+fn csv_header(nesting: &NestingOptions, times: &TimeOptions) -> Bytes {
+    let header = nesting.csv_header();
+    if !times.is_enabled() {
+        return header;
+    }
+    let mut columns = BytesMut::from(&header[..header.len() - 1]);
+    for kind in times.kinds() {
+        columns.put_u8(b',');
+        columns.put_slice(kind.as_str().as_bytes());
+    }
+    columns.put_u8(b'\n');
+    columns.freeze()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Bytes(u64),
+    Records(u64),
+}
+
+/// Configures an in-process [`Generator`], for producing data without the
+/// server.
+///
+/// Either `size` or `count` is required. Everything else defaults to what a
+/// plain `/generate` request gets: a JSON document, a random seed and one
+/// generation thread per CPU.
+#[derive(Clone)]
+pub struct GeneratorBuilder {
+    format: OutputFormat,
+    framing: Framing,
+    target: Option<Target>,
+    seed: Option<u64>,
+    pretty: bool,
+    locale: Locale,
+    threads: Option<usize>,
+    chunk_size: u64,
+    pools: Option<Arc<DataPools>>,
+    pool_options: PoolOptions,
+    numbers: NumericOptions,
+    gaps: GapRates,
+    nesting: NestingOptions,
+    times: TimeOptions,
+    unique: UniqueFields,
+    dataset: Dataset,
+}
+
+impl Default for GeneratorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeneratorBuilder {
+    pub fn new() -> Self {
+        Self {
+            format: OutputFormat::JSON,
+            framing: Framing::Document,
+            target: None,
+            seed: None,
+            pretty: false,
+            locale: Locale::En,
+            threads: None,
+            chunk_size: OPTIMAL_CHUNK_SIZE,
+            pools: None,
+            pool_options: PoolOptions::default(),
+            numbers: NumericOptions::default(),
+            gaps: GapRates::default(),
+            nesting: NestingOptions::default(),
+            times: TimeOptions::default(),
+            unique: UniqueFields::default(),
+            dataset: Dataset::Business,
+        }
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Generates about `bytes` bytes of records, like `/generate?size=`.
+    pub fn size(mut self, bytes: u64) -> Self {
+        self.target = Some(Target::Bytes(bytes));
+        self
+    }
+
+    /// Generates exactly `records` records.
+    pub fn count(mut self, records: u64) -> Self {
+        self.target = Some(Target::Records(records));
+        self
+    }
+
+    /// Makes the output reproducible: the same seed and settings always give
+    /// the same bytes, whatever the thread count.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Locale the data pools are sampled from; ignored when `pools` is set.
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Number of generation threads; one per CPU by default.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Target size of each yielded chunk. Chunks always end on a record.
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Reuses data pools that are already built instead of sampling new ones
+    /// from the seed when generation starts. The output is then only
+    /// reproducible if the same pools are passed again.
+    pub fn pools(mut self, pools: Arc<DataPools>) -> Self {
+        self.pools = Some(pools);
+        self
+    }
+
+    /// Draws `field` from `pool` instead of the sampled or given pools, e.g.
+    /// one read with [`Pool::load`].
+    pub fn pool(mut self, field: PoolField, pool: Pool) -> Self {
+        self.pool_options.overrides.push((field, Arc::new(pool)));
+        self
+    }
+
+    /// Draws `field` with `distribution` instead of the pool's own weights,
+    /// e.g. [`Distribution::Zipf`] to make a few values dominate.
+    pub fn distribution(mut self, field: PoolField, distribution: Distribution) -> Self {
+        self.pool_options.distributions.push((field, distribution));
+        self
+    }
+
+    /// How revenue and employees are drawn and how revenue is written, e.g.
+    /// log-normal revenue as a decimal string.
+    pub fn numbers(mut self, numbers: NumericOptions) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    /// Leaves fields null, missing or empty in some records, to exercise
+    /// parsers. Which records get gaps depends only on the seed.
+    pub fn gaps(mut self, gaps: GapRates) -> Self {
+        self.gaps = gaps;
+        self
+    }
+
+    /// Adds nested objects and arrays to every record, e.g. an `address`
+    /// object and a `contacts` array. JSON and CSV only.
+    pub fn nesting(mut self, nesting: NestingOptions) -> Self {
+        self.nesting = nesting;
+        self
+    }
+
+    /// Adds date, datetime and timestamp fields to every record, or a time
+    /// series of timestamps. JSON and CSV only.
+    pub fn times(mut self, times: TimeOptions) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// Makes every value of the `unique` fields different, e.g. company
+    /// names or contact emails, by suffixing each with its record's key.
+    pub fn unique(mut self, unique: UniqueFields) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    /// Generates records of a built-in `dataset`, e.g. users or clickstream
+    /// events, instead of companies. Their fields are fixed, so only the
+    /// `timestamp` range and series of `times` apply to them. JSON and CSV
+    /// only. Log datasets always come one line per record, in time order.
+    pub fn dataset(mut self, dataset: Dataset) -> Self {
+        self.dataset = dataset;
+        self
+    }
+
+    pub fn build(self) -> Result<Generator> {
+        let Some(target) = self.target else {
+            bail!("Either size or count must be set");
+        };
+        if self.threads == Some(0) {
+            bail!("threads must be at least 1");
+        }
+        if self.chunk_size == 0 {
+            bail!("chunk_size must be greater than zero");
+        }
+        if !(-1.0..=1.0).contains(&self.numbers.correlation) {
+            bail!("correlation must be between -1 and 1");
+        }
+        if self.nesting.is_nested() && self.format == OutputFormat::PROTOBUF {
+            bail!("Nested records need json or csv output");
+        }
+        if self.times.is_enabled() && self.format == OutputFormat::PROTOBUF {
+            bail!("Time fields need json or csv output");
+        }
+        if let Err(e) = self.unique.validate(&self.gaps, &self.nesting) {
+            bail!(e);
+        }
+        if self.dataset != Dataset::Business {
+            if self.format == OutputFormat::PROTOBUF {
+                bail!("The {} dataset needs json or csv output", self.dataset);
+            }
+            if self.numbers != NumericOptions::default()
+                || self.gaps != GapRates::default()
+                || self.nesting.is_nested()
+                || self.times.date.is_some()
+                || self.times.datetime.is_some()
+                || !self.unique.is_empty()
+            {
+                bail!(
+                    "Numbers, gaps, nesting, date and datetime fields and unique fields only \
+                     apply to the business dataset"
+                );
+            }
+        }
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.threads.unwrap_or(0))
+            .thread_name(|i| format!("fjg-generator-{}", i))
+            .build()?;
+
+        let framing = if self.dataset.is_log() {
+            Framing::Lines
+        } else {
+            self.framing
+        };
+        Ok(Generator {
+            format: self.format,
+            framing,
+            target,
+            seed: self.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+            pretty: self.pretty && framing == Framing::Document,
+            locale: self.locale,
+            chunk_size: self.chunk_size,
+            pools: self.pools,
+            pool_options: self.pool_options,
+            numbers: self.numbers,
+            gaps: self.gaps,
+            nesting: self.nesting,
+            times: self.times,
+            unique: self.unique,
+            dataset: self.dataset,
+            pool,
+        })
+    }
+}
+
+/// A configured generation, consumed by one of `into_iter`, `into_stream` or
+/// `into_async_read`. Generation starts on its own thread at that point and
+/// stops early once the consumer is dropped.
+///
+/// Unlike the server, which streams chunks in whatever order its tasks finish,
+/// chunks here always come out in order, which is what makes `seed` reproduce
+/// the same bytes.
+pub struct Generator {
+    format: OutputFormat,
+    framing: Framing,
+    target: Target,
+    seed: u64,
+    pretty: bool,
+    locale: Locale,
+    chunk_size: u64,
+    pools: Option<Arc<DataPools>>,
+    pool_options: PoolOptions,
+    numbers: NumericOptions,
+    gaps: GapRates,
+    nesting: NestingOptions,
+    times: TimeOptions,
+    unique: UniqueFields,
+    dataset: Dataset,
+    pool: ThreadPool,
+}
+
+impl Generator {
+    pub fn builder() -> GeneratorBuilder {
+        GeneratorBuilder::new()
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Yields the output as chunks through an async `Stream`.
+    pub fn into_stream(self) -> impl Stream<Item = Bytes> + Send + Unpin {
+        ReceiverStream::new(self.start())
+    }
+
+    /// Exposes the output as one continuous `AsyncRead`.
+    pub fn into_async_read(self) -> ChunkReader {
+        ChunkReader {
+            body: self.start(),
+            current: Bytes::new(),
+        }
+    }
+
+    fn start(self) -> Receiver<Bytes> {
+        let (tx, rx) = channel(CHANNEL_DEPTH);
+        std::thread::Builder::new()
+            .name("fjg-generator".to_owned())
+            .spawn(move || self.run(tx))
+            .expect("failed to spawn the generator thread");
+        rx
+    }
+
+    fn run(self, tx: Sender<Bytes>) {
+        let pools = self.pools.clone().unwrap_or_else(|| {
+            Arc::new(DataPools::with_locale(
+                POOL_SIZE,
+                self.locale,
+                Some(self.seed),
+            ))
+        });
+        let pools = if self.pool_options.is_empty() {
+            pools
+        } else {
+            Arc::new(pools.customize(&self.pool_options))
+        };
+        let line_delimited = self.framing == Framing::Lines;
+        // Time series and log lines need their records in order.
+        let ordered = self.times.series.is_some() || self.dataset.is_log();
+        let per_task = |target| match target {
+            Target::Bytes(_) => TASK_SIZE,
+            Target::Records(_) => TASK_SIZE / 100,
+        };
+        // The kickoff generator and one per task share the key space, so
+        // unique values stay unique across them. The task count is bounded
+        // by the whole target.
+        let streams = if ordered {
+            1
+        } else {
+            match self.target {
+                Target::Bytes(total) | Target::Records(total) => {
+                    total.div_ceil(per_task(self.target)) + 1
+                }
+            }
+        };
+        let generator = |rng, budget, stream| {
+            match budget {
+                Target::Bytes(bytes) => {
+                    StreamGenerator::new(rng, &pools, self.pretty, self.format, bytes)
+                }
+                Target::Records(records) => {
+                    StreamGenerator::new(rng, &pools, self.pretty, self.format, u64::MAX)
+                        .with_record_limit(records)
+                }
+            }
+            .with_target_chunk_size(self.chunk_size)
+            .with_line_delimited(line_delimited)
+            .with_numbers(self.numbers)
+            .with_gaps(self.gaps)
+            .with_nesting(self.nesting)
+            .with_times(self.times.clone())
+            .with_dataset(self.dataset)
+            .with_unique(self.unique, KeySpace::new(stream, streams))
+        };
+        let send = |chunk: Bytes| tx.blocking_send(chunk).is_ok();
+
+        if let Some(opening) =
+            self.framing
+                .opening(self.format, self.dataset, &self.nesting, &self.times)
+        {
+            if !send(opening) {
+                return;
+            }
+        }
+
+        // The first record goes out alone so JSON documents need no leading
+        // comma; the parallel tasks then produce the rest.
+        let mut kickoff = generator(ChaCha8Rng::seed_from_u64(self.seed), self.target, 0);
+        let remaining = match kickoff.generate_kickoff_chunk() {
+            Some(chunk) => {
+                let written = chunk.len() as u64;
+                if !send(chunk) {
+                    return;
+                }
+                match self.target {
+                    Target::Bytes(bytes) => Target::Bytes(bytes.saturating_sub(written)),
+                    Target::Records(records) => Target::Records(records - 1),
+                }
+            }
+            None => match self.target {
+                Target::Bytes(_) => Target::Bytes(0),
+                Target::Records(_) => Target::Records(0),
+            },
+        };
+
+        // A time series numbers records across the whole output, so the
+        // kickoff generator carries on through it, drawing each of its chunks
+        // in parallel.
+        if ordered {
+            let finished = self.pool.install(|| {
+                while let Some(chunk) = kickoff.generate_chunk() {
+                    if !send(chunk) {
+                        return false;
+                    }
+                }
+                true
+            });
+            if finished {
+                if let Some(closing) = self.framing.closing(self.format) {
+                    send(closing);
+                }
+            }
+            return;
+        }
+
+        let per_task = per_task(remaining);
+        let total = match remaining {
+            Target::Bytes(total) | Target::Records(total) => total,
+        };
+        let num_tasks = total.div_ceil(per_task);
+        let window = self.pool.current_num_threads() as u64 * 2;
+
+        let mut first = 0;
+        while first < num_tasks {
+            let last = (first + window).min(num_tasks);
+            let outputs: Vec<Vec<Bytes>> = self.pool.install(|| {
+                (first..last)
+                    .into_par_iter()
+                    .map(|task| {
+                        let budget = per_task.min(total - task * per_task);
+                        let budget = match remaining {
+                            Target::Bytes(_) => Target::Bytes(budget),
+                            Target::Records(_) => Target::Records(budget),
+                        };
+                        let rng = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(task + 1));
+                        let mut generator = generator(rng, budget, task + 1);
+                        std::iter::from_fn(|| generator.generate_chunk()).collect()
+                    })
+                    .collect()
+            });
+            for chunk in outputs.into_iter().flatten() {
+                if !send(chunk) {
+                    return;
+                }
+            }
+            first = last;
+        }
+
+        if let Some(closing) = self.framing.closing(self.format) {
+            send(closing);
+        }
+    }
+}
+
+impl IntoIterator for Generator {
+    type Item = Bytes;
+    type IntoIter = Chunks;
+
+    /// Yields the output as chunks, blocking until each is ready. Must not be
+    /// driven from async code; use `into_stream` there.
+    fn into_iter(self) -> Chunks {
+        Chunks { body: self.start() }
+    }
+}
+
+/// Blocking iterator over a [`Generator`]'s chunks.
+pub struct Chunks {
+    body: Receiver<Bytes>,
+}
+
+impl Iterator for Chunks {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        self.body.blocking_recv()
+    }
+}
+
+/// A [`Generator`]'s output as one continuous byte stream.
+pub struct ChunkReader {
+    body: Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl AsyncRead for ChunkReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.current.is_empty() {
+            match ready!(self.body.poll_recv(cx)) {
+                Some(chunk) => self.current = chunk,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let len = self.current.len().min(buf.remaining());
+        buf.put_slice(&self.current.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    fn builder(format: OutputFormat, threads: usize) -> GeneratorBuilder {
+        GeneratorBuilder::new()
+            .format(format)
+            .size(5 * TASK_SIZE / 2)
+            .chunk_size(256 * 1024)
+            .seed(42)
+            .threads(threads)
+    }
+
+    fn read_all(mut reader: ChunkReader) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut buffer = [0; 8192];
+        loop {
+            let mut buf = ReadBuf::new(&mut buffer);
+            block_on(std::future::poll_fn(|cx| {
+                Pin::new(&mut reader).poll_read(cx, &mut buf)
+            }))
+            .unwrap();
+            if buf.filled().is_empty() {
+                return output;
+            }
+            output.extend_from_slice(buf.filled());
+        }
+    }
+
+    #[test]
+    fn every_interface_gives_the_same_bytes() {
+        for format in [
+            OutputFormat::JSON,
+            OutputFormat::CSV,
+            OutputFormat::PROTOBUF,
+        ] {
+            let build = |threads| builder(format, threads).build().unwrap();
+
+            let iterated: Vec<u8> = build(1).into_iter().flatten().collect();
+            let streamed: Vec<u8> = block_on(build(4).into_stream().collect::<Vec<_>>()).concat();
+            let read = read_all(build(2).into_async_read());
+
+            // Three tasks, more than one thread's window.
+            assert!(iterated.len() as u64 > 2 * TASK_SIZE, "{:?}", format);
+            assert!(iterated == streamed, "{:?}", format);
+            assert!(iterated == read, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn a_different_seed_gives_different_bytes() {
+        let first: Vec<u8> = builder(OutputFormat::CSV, 2)
+            .build()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        let other: Vec<u8> = builder(OutputFormat::CSV, 2)
+            .seed(43)
+            .build()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        assert!(first != other);
+    }
+
+    #[test]
+    fn time_series_rise_across_the_whole_output() {
+        let mut times = TimeOptions::default();
+        times.enable(crate::temporal::TimeKind::Date);
+        times.set_series(crate::temporal::TimeSeries::new(10.0, 0.5).unwrap());
+        let output: Vec<u8> = GeneratorBuilder::new()
+            .format(OutputFormat::CSV)
+            .count(5000)
+            .chunk_size(4 * 1024)
+            .threads(4)
+            .seed(1)
+            .times(times)
+            .build()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert!(lines.next().unwrap().ends_with(",date,timestamp"));
+        let stamps: Vec<i64> = lines
+            .map(|line| line.rsplit(',').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(stamps.len(), 5000);
+        assert!(stamps.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn log_lines_come_in_time_order() {
+        let output: Vec<u8> = GeneratorBuilder::new()
+            .count(5000)
+            .chunk_size(4 * 1024)
+            .threads(4)
+            .seed(1)
+            .dataset(Dataset::AppLog)
+            .build()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        let stamps: Vec<String> = serde_json::Deserializer::from_slice(&output)
+            .into_iter::<serde_json::Value>()
+            .map(|line| line.unwrap()["timestamp"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(stamps.len(), 5000);
+        assert_eq!(output.iter().filter(|&&b| b == b'\n').count(), 5000);
+        // RFC 3339 times in UTC sort as text.
+        assert!(stamps.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn unique_values_never_repeat_across_tasks() {
+        let mut unique = UniqueFields::default();
+        unique.set_spec("name,email").unwrap();
+        let nesting = NestingOptions {
+            contacts: Some(crate::nesting::Lengths::DEFAULT_CONTACTS),
+            ..Default::default()
+        };
+        let output: Vec<u8> = GeneratorBuilder::new()
+            .framing(Framing::Lines)
+            .count(50_000)
+            .threads(4)
+            .seed(1)
+            .nesting(nesting)
+            .unique(unique)
+            .build()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut names = std::collections::HashSet::new();
+        let mut emails = std::collections::HashSet::new();
+        for line in output
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let record: serde_json::Value = serde_json::from_slice(line).unwrap();
+            assert!(names.insert(record["name"].as_str().unwrap().to_owned()));
+            for contact in record["contacts"].as_array().unwrap() {
+                assert!(emails.insert(contact["email"].as_str().unwrap().to_owned()));
+            }
+        }
+        assert_eq!(names.len(), 50_000);
+    }
+
+    #[test]
+    fn counts_give_exactly_that_many_records() {
+        for framing in [Framing::Document, Framing::Lines] {
+            let output: Vec<u8> = GeneratorBuilder::new()
+                .count(2500)
+                .chunk_size(16 * 1024)
+                .framing(framing)
+                .seed(1)
+                .build()
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .collect();
+            let records: Vec<serde_json::Value> = match framing {
+                Framing::Document => serde_json::from_slice(&output).unwrap(),
+                Framing::Lines => output
+                    .split(|&byte| byte == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .map(|line| serde_json::from_slice(line).unwrap())
+                    .collect(),
+            };
+            assert_eq!(records.len(), 2500);
+        }
+    }
+}
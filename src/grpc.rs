@@ -0,0 +1,338 @@
+// `tonic::Status` is large, but it is what every streamed item carries.
+#![allow(clippy::result_large_err)]
+
+use crate::jobs::JobState;
+use crate::pipeline::{Generation, GenerationRequest, GenerationService, StartError};
+use crate::processing::OutputFormat;
+use crate::shutdown::ShutdownCoordinator;
+use bytes::{Buf, BufMut, Bytes};
+use futures::{future, stream, Stream, StreamExt};
+use prost::encoding::{skip_field, DecodeContext, WireType};
+use prost::DecodeError;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::{info, info_span, warn, Instrument};
+
+include!(concat!(env!("OUT_DIR"), "/fastjsongen.v1.Generator.rs"));
+
+pub use generator_server::{Generator, GeneratorServer};
+
+// Messages from `proto/generator.proto`, written out by hand so the build does
+// not need `protoc`.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GenerateRequest {
+    /// Target size of the encoded records, as in `/generate?size=`.
+    #[prost(string, tag = "1")]
+    pub size: String,
+    /// Locale of the generated strings, as in `/generate?locale=`; empty for
+    /// the server's default.
+    #[prost(string, tag = "2")]
+    pub locale: String,
+    /// Any other `/generate` query parameter. `format` must be `protobuf` if
+    /// given, and `pretty` has no effect.
+    #[prost(map = "string, string", tag = "3")]
+    pub parameters: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BusinessLocation {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub industry: String,
+    #[prost(float, tag = "3")]
+    pub revenue: f32,
+    #[prost(uint32, tag = "4")]
+    pub employees: u32,
+    #[prost(string, tag = "5")]
+    pub city: String,
+    #[prost(string, tag = "6")]
+    pub state: String,
+    #[prost(string, tag = "7")]
+    pub country: String,
+    #[prost(double, tag = "8")]
+    pub revenue_f64: f64,
+    #[prost(string, tag = "9")]
+    pub revenue_decimal: String,
+    #[prost(int64, tag = "10")]
+    pub revenue_cents: i64,
+}
+
+/// The decoded form of a streamed batch, for Rust clients.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RecordBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<BusinessLocation>,
+}
+
+/// A `RecordBatch` already encoded by the `protobuf` output format. Every
+/// pipeline chunk is a run of `records` entries, so it goes on the wire as is.
+#[derive(Debug, Clone, Default)]
+pub struct EncodedBatch(pub Bytes);
+
+impl prost::Message for EncodedBatch {
+    fn encode_raw(&self, buf: &mut impl BufMut) {
+        buf.put_slice(&self.0);
+    }
+
+    // Only ever encoded by the server; decode into `RecordBatch` instead.
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut impl Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        skip_field(wire_type, tag, buf, ctx)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl From<StartError> for Status {
+    fn from(err: StartError) -> Self {
+        match err {
+            StartError::ShuttingDown | StartError::WarmingUp { .. } => {
+                Status::unavailable(err.to_string())
+            }
+            StartError::NotAdmitted { .. } => Status::resource_exhausted(err.to_string()),
+        }
+    }
+}
+
+pub struct GrpcGenerator {
+    service: GenerationService,
+}
+
+impl GrpcGenerator {
+    pub fn new(service: GenerationService) -> Self {
+        Self { service }
+    }
+}
+
+type BatchStream = Pin<Box<dyn Stream<Item = Result<EncodedBatch, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Generator for GrpcGenerator {
+    type GenerateStream = BatchStream;
+
+    async fn generate(
+        &self,
+        request: Request<GenerateRequest>,
+    ) -> Result<Response<Self::GenerateStream>, Status> {
+        let span = info_span!(
+            "grpc_generate",
+            job_id = tracing::field::Empty,
+            format = tracing::field::Empty,
+            size = tracing::field::Empty
+        );
+
+        let GenerateRequest {
+            size,
+            locale,
+            mut parameters,
+        } = request.into_inner();
+        if let Some(format) = parameters.get("format") {
+            if format.parse::<OutputFormat>() != Ok(OutputFormat::PROTOBUF) {
+                return Err(Status::invalid_argument(
+                    "gRPC streams are always protobuf, format cannot be changed",
+                ));
+            }
+        }
+        parameters.insert("format".to_owned(), "protobuf".to_owned());
+        for (key, value) in [("size", size), ("locale", locale)] {
+            if !value.is_empty() {
+                parameters.insert(key.to_owned(), value);
+            }
+        }
+        let generate = GenerationRequest::from_query(&parameters, self.service.config())
+            .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+
+        let Generation { body, progress, .. } =
+            self.service.start(generate).instrument(span).await?;
+
+        // The pipeline has no record to spare for a truncation marker, so a
+        // stream cut short by shutdown ends with a status instead.
+        let end = stream::once(async move {
+            (progress.state() == JobState::Truncated)
+                .then(|| Err(Status::unavailable("truncated: server shutting down")))
+        })
+        .filter_map(future::ready);
+
+        let batches = ReceiverStream::new(body).map(|chunk| Ok(EncodedBatch(chunk)));
+        Ok(Response::new(Box::pin(batches.chain(end))))
+    }
+}
+
+/// Serves the `Generator` service on `addr` until shutdown begins, then lets
+/// in-flight streams drain or be truncated like HTTP ones.
+pub async fn serve(
+    addr: SocketAddr,
+    service: GenerationService,
+    coordinator: Arc<ShutdownCoordinator>,
+) {
+    info!(%addr, "gRPC service listening");
+    let result = tonic::transport::Server::builder()
+        .add_service(GeneratorServer::new(GrpcGenerator::new(service)))
+        .serve_with_shutdown(addr, async move { coordinator.shutdown_started().await })
+        .await;
+    if let Err(e) = result {
+        warn!(error = %e, "gRPC service stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::gaps::{Gap, GapRates};
+    use crate::generator::GeneratorBuilder;
+    use crate::jobs::JobRegistry;
+    use crate::numeric::NumericOptions;
+    use crate::processing::{Locale, PoolCache};
+    use crate::scheduler::Scheduler;
+    use prost::Message;
+    use serde_json::Value;
+    use std::time::Duration;
+    use tonic::Code;
+
+    fn generate(format: OutputFormat, numbers: NumericOptions, gaps: GapRates) -> Vec<u8> {
+        GeneratorBuilder::new()
+            .format(format)
+            .count(2_000)
+            .seed(7)
+            .threads(2)
+            .numbers(numbers)
+            .gaps(gaps)
+            .build()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn protobuf_output_decodes_to_the_json_records() {
+        let mut gaps = GapRates::default();
+        gaps.set_rate(Gap::Null, "*", "0.2").unwrap();
+        for revenue_type in ["f32", "f64:2", "decimal:2", "cents"] {
+            let numbers = NumericOptions {
+                revenue_type: revenue_type.parse().unwrap(),
+                ..NumericOptions::default()
+            };
+            let encoded = generate(OutputFormat::PROTOBUF, numbers, gaps);
+            let batch = RecordBatch::decode(&encoded[..]).unwrap();
+            let records: Vec<Value> =
+                serde_json::from_slice(&generate(OutputFormat::JSON, numbers, gaps)).unwrap();
+            assert_eq!(batch.records.len(), 2_000);
+            assert_eq!(batch.records.len(), records.len());
+
+            for (decoded, record) in batch.records.iter().zip(&records) {
+                let text = |field: &str| record[field].as_str().unwrap_or_default();
+                assert_eq!(decoded.name, text("name"));
+                assert_eq!(decoded.industry, text("industry"));
+                assert_eq!(decoded.city, text("city"));
+                assert_eq!(decoded.state, text("state"));
+                assert_eq!(decoded.country, text("country"));
+                assert_eq!(
+                    decoded.employees as u64,
+                    record["employees"].as_u64().unwrap_or_default()
+                );
+
+                let revenue = &record["revenue"];
+                match revenue_type {
+                    "f32" => {
+                        assert_eq!(decoded.revenue, revenue.as_f64().unwrap_or_default() as f32)
+                    }
+                    "f64:2" => {
+                        assert_eq!(decoded.revenue_f64, revenue.as_f64().unwrap_or_default())
+                    }
+                    "decimal:2" => assert_eq!(decoded.revenue_decimal, text("revenue")),
+                    _ => assert_eq!(decoded.revenue_cents, revenue.as_i64().unwrap_or_default()),
+                }
+            }
+        }
+    }
+
+    async fn grpc_generator() -> GrpcGenerator {
+        let config = ServerConfig::default();
+        let pools = PoolCache::ready(Locale::En).await;
+        GrpcGenerator::new(GenerationService::new(
+            Arc::new(config),
+            Scheduler::new(2, 1, 0, Duration::from_secs(1)).unwrap(),
+            Arc::new(JobRegistry::new()),
+            pools,
+            Arc::new(ShutdownCoordinator::new()),
+        ))
+    }
+
+    fn request(size: &str, parameters: &[(&str, &str)]) -> Request<GenerateRequest> {
+        Request::new(GenerateRequest {
+            size: size.to_owned(),
+            locale: String::new(),
+            parameters: parameters
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+
+    #[actix_web::test]
+    async fn generate_streams_record_batches_up_to_the_size() {
+        let generator = grpc_generator().await;
+        let response = generator
+            .generate(request("64kb", &[("revenue_type", "cents")]))
+            .await
+            .unwrap();
+        let batches: Vec<_> = response.into_inner().collect().await;
+        assert!(!batches.is_empty());
+
+        let mut records = 0;
+        let mut bytes = 0;
+        for batch in batches {
+            let batch = batch.unwrap();
+            bytes += batch.0.len();
+            let decoded = RecordBatch::decode(batch.0).unwrap();
+            assert!(decoded.records.iter().all(|record| record.revenue == 0.0
+                && record.revenue_cents > 0
+                && !record.name.is_empty()));
+            records += decoded.records.len();
+        }
+        assert!(records > 0);
+        assert!((64 * 1024..64 * 1024 + 512).contains(&bytes), "{}", bytes);
+    }
+
+    #[actix_web::test]
+    async fn bad_requests_are_invalid_arguments() {
+        let generator = grpc_generator().await;
+        for (size, parameters) in [
+            ("", &[][..]),
+            ("10pb", &[]),
+            ("1kb", &[("format", "json")]),
+            ("1kb", &[("locale", "xx_XX")]),
+            ("1kb", &[("nest", "address")]),
+        ] {
+            let status = generator
+                .generate(request(size, parameters))
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(status.code(), Code::InvalidArgument, "{}", status.message());
+        }
+        let response = generator
+            .generate(request("1kb", &[("format", "proto"), ("pretty", "true")]))
+            .await;
+        assert!(response.is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::util::ProgressInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+/// Number of finished jobs kept around so `/jobs/{id}` still answers after the
+/// stream ends.
+const FINISHED_HISTORY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Aborted,
+    Rejected,
+    Truncated,
+}
+
+impl JobState {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => JobState::Queued,
+            1 => JobState::Running,
+            2 => JobState::Completed,
+            3 => JobState::Aborted,
+            4 => JobState::Rejected,
+            _ => JobState::Truncated,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+/// Point-in-time view of a job, as served by `/jobs`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub state: JobState,
+    pub format: String,
+    pub target_bytes: u64,
+    pub bytes_generated: u64,
+    pub bytes_streamed: u64,
+    pub records: u64,
+    pub generated_percent: f64,
+    pub streamed_percent: f64,
+    pub elapsed_seconds: f64,
+    pub rate_bytes_per_second: f64,
+    pub eta_seconds: Option<f64>,
+}
+
+/// Tracks every generation request from admission until it leaves the
+/// finished-history window.
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    active: Mutex<BTreeMap<u64, Arc<ProgressInfo>>>,
+    finished: Mutex<VecDeque<Arc<ProgressInfo>>>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            ..Default::default()
+        }
+    }
+
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn insert(&self, progress: Arc<ProgressInfo>) {
+        self.active.lock().insert(progress.id(), progress);
+    }
+
+    /// Moves a job into the finished history with its final state.
+    pub fn finish(&self, id: u64, state: JobState) {
+        let Some(progress) = self.active.lock().remove(&id) else {
+            return;
+        };
+        progress.set_state(state);
+
+        let mut finished = self.finished.lock();
+        if finished.len() == FINISHED_HISTORY {
+            finished.pop_front();
+        }
+        finished.push_back(progress);
+    }
+
+    pub fn active(&self) -> Vec<Arc<ProgressInfo>> {
+        self.active.lock().values().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<ProgressInfo>> {
+        if let Some(progress) = self.active.lock().get(&id) {
+            return Some(progress.clone());
+        }
+        self.finished
+            .lock()
+            .iter()
+            .find(|progress| progress.id() == id)
+            .cloned()
+    }
+
+    /// Active jobs first, then the finished history, newest first.
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        let mut statuses: Vec<_> = self.active().iter().map(|p| p.status()).collect();
+        statuses.extend(self.finished.lock().iter().rev().map(|p| p.status()));
+        statuses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::OutputFormat;
+
+    fn job(registry: &JobRegistry) -> u64 {
+        let id = registry.next_id();
+        let progress = ProgressInfo::new(id, 100, 1, "b".to_owned(), OutputFormat::JSON);
+        registry.insert(Arc::new(progress));
+        id
+    }
+
+    #[test]
+    fn jobs_move_from_active_to_finished() {
+        let registry = JobRegistry::new();
+        let first = job(&registry);
+        let second = job(&registry);
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(registry.get(first).unwrap().state(), JobState::Queued);
+        registry.get(first).unwrap().set_state(JobState::Running);
+
+        registry.finish(first, JobState::Completed);
+        assert_eq!(registry.active().len(), 1);
+        assert_eq!(registry.get(first).unwrap().state(), JobState::Completed);
+        // Only the first finish counts.
+        registry.finish(first, JobState::Aborted);
+        assert_eq!(registry.get(first).unwrap().state(), JobState::Completed);
+
+        let states: Vec<_> = registry
+            .statuses()
+            .iter()
+            .map(|status| (status.id, status.state))
+            .collect();
+        assert_eq!(
+            states,
+            [(second, JobState::Queued), (first, JobState::Completed)]
+        );
+        assert!(registry.get(99).is_none());
+    }
+
+    #[test]
+    fn only_the_latest_finished_jobs_are_kept() {
+        let registry = JobRegistry::new();
+        let ids: Vec<u64> = (0..FINISHED_HISTORY + 3)
+            .map(|_| {
+                let id = job(&registry);
+                registry.finish(id, JobState::Completed);
+                id
+            })
+            .collect();
+
+        for evicted in &ids[..3] {
+            assert!(registry.get(*evicted).is_none());
+        }
+        let statuses = registry.statuses();
+        assert_eq!(statuses.len(), FINISHED_HISTORY);
+        assert_eq!(statuses[0].id, *ids.last().unwrap());
+        assert_eq!(statuses[FINISHED_HISTORY - 1].id, ids[3]);
+    }
+
+    #[test]
+    fn states_round_trip_through_u8() {
+        for state in [
+            JobState::Queued,
+            JobState::Running,
+            JobState::Completed,
+            JobState::Aborted,
+            JobState::Rejected,
+            JobState::Truncated,
+        ] {
+            assert_eq!(JobState::from_u8(state as u8), state);
+        }
+    }
+}
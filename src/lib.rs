@@ -1,3 +1,50 @@
-#![feature(portable_simd)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+pub mod archive;
+#[cfg(feature = "server")]
+pub mod config;
+pub mod datasets;
+pub mod gaps;
+pub mod generator;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
+pub mod logging;
+pub mod logs;
+#[cfg(feature = "server")]
+pub mod metrics;
+pub mod nesting;
+pub mod numeric;
+#[cfg(feature = "server")]
+pub mod pipeline;
+pub mod pools;
 pub mod processing;
+#[cfg(feature = "server")]
+pub mod raw;
+pub mod relational;
+#[cfg(feature = "server")]
+pub mod scheduler;
+#[cfg(feature = "server")]
+pub mod shutdown;
+#[cfg(feature = "server")]
+pub mod sse;
+pub mod temporal;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod unique;
+#[cfg(feature = "server")]
 pub mod util;
+#[cfg(feature = "server")]
+pub mod ws;
+
+pub use datasets::Dataset;
+pub use gaps::{Gap, GapRates};
+pub use generator::{Generator, GeneratorBuilder};
+pub use nesting::{Flatten, Lengths, Nested, NestingOptions};
+pub use numeric::{NumericDistribution, NumericOptions, RevenueType};
+pub use pools::{Distribution, Pool, PoolField};
+pub use processing::{Locale, RecordField};
+pub use relational::{Table, TableFormat, TableOptions, Tables};
+pub use temporal::{TimeField, TimeFormat, TimeKind, TimeOptions, TimeRange, TimeSeries};
+pub use unique::{KeySpace, UniqueFields};
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub const ALL: [LogFormat; 2] = [LogFormat::Text, LogFormat::Json];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        LogFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str() == name)
+            .ok_or_else(|| format!("Unknown log format '{}'", name))
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Installs the global subscriber. `filter` takes `RUST_LOG`-style directives
+/// such as `info` or `fast_json_gen=debug,actix_server=warn`.
+///
+/// Logs go to stderr so they never interleave with generated data or the
+/// dashboard on stdout. Span close events carry `time.busy` and `time.idle`,
+/// which is how request phase timings show up.
+pub fn init(filter: &str, format: LogFormat) -> anyhow::Result<()> {
+    subscriber(filter, format, std::io::stderr)?
+        .try_init()
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+/// The subscriber `init` installs, writing to `writer`.
+fn subscriber<W>(
+    filter: &str,
+    format: LogFormat,
+    writer: W,
+) -> anyhow::Result<Box<dyn Subscriber + Send + Sync>>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_new(filter)?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(writer);
+
+    Ok(match format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().with_current_span(true).finish()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing::{info, info_span, warn};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The lines logged while running `log` with `filter` and `format`.
+    fn logged(filter: &str, format: LogFormat, log: impl FnOnce()) -> Vec<String> {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = subscriber(filter, format, move || writer.clone()).unwrap();
+        tracing::subscriber::with_default(subscriber, log);
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn formats_parse_in_any_case() {
+        assert_eq!("JSON".parse(), Ok(LogFormat::Json));
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert!("yaml".parse::<LogFormat>().is_err());
+        for format in LogFormat::ALL {
+            assert_eq!(format.as_str().parse(), Ok(format));
+            assert_eq!(format.to_string(), format.as_str());
+        }
+    }
+
+    #[test]
+    fn json_lines_carry_the_span_and_its_timings() {
+        let lines = logged("info", LogFormat::Json, || {
+            let _span = info_span!("generate", job_id = 7).entered();
+            info!(records = 3, "generation finished");
+        });
+        let events: Vec<Value> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(events[0]["fields"]["message"], "generation finished");
+        assert_eq!(events[0]["fields"]["records"], 3);
+        assert_eq!(events[0]["span"]["name"], "generate");
+        assert_eq!(events[0]["span"]["job_id"], 7);
+        // The span's close event has its phase timings.
+        assert_eq!(events[1]["fields"]["message"], "close");
+        assert!(events[1]["fields"]["time.busy"].is_string());
+        assert!(events[1]["fields"]["time.idle"].is_string());
+    }
+
+    #[test]
+    fn filters_take_per_target_levels() {
+        let lines = logged("info,fast_json_gen=warn", LogFormat::Text, || {
+            info!("hidden");
+            warn!("shown");
+        });
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("WARN") && lines[0].contains("shown"));
+
+        assert!(subscriber("fast_json_gen=loud", LogFormat::Text, std::io::stderr).is_err());
+    }
+}
@@ -0,0 +1,681 @@
+use crate::datasets::{weighted, Dataset};
+use bytes::{BufMut, BytesMut};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Draws line `index` of a log dataset, stamped `at` milliseconds since the
+/// epoch, without its newline.
+pub(crate) fn draw(dataset: Dataset, rng: &mut ChaCha8Rng, index: u64, at: i64) -> BytesMut {
+    let mut line = BytesMut::with_capacity(256);
+    let time = Civil::new(at);
+    match dataset {
+        Dataset::AccessLog => access_line(rng, &time, &mut line),
+        Dataset::Syslog => syslog_line(rng, &time, &mut line),
+        Dataset::AppLog => {
+            app_line(rng, index, &time, &mut line);
+        }
+        Dataset::ContainerLog => container_line(rng, index, &time, &mut line),
+        _ => unreachable!("{} is not a log dataset", dataset),
+    }
+    line
+}
+
+/// A UTC time split into the fields log formats write.
+struct Civil {
+    year: u32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    millis: u32,
+}
+
+impl Civil {
+    fn new(millis: i64) -> Self {
+        let time = DateTime::<Utc>::from_timestamp_millis(millis).unwrap_or_default();
+        Self {
+            year: time.year() as u32,
+            month: time.month(),
+            day: time.day(),
+            hour: time.hour(),
+            minute: time.minute(),
+            second: time.second(),
+            millis: time.timestamp_subsec_millis(),
+        }
+    }
+
+    /// `2024-06-01T12:30:05.250`, to be followed by a zone or more digits.
+    fn put_rfc3339(&self, line: &mut BytesMut) {
+        put_padded(line, self.year, 4);
+        line.put_u8(b'-');
+        put_padded(line, self.month, 2);
+        line.put_u8(b'-');
+        put_padded(line, self.day, 2);
+        line.put_u8(b'T');
+        self.put_clock(line);
+        line.put_u8(b'.');
+        put_padded(line, self.millis, 3);
+    }
+
+    /// `01/Jun/2024:12:30:05 +0000`, as web servers write it.
+    fn put_common_log(&self, line: &mut BytesMut) {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        put_padded(line, self.day, 2);
+        line.put_u8(b'/');
+        line.put_slice(MONTHS[self.month as usize - 1].as_bytes());
+        line.put_u8(b'/');
+        put_padded(line, self.year, 4);
+        line.put_u8(b':');
+        self.put_clock(line);
+        line.put_slice(b" +0000");
+    }
+
+    fn put_clock(&self, line: &mut BytesMut) {
+        put_padded(line, self.hour, 2);
+        line.put_u8(b':');
+        put_padded(line, self.minute, 2);
+        line.put_u8(b':');
+        put_padded(line, self.second, 2);
+    }
+}
+
+fn put_padded(line: &mut BytesMut, value: u32, width: usize) {
+    let mut digits = itoa::Buffer::new();
+    let digits = digits.format(value);
+    line.put_bytes(b'0', width.saturating_sub(digits.len()));
+    line.put_slice(digits.as_bytes());
+}
+
+fn put_number(line: &mut BytesMut, value: u64) {
+    line.put_slice(itoa::Buffer::new().format(value).as_bytes());
+}
+
+fn put_hex(line: &mut BytesMut, rng: &mut ChaCha8Rng, digits: usize) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    for _ in 0..digits {
+        line.put_u8(HEX[rng.gen_range(0..16)]);
+    }
+}
+
+fn put_ip(line: &mut BytesMut, octets: [u8; 4]) {
+    for (i, octet) in octets.into_iter().enumerate() {
+        if i > 0 {
+            line.put_u8(b'.');
+        }
+        put_number(line, octet as u64);
+    }
+}
+
+/// A public-looking IPv4 address: no private, loopback or multicast ranges.
+fn public_ip(rng: &mut ChaCha8Rng) -> [u8; 4] {
+    let mut octets: [u8; 4] = rng.gen();
+    octets[0] = match octets[0] % 223 + 1 {
+        10 | 127 => 11,
+        first => first,
+    };
+    match octets {
+        [172, second @ 16..=31, ..] => octets[1] = second + 16,
+        [192, 168, ..] => octets[1] = 169,
+        _ => {}
+    }
+    octets[3] = octets[3].max(1);
+    octets
+}
+
+const USERNAMES: [&str; 12] = [
+    "root", "admin", "ubuntu", "deploy", "jenkins", "postgres", "alice", "bob", "carol", "dave",
+    "git", "backup",
+];
+
+/// Writes `template`, replacing each placeholder with a fresh draw: `{ip}`,
+/// `{port}`, `{n}` for an id, `{small}` for a count, `{ms}` for a duration and
+/// `{hex}` for a short hex id. `{user}` is the same user throughout.
+fn put_template(line: &mut BytesMut, rng: &mut ChaCha8Rng, template: &str) {
+    let user = USERNAMES[rng.gen_range(0..USERNAMES.len())];
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        line.put_slice(&rest.as_bytes()[..open]);
+        let close = open + rest[open..].find('}').expect("placeholders are closed");
+        match &rest[open + 1..close] {
+            "ip" => put_ip(line, public_ip(rng)),
+            "port" => put_number(line, rng.gen_range(1024..65536)),
+            "user" => line.put_slice(user.as_bytes()),
+            "n" => put_number(line, rng.gen_range(1000..1_000_000)),
+            "small" => put_number(line, rng.gen_range(2..6)),
+            "ms" => put_number(line, rng.gen_range(250..30_000)),
+            "hex" => put_hex(line, rng, 12),
+            other => unreachable!("unknown placeholder {{{}}}", other),
+        }
+        rest = &rest[close + 1..];
+    }
+    line.put_slice(rest.as_bytes());
+}
+
+/// A requested path and the median size of its response body.
+#[derive(Clone, Copy)]
+struct Route {
+    path: &'static str,
+    /// Whether an id follows the path, as in `/products/1234`.
+    id: bool,
+    bytes: f64,
+}
+
+const fn route(path: &'static str, id: bool, bytes: f64) -> Route {
+    Route { path, id, bytes }
+}
+
+const ROUTES: [(Route, u32); 20] = [
+    (route("/", false, 18_000.0), 12),
+    (route("/static/css/main.css", false, 42_000.0), 8),
+    (route("/static/js/app.js", false, 180_000.0), 8),
+    (route("/static/img/logo.svg", false, 3_500.0), 6),
+    (route("/favicon.ico", false, 1_150.0), 5),
+    (route("/products", false, 24_000.0), 7),
+    (route("/products/", true, 21_000.0), 10),
+    (route("/search?q=shoes&page=", true, 26_000.0), 4),
+    (route("/cart", false, 9_000.0), 4),
+    (route("/checkout", false, 11_000.0), 2),
+    (route("/login", false, 6_500.0), 3),
+    (route("/api/v1/products?page=", true, 4_800.0), 7),
+    (route("/api/v1/products/", true, 1_200.0), 6),
+    (route("/api/v1/cart", false, 650.0), 4),
+    (route("/api/v1/orders", false, 900.0), 2),
+    (route("/api/v1/users/", true, 700.0), 3),
+    (route("/health", false, 2.0), 4),
+    (route("/robots.txt", false, 68.0), 1),
+    (route("/wp-login.php", false, 150.0), 1),
+    (route("/.env", false, 150.0), 1),
+];
+const METHODS: [(&str, u32); 6] = [
+    ("GET", 82),
+    ("POST", 11),
+    ("PUT", 2),
+    ("DELETE", 1),
+    ("HEAD", 3),
+    ("OPTIONS", 1),
+];
+const STATUSES: [(u16, u32); 14] = [
+    (200, 780),
+    (304, 70),
+    (301, 12),
+    (302, 25),
+    (201, 10),
+    (204, 8),
+    (206, 3),
+    (404, 55),
+    (400, 8),
+    (401, 9),
+    (403, 5),
+    (500, 8),
+    (502, 3),
+    (503, 4),
+];
+const PROTOCOLS: [(&str, u32); 3] = [("HTTP/1.1", 70), ("HTTP/2.0", 28), ("HTTP/1.0", 2)];
+const REFERRERS: [(&str, u32); 6] = [
+    ("-", 55),
+    ("https://www.example.com/", 15),
+    ("https://www.example.com/products", 10),
+    ("https://www.google.com/", 14),
+    ("https://www.bing.com/", 3),
+    ("https://t.co/", 3),
+];
+const USER_AGENTS: [(&str, u32); 12] = [
+    ("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36", 30),
+    ("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15", 10),
+    ("Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1", 16),
+    ("Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Mobile Safari/537.36", 12),
+    ("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:127.0) Gecko/20100101 Firefox/127.0", 5),
+    ("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0", 6),
+    ("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)", 6),
+    ("Mozilla/5.0 (compatible; bingbot/2.0; +http://www.bing.com/bingbot.htm)", 2),
+    ("Mozilla/5.0 (compatible; AhrefsBot/7.0; +http://ahrefs.com/robot/)", 2),
+    ("curl/8.5.0", 3),
+    ("python-requests/2.32.3", 3),
+    ("kube-probe/1.30", 5),
+];
+/// Clients sending requests, a few of them far busier than the rest.
+const CLIENTS: f64 = 20_000.0;
+
+/// An Apache or Nginx `combined` access log line.
+fn access_line(rng: &mut ChaCha8Rng, time: &Civil, line: &mut BytesMut) {
+    // Each client keeps its address and user agent from line to line.
+    let client = (rng.gen::<f64>().powi(3) * CLIENTS) as u64;
+    let mut client = ChaCha8Rng::seed_from_u64(client);
+    put_ip(line, public_ip(&mut client));
+    line.put_slice(b" - - [");
+    time.put_common_log(line);
+    line.put_slice(b"] \"");
+
+    let method = weighted(rng, &METHODS);
+    let route = weighted(rng, &ROUTES);
+    line.put_slice(method.as_bytes());
+    line.put_u8(b' ');
+    line.put_slice(route.path.as_bytes());
+    if route.id {
+        put_number(line, rng.gen_range(1..5000));
+    }
+    line.put_u8(b' ');
+    line.put_slice(weighted(rng, &PROTOCOLS).as_bytes());
+    line.put_slice(b"\" ");
+
+    let status = match (method, weighted(rng, &STATUSES)) {
+        ("POST", 304 | 206) => 201,
+        (_, status) => status,
+    };
+    put_number(line, status as u64);
+    line.put_u8(b' ');
+    // Bodies vary around the route's usual size; errors send a small page.
+    let bytes = match status {
+        204 | 304 => 0,
+        301 | 302 => rng.gen_range(150..250),
+        400..=599 => rng.gen_range(150..600),
+        _ if method == "HEAD" => 0,
+        _ => (route.bytes * (rng.gen::<f64>() - 0.5).exp()) as u64 + 1,
+    };
+    if bytes == 0 {
+        line.put_u8(b'-');
+    } else {
+        put_number(line, bytes);
+    }
+    line.put_slice(b" \"");
+    line.put_slice(weighted(rng, &REFERRERS).as_bytes());
+    line.put_slice(b"\" \"");
+    line.put_slice(weighted(&mut client, &USER_AGENTS).as_bytes());
+    line.put_u8(b'"');
+}
+
+/// A program writing to syslog, its facility and its weighted messages, each
+/// with its severity.
+#[derive(Clone, Copy)]
+struct Program {
+    name: &'static str,
+    facility: u8,
+    messages: &'static [((&'static str, u8), u32)],
+}
+
+const PROGRAMS: [(Program, u32); 7] = [
+    (
+        Program {
+            name: "sshd",
+            facility: 10,
+            messages: &[
+                (("Accepted publickey for {user} from {ip} port {port} ssh2", 6), 30),
+                (("Failed password for {user} from {ip} port {port} ssh2", 4), 25),
+                (("Invalid user {user} from {ip} port {port}", 4), 15),
+                (("Connection closed by {ip} port {port} [preauth]", 6), 20),
+                (("Disconnected from user {user} {ip} port {port}", 6), 10),
+            ],
+        },
+        25,
+    ),
+    (
+        Program {
+            name: "CRON",
+            facility: 9,
+            messages: &[
+                (("({user}) CMD (run-parts /etc/cron.hourly)", 6), 50),
+                (("({user}) CMD (/usr/local/bin/backup.sh > /dev/null 2>&1)", 6), 30),
+                (("pam_unix(cron:session): session opened for user {user}", 6), 20),
+            ],
+        },
+        15,
+    ),
+    (
+        Program {
+            name: "systemd",
+            facility: 3,
+            messages: &[
+                (("Started Session {n} of User {user}.", 6), 40),
+                (("Starting Daily apt download activities...", 6), 15),
+                (("Reloading.", 6), 15),
+                (("nginx.service: Main process exited, code=exited, status=1/FAILURE", 3), 5),
+                (("Finished Rotate log files.", 6), 25),
+            ],
+        },
+        20,
+    ),
+    (
+        Program {
+            name: "kernel",
+            facility: 0,
+            messages: &[
+                (("[UFW BLOCK] IN=eth0 OUT= SRC={ip} DST=10.0.0.4 PROTO=TCP SPT={port} DPT=22", 4), 60),
+                (("TCP: request_sock_TCP: Possible SYN flooding on port {port}. Sending cookies.", 4), 10),
+                (("Out of memory: Killed process {n} (java) total-vm:{n}kB", 3), 5),
+                (("eth0: Link is Up - 10Gbps Full Duplex", 6), 25),
+            ],
+        },
+        15,
+    ),
+    (
+        Program {
+            name: "nginx",
+            facility: 23,
+            messages: &[
+                (("*{n} connect() failed (111: Connection refused) while connecting to upstream, client: {ip}", 3), 40),
+                (("*{n} upstream timed out (110: Connection timed out) while reading response header from upstream, client: {ip}", 3), 30),
+                (("*{n} an upstream response is buffered to a temporary file", 4), 30),
+            ],
+        },
+        10,
+    ),
+    (
+        Program {
+            name: "postfix/smtpd",
+            facility: 2,
+            messages: &[
+                (("connect from unknown[{ip}]", 6), 40),
+                (("disconnect from unknown[{ip}] ehlo=1 mail=1 rcpt=0/1 quit=1 commands=3/4", 6), 35),
+                (("NOQUEUE: reject: RCPT from unknown[{ip}]: 554 5.7.1 Relay access denied", 5), 25),
+            ],
+        },
+        8,
+    ),
+    (
+        Program {
+            name: "sudo",
+            facility: 10,
+            messages: &[
+                (("{user} : TTY=pts/0 ; PWD=/home/{user} ; USER=root ; COMMAND=/usr/bin/systemctl restart nginx", 5), 70),
+                (("{user} : 3 incorrect password attempts ; TTY=pts/1 ; PWD=/home/{user} ; USER=root ; COMMAND=/bin/bash", 1), 30),
+            ],
+        },
+        7,
+    ),
+];
+const HOSTS: [(&str, u32); 8] = [
+    ("web-01", 20),
+    ("web-02", 20),
+    ("web-03", 15),
+    ("api-01", 15),
+    ("api-02", 10),
+    ("db-01", 8),
+    ("cache-01", 5),
+    ("worker-01", 7),
+];
+
+/// An RFC 5424 syslog line.
+fn syslog_line(rng: &mut ChaCha8Rng, time: &Civil, line: &mut BytesMut) {
+    let host = weighted(rng, &HOSTS);
+    let program = weighted(rng, &PROGRAMS);
+    let (template, severity) = weighted(rng, program.messages);
+    line.put_u8(b'<');
+    put_number(line, (program.facility * 8 + severity) as u64);
+    line.put_slice(b">1 ");
+    time.put_rfc3339(line);
+    line.put_slice(b"Z ");
+    line.put_slice(host.as_bytes());
+    line.put_u8(b' ');
+    line.put_slice(program.name.as_bytes());
+    line.put_u8(b' ');
+    // The kernel has no process id and systemd is always 1. Other programs
+    // keep theirs on each host.
+    match program.name {
+        "kernel" => line.put_u8(b'-'),
+        "systemd" => line.put_u8(b'1'),
+        name => {
+            let pid = host.bytes().chain(name.bytes()).fold(17u64, |pid, byte| {
+                pid.wrapping_mul(31).wrapping_add(byte as u64)
+            });
+            put_number(line, 300 + pid % 60_000);
+        }
+    }
+    line.put_slice(b" - ");
+    if rng.gen_bool(0.2) {
+        line.put_slice(b"[timeQuality tzKnown=\"1\" isSynced=\"1\"] ");
+    } else {
+        line.put_slice(b"- ");
+    }
+    put_template(line, rng, template);
+}
+
+const SERVICES: [(&str, u32); 8] = [
+    ("api-gateway", 25),
+    ("checkout", 12),
+    ("cart", 14),
+    ("payments", 8),
+    ("inventory", 12),
+    ("users", 10),
+    ("search", 14),
+    ("notifications", 5),
+];
+/// Weighted message templates.
+type Messages = &'static [(&'static str, u32)];
+
+/// Application log messages by level, weighted within each level.
+const LEVELS: [((&str, Messages), u32); 4] = [
+    (
+        (
+            "debug",
+            &[
+                ("cache hit for key product:{n}", 40),
+                ("cache miss for key user:{n}", 25),
+                ("executing query on replica db-02", 20),
+                ("loaded feature flags in {small} ms", 15),
+            ],
+        ),
+        8,
+    ),
+    (
+        (
+            "info",
+            &[
+                ("request completed", 50),
+                ("user {n} signed in", 10),
+                ("order {n} created", 10),
+                ("payment {hex} authorized", 8),
+                ("published event to topic orders.v1", 12),
+                ("scheduled job finished", 10),
+            ],
+        ),
+        72,
+    ),
+    (
+        (
+            "warn",
+            &[
+                ("slow query took {ms} ms", 35),
+                ("retrying request to inventory, attempt {small}", 30),
+                ("rate limit at 90% for client {ip}", 20),
+                ("deprecated endpoint /api/v1/cart called", 15),
+            ],
+        ),
+        13,
+    ),
+    (
+        (
+            "error",
+            &[
+                ("payment {hex} declined: insufficient funds", 30),
+                ("upstream timeout after {ms} ms calling inventory", 30),
+                (
+                    "failed to connect to redis at 10.0.3.17:6379: connection refused",
+                    20,
+                ),
+                (
+                    "unhandled exception in OrderService.submit: NullPointerException",
+                    20,
+                ),
+            ],
+        ),
+        7,
+    ),
+];
+/// Log lines in a trace.
+const TRACE_LINES: u64 = 6;
+/// Where trace draws start in their stream.
+const TRACE_WORDS: u128 = 1 << 40;
+
+/// A JSON structured application log line. Neighbouring lines share a
+/// trace, drawn from a stream of its own far past any line's draws.
+fn app_line(rng: &mut ChaCha8Rng, index: u64, time: &Civil, line: &mut BytesMut) -> &'static str {
+    let mut trace = ChaCha8Rng::from_seed(rng.get_seed());
+    trace.set_stream(index / TRACE_LINES);
+    trace.set_word_pos(TRACE_WORDS);
+    let (level, messages) = weighted(rng, &LEVELS);
+    let template = weighted(rng, messages);
+
+    line.put_slice(b"{\"timestamp\":\"");
+    time.put_rfc3339(line);
+    line.put_slice(b"Z\",\"level\":\"");
+    line.put_slice(level.as_bytes());
+    line.put_slice(b"\",\"service\":\"");
+    line.put_slice(weighted(rng, &SERVICES).as_bytes());
+    line.put_slice(b"\",\"trace_id\":\"");
+    put_hex(line, &mut trace, 32);
+    line.put_slice(b"\",\"span_id\":\"");
+    put_hex(line, rng, 16);
+    line.put_slice(b"\",\"message\":\"");
+    put_template(line, rng, template);
+    line.put_u8(b'"');
+    if template == "request completed" {
+        let method = weighted(rng, &METHODS);
+        let route = weighted(rng, &ROUTES);
+        line.put_slice(b",\"http.method\":\"");
+        line.put_slice(method.as_bytes());
+        line.put_slice(b"\",\"http.route\":\"");
+        // Routes are logged as templates, without their query.
+        match route.path.split_once('?') {
+            Some((path, _)) => line.put_slice(path.as_bytes()),
+            None if route.id => {
+                line.put_slice(route.path.as_bytes());
+                line.put_slice(b"{id}");
+            }
+            None => line.put_slice(route.path.as_bytes()),
+        }
+        line.put_slice(b"\",\"http.status_code\":");
+        put_number(line, weighted(rng, &STATUSES) as u64);
+        line.put_slice(b",\"duration_ms\":");
+        // Most requests are quick, a few very slow.
+        put_number(line, (12.0 * (rng.gen::<f64>() * 4.0 - 1.0).exp()) as u64);
+    }
+    line.put_u8(b'}');
+    level
+}
+
+/// A line as the container runtime writes it under `/var/log/containers`:
+/// the time with nanosecond digits, the stream and a full-line tag, then an
+/// application log line or an ingress access log line.
+fn container_line(rng: &mut ChaCha8Rng, index: u64, time: &Civil, line: &mut BytesMut) {
+    time.put_rfc3339(line);
+    // Lines are only timed to the millisecond, and lines sharing one must
+    // not go back in time, so the digits below it are zero.
+    line.put_slice(b"000000");
+    let start = line.len();
+    line.put_slice(b"Z stdout F ");
+    let level = if rng.gen_bool(0.7) {
+        app_line(rng, index, time, line)
+    } else {
+        access_line(rng, time, line);
+        "info"
+    };
+    if level == "error" {
+        line[start + 2..start + 8].copy_from_slice(b"stderr");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(dataset: Dataset) -> Vec<String> {
+        (0..500)
+            .map(|index| {
+                let mut rng = ChaCha8Rng::seed_from_u64(3);
+                rng.set_stream(index);
+                let at = 1_717_243_200_000 + index as i64 * 37;
+                String::from_utf8(draw(dataset, &mut rng, index, at).to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn access_lines_are_combined_log_format() {
+        for line in lines(Dataset::AccessLog) {
+            // Host, ident, user, time, then the quoted request, status, size,
+            // referrer and user agent.
+            let (head, rest) = line.split_once(" [").unwrap();
+            assert_eq!(head.split(' ').count(), 3, "{}", line);
+            assert!(rest.starts_with("01/Jun/2024:12:"), "{}", line);
+            let quoted: Vec<_> = rest.split('"').collect();
+            assert_eq!(quoted.len(), 7, "{}", line);
+            assert_eq!(quoted[1].split(' ').count(), 3, "{}", line);
+            let status_size: Vec<_> = quoted[2].split_whitespace().collect();
+            assert!(status_size[0].parse::<u16>().is_ok(), "{}", line);
+            assert!(status_size[1] == "-" || status_size[1].parse::<u64>().is_ok());
+        }
+    }
+
+    #[test]
+    fn syslog_lines_follow_rfc_5424() {
+        for line in lines(Dataset::Syslog) {
+            let (priority, rest) = line[1..].split_once(">1 ").unwrap();
+            assert!(priority.parse::<u8>().unwrap() < 192, "{}", line);
+            let fields: Vec<_> = rest.splitn(6, ' ').collect();
+            assert!(fields[0].starts_with("2024-06-01T12:") && fields[0].ends_with('Z'));
+            assert!(fields[5].starts_with("- ") || fields[5].starts_with("[timeQuality "));
+        }
+    }
+
+    #[test]
+    fn app_lines_share_traces_and_wrap_for_containers() {
+        let app = lines(Dataset::AppLog);
+        let traces: Vec<String> =
+            app.iter()
+                .map(|line| {
+                    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                    assert!(["debug", "info", "warn", "error"]
+                        .contains(&record["level"].as_str().unwrap()));
+                    record["trace_id"].as_str().unwrap().to_owned()
+                })
+                .collect();
+        assert!(traces.iter().all(|trace| trace.len() == 32));
+        assert_eq!(traces[0], traces[TRACE_LINES as usize - 1]);
+        assert_ne!(traces[0], traces[TRACE_LINES as usize]);
+
+        for line in lines(Dataset::ContainerLog) {
+            let mut parts = line.splitn(4, ' ');
+            let time = parts.next().unwrap();
+            assert_eq!(
+                time.len(),
+                "2024-06-01T12:00:00.000000000Z".len(),
+                "{}",
+                line
+            );
+            assert!(matches!(parts.next(), Some("stdout" | "stderr")));
+            assert_eq!(parts.next(), Some("F"));
+        }
+    }
+
+    #[test]
+    fn container_times_never_go_back() {
+        // Four lines to each millisecond.
+        let times: Vec<String> = (0..500)
+            .map(|index| {
+                let mut rng = ChaCha8Rng::seed_from_u64(3);
+                rng.set_stream(index);
+                let at = 1_717_243_200_000 + index as i64 / 4;
+                let line = draw(Dataset::ContainerLog, &mut rng, index, at);
+                String::from_utf8(line.to_vec()).unwrap()[..30].to_owned()
+            })
+            .collect();
+        assert!(times.is_sorted(), "{:?}", times);
+    }
+
+    #[test]
+    fn public_ips_skip_the_private_ranges() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for _ in 0..100_000 {
+            let ip = std::net::Ipv4Addr::from(public_ip(&mut rng));
+            assert!(
+                !(ip.is_private() || ip.is_loopback() || ip.is_multicast() || ip.is_unspecified()),
+                "{}",
+                ip
+            );
+        }
+    }
+}
@@ -1,136 +1,602 @@
-#![feature(portable_simd)]
-use actix_web::web::Bytes;
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 use actix_web::{web, App, HttpResponse, HttpServer};
-use core::fmt::Error;
+use futures::{stream, StreamExt};
 
 use processing::*;
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha8Rng;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::collections::HashMap;
-use std::sync::mpsc as std_mpsc;
+#[cfg(feature = "progress-tui")]
+use std::io::IsTerminal;
 use std::sync::Arc;
-use tokio::sync::mpsc::channel;
+use std::time::Duration;
 use tokio_stream::wrappers::ReceiverStream;
+use tracing::{info, info_span};
 
-use util::{convert_error, get_size_info, ProgressInfo};
+#[cfg(feature = "tls")]
+use config::TlsSource;
+use config::{BindAddress, ServerConfig};
+use datasets::Dataset;
+use jobs::JobRegistry;
+use metrics::{write_header, METRICS};
+use pipeline::{GenerationRequest, GenerationService, TablesRequest};
+use scheduler::Scheduler;
+use serde::Serialize;
+use shutdown::{wait_for_signal, ShutdownCoordinator};
+#[cfg(feature = "progress-tui")]
+use util::Dashboard;
+use util::{convert_error, SIZE_UNITS};
 
+pub mod archive;
+pub mod config;
+pub mod datasets;
+pub mod gaps;
+pub mod generator;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod jobs;
+pub mod logging;
+pub mod logs;
+pub mod metrics;
+pub mod nesting;
+pub mod numeric;
+pub mod pipeline;
+pub mod pools;
 pub mod processing;
+pub mod raw;
+pub mod relational;
+pub mod scheduler;
+pub mod shutdown;
+pub mod sse;
+pub mod temporal;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod unique;
 pub mod util;
+pub mod ws;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let num_cpus = num_cpus::get();
-    println!("Starting server at http://127.0.0.1:8080");
-    println!("Using {} Cores for generation", num_cpus);
-
-    HttpServer::new(move || App::new().route("/generate", web::get().to(generate_data)))
-        .bind("127.0.0.1:8080")?
-        .workers(num_cpus)
-        .run()
-        .await
+    let config = ServerConfig::load().map_err(std::io::Error::other)?;
+    logging::init(&config.log_level, config.log_format).map_err(std::io::Error::other)?;
+
+    let scheduler = Scheduler::new(
+        config.generation_threads,
+        config.max_concurrent,
+        config.max_queued,
+        Duration::from_secs(config.queue_timeout),
+    )?;
+
+    info!("{}", config);
+    info!(
+        threads_per_generation = scheduler.threads_per_slot(),
+        "generation pools ready"
+    );
+
+    let overrides =
+        pools::load_all(&config.pools).map_err(|e| std::io::Error::other(format!("{:#}", e)))?;
+    let options = pools::PoolOptions {
+        overrides,
+        distributions: config.distributions.clone(),
+    };
+    let pools = Arc::new(PoolCache::new(config.pool_size, options));
+    for locale in &config.locales {
+        pools.warm_up(*locale);
+    }
+
+    let coordinator = Arc::new(ShutdownCoordinator::new());
+
+    let registry = Arc::new(JobRegistry::new());
+    #[cfg(feature = "progress-tui")]
+    if config.dashboard && std::io::stdout().is_terminal() {
+        Dashboard::spawn(registry.clone(), Duration::from_millis(250));
+    }
+
+    let workers = config.workers;
+    let bind = config.bind.clone();
+    let shutdown_grace = Duration::from_secs(config.shutdown_grace);
+    #[cfg(feature = "tls")]
+    let tls = config.tls.clone();
+    #[cfg(feature = "grpc")]
+    let grpc_bind = config.grpc_bind;
+    let config = Arc::new(config);
+    let service = GenerationService::new(
+        config.clone(),
+        scheduler.clone(),
+        registry.clone(),
+        pools.clone(),
+        coordinator.clone(),
+    );
+    #[cfg(feature = "grpc")]
+    let grpc = grpc_bind
+        .map(|addr| actix_web::rt::spawn(grpc::serve(addr, service.clone(), coordinator.clone())));
+    let mut raw = Vec::new();
+    for addr in &config.raw_bind {
+        let listener = raw::RawListener::bind(addr).await?;
+        raw.push(actix_web::rt::spawn(raw::serve(
+            listener,
+            service.clone(),
+            coordinator.clone(),
+        )));
+    }
+    let app_service = web::Data::new(service);
+    let app_config = web::Data::from(config);
+    let app_scheduler = web::Data::from(scheduler);
+    let app_registry = web::Data::from(registry);
+    let app_pools = web::Data::from(pools);
+    let app_coordinator = web::Data::from(coordinator.clone());
+
+    let mut server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_config.clone())
+            .app_data(app_scheduler.clone())
+            .app_data(app_registry.clone())
+            .app_data(app_pools.clone())
+            .app_data(app_coordinator.clone())
+            .app_data(app_service.clone())
+            .route("/generate", web::get().to(generate_data))
+            .route("/tables", web::get().to(generate_tables))
+            .route("/ws/generate", web::get().to(ws::ws_generate))
+            .route("/sse/generate", web::get().to(sse::sse_generate))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .route("/capabilities", web::get().to(capabilities))
+            .route("/formats", web::get().to(capabilities))
+            .route("/jobs", web::get().to(list_jobs))
+            .route("/jobs/{id}", web::get().to(get_job))
+            .route("/metrics", web::get().to(metrics_endpoint))
+    })
+    .workers(workers)
+    .disable_signals()
+    .shutdown_timeout(10);
+
+    #[cfg(feature = "tls")]
+    let tls_config = match &tls {
+        Some(TlsSource::Files { cert, key }) => {
+            Some(tls::load_server_config(cert, key).map_err(std::io::Error::other)?)
+        }
+        Some(TlsSource::SelfSigned) => {
+            Some(tls::self_signed_config().map_err(std::io::Error::other)?.0)
+        }
+        None => None,
+    };
+
+    for addr in bind {
+        server = match addr {
+            #[cfg(feature = "tls")]
+            BindAddress::Tcp(addr) => match &tls_config {
+                Some(tls_config) => server.bind_rustls_0_23(addr, tls_config.clone())?,
+                None => server.bind_auto_h2c(addr)?,
+            },
+            #[cfg(not(feature = "tls"))]
+            BindAddress::Tcp(addr) => server.bind(addr)?,
+            #[cfg(unix)]
+            BindAddress::Unix(path) => server.bind_uds(path)?,
+        };
+    }
+
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        wait_for_signal().await;
+        coordinator.shutdown(handle, shutdown_grace).await;
+    });
+
+    let result = server.await;
+    // Give gRPC and raw socket streams the same chance to finish cleanly.
+    let _ = tokio::time::timeout(Duration::from_secs(10), async {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = grpc {
+            let _ = grpc.await;
+        }
+        for raw in raw {
+            let _ = raw.await;
+        }
+    })
+    .await;
+    result
+}
+
+async fn metrics_endpoint(scheduler: web::Data<Scheduler>) -> HttpResponse {
+    let mut body = METRICS.render();
+
+    write_header(
+        &mut body,
+        "fjg_generations_active",
+        "gauge",
+        "Generation slots in use.",
+    );
+    body.push_str(&format!("fjg_generations_active {}\n", scheduler.active()));
+    write_header(
+        &mut body,
+        "fjg_generations_queued",
+        "gauge",
+        "Requests waiting for a generation slot.",
+    );
+    body.push_str(&format!("fjg_generations_queued {}\n", scheduler.queued()));
+
+    HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/plain; version=0.0.4"))
+        .body(body)
+}
+
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().body("ok")
+}
+
+async fn readyz(
+    config: web::Data<ServerConfig>,
+    pools: web::Data<PoolCache>,
+    coordinator: web::Data<ShutdownCoordinator>,
+) -> HttpResponse {
+    if coordinator.is_shutting_down() {
+        HttpResponse::ServiceUnavailable().body("shutting down")
+    } else if config.locales.iter().all(|locale| pools.is_ready(*locale)) {
+        HttpResponse::Ok().body("ready")
+    } else {
+        HttpResponse::ServiceUnavailable().body("warming up data pools")
+    }
 }
 
+#[derive(Serialize)]
+struct FormatInfo {
+    name: String,
+    content_type: &'static str,
+}
+
+#[derive(Serialize)]
+struct ParameterInfo {
+    name: &'static str,
+    required: bool,
+    description: &'static str,
+}
+
+#[derive(Serialize)]
+struct Capabilities {
+    formats: Vec<FormatInfo>,
+    default_format: String,
+    locales: Vec<&'static str>,
+    default_locale: &'static str,
+    datasets: Vec<&'static str>,
+    size_units: Vec<&'static str>,
+    max_request_size: u64,
+    parameters: Vec<ParameterInfo>,
+}
+
+const PARAMETERS: [ParameterInfo; 23] = [
+    ParameterInfo {
+        name: "size",
+        required: true,
+        description: "Target size of the response, a number followed by a size unit",
+    },
+    ParameterInfo {
+        name: "format",
+        required: false,
+        description: "Output format, one of `formats`",
+    },
+    ParameterInfo {
+        name: "pretty",
+        required: false,
+        description: "Pretty-print JSON output when `true`",
+    },
+    ParameterInfo {
+        name: "dataset",
+        required: false,
+        description: "Kind of record to generate, one of `datasets`",
+    },
+    ParameterInfo {
+        name: "locale",
+        required: false,
+        description: "Locale of the generated names and places, one of `locales`",
+    },
+    ParameterInfo {
+        name: "pool.<field>",
+        required: false,
+        description: "Pool file in the server's pool directory to draw the field from, \
+                      `file` or `file#column`",
+    },
+    ParameterInfo {
+        name: "dist.<field>",
+        required: false,
+        description: "How often each pooled value comes up: `uniform`, `weighted`, \
+                      `zipf[:exponent]` or `normal[:mean[:std_dev]]`. For `revenue` and \
+                      `employees`, how the number is drawn",
+    },
+    ParameterInfo {
+        name: "revenue_type",
+        required: false,
+        description: "How revenue is written: `f32`, `f64`, `decimal[:places]` or `cents`",
+    },
+    ParameterInfo {
+        name: "correlation",
+        required: false,
+        description: "Correlation between employees and revenue, from -1 to 1",
+    },
+    ParameterInfo {
+        name: "null.<field>",
+        required: false,
+        description: "Fraction of records where the field is null, `*` for every field",
+    },
+    ParameterInfo {
+        name: "missing.<field>",
+        required: false,
+        description: "Fraction of records where the field is left out, `*` for every field",
+    },
+    ParameterInfo {
+        name: "empty.<field>",
+        required: false,
+        description: "Fraction of records where the field is an empty string, `*` for every field",
+    },
+    ParameterInfo {
+        name: "nest",
+        required: false,
+        description: "Nested structures to add: `address`, `contacts`, `tags`",
+    },
+    ParameterInfo {
+        name: "contacts",
+        required: false,
+        description: "Length of the `contacts` array, `n` or `min:max`",
+    },
+    ParameterInfo {
+        name: "tags",
+        required: false,
+        description: "Length of the `tags` array, `n` or `min:max`",
+    },
+    ParameterInfo {
+        name: "depth",
+        required: false,
+        description: "How much of each contact is written, from 1 to 3",
+    },
+    ParameterInfo {
+        name: "flatten",
+        required: false,
+        description: "How nested fields become CSV columns: `dotted` or `json`",
+    },
+    ParameterInfo {
+        name: "time",
+        required: false,
+        description: "Time fields to add: `date`, `datetime`, `timestamp`",
+    },
+    ParameterInfo {
+        name: "range.<field>",
+        required: false,
+        description: "Range of a time field, `start..end`",
+    },
+    ParameterInfo {
+        name: "time_format.<field>",
+        required: false,
+        description: "How a time field is written: `rfc3339`, `epoch`, `epoch_ms` or a \
+                      `strftime` pattern",
+    },
+    ParameterInfo {
+        name: "series",
+        required: false,
+        description: "Records per second of an ordered time series on `timestamp`",
+    },
+    ParameterInfo {
+        name: "jitter",
+        required: false,
+        description: "Random spread of the series timestamps, from 0 to 1",
+    },
+    ParameterInfo {
+        name: "unique",
+        required: false,
+        description: "Pooled fields whose values never repeat, e.g. `name,email`",
+    },
+];
+
+async fn capabilities(config: web::Data<ServerConfig>) -> HttpResponse {
+    HttpResponse::Ok().json(Capabilities {
+        formats: OutputFormat::ALL
+            .iter()
+            .map(|format| FormatInfo {
+                name: format.to_string().to_lowercase(),
+                content_type: format.content_type(),
+            })
+            .collect(),
+        default_format: config.default_format.to_string().to_lowercase(),
+        locales: Locale::ALL.iter().map(Locale::as_str).collect(),
+        default_locale: config.default_locale().as_str(),
+        datasets: Dataset::ALL.iter().map(Dataset::as_str).collect(),
+        size_units: SIZE_UNITS.iter().map(|(unit, _)| *unit).collect(),
+        max_request_size: config.max_request_size,
+        parameters: PARAMETERS.into_iter().collect(),
+    })
+}
+
+async fn list_jobs(registry: web::Data<JobRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(registry.statuses())
+}
+
+async fn get_job(registry: web::Data<JobRegistry>, path: web::Path<u64>) -> HttpResponse {
+    match registry.get(path.into_inner()) {
+        Some(progress) => HttpResponse::Ok().json(progress.status()),
+        None => HttpResponse::NotFound().body("Unknown job id"),
+    }
+}
+
+#[tracing::instrument(
+    name = "generate",
+    skip_all,
+    fields(job_id = tracing::field::Empty, format = tracing::field::Empty, size = tracing::field::Empty)
+)]
 async fn generate_data(
+    service: web::Data<GenerationService>,
     web::Query(params): web::Query<HashMap<String, String>>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    const CHUNK_SIZE: u64 = 256 * 1024 * 1024;
+    let request = {
+        let _parse = info_span!("parse_params").entered();
+        GenerationRequest::from_query(&params, service.config()).map_err(convert_error)?
+    };
 
-    let (tx, rx) = channel::<Result<Bytes, Error>>(16);
-    let sender = tx.clone();
-    let stream = ReceiverStream::new(rx);
+    let content_type = request.content_type();
+    let generation = service.start(request).await?;
 
-    let stream_content_type = OutputFormat::from_str(params.get("format").map_or("json", |s| s));
-    let pretty_print = params.get("pretty").map_or(false, |v| v == "true");
+    // Split into frames so h2 DATA frames and flow-control windows see a
+    // tunable unit instead of whole chunks.
+    let frame_size = service
+        .config()
+        .frame_size
+        .map_or(usize::MAX, |size| size as usize);
+    let body = ReceiverStream::new(generation.body).flat_map(move |mut chunk| {
+        stream::iter(std::iter::from_fn(move || {
+            (!chunk.is_empty())
+                .then(|| Ok::<_, actix_web::Error>(chunk.split_to(frame_size.min(chunk.len()))))
+        }))
+    });
 
-    let size_info = get_size_info(params.get("size")).map_err(convert_error)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", content_type))
+        .insert_header(("X-Request-Id", generation.job_id.to_string()))
+        .streaming(body))
+}
 
-    let num_threads = num_cpus::get();
-    let chunk_size = size_info.total_size / (num_threads as u64);
-    let num_chunks = (size_info.total_size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+#[tracing::instrument(
+    name = "tables",
+    skip_all,
+    fields(job_id = tracing::field::Empty, format = tracing::field::Empty)
+)]
+async fn generate_tables(
+    service: web::Data<GenerationService>,
+    web::Query(params): web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request = {
+        let _parse = info_span!("parse_params").entered();
+        TablesRequest::from_query(&params, service.config()).map_err(convert_error)?
+    };
+    let format = request.tables.format;
 
-    let progress = Arc::new(ProgressInfo::new(
-        size_info.total_size,
-        size_info.multiplier,
-        size_info.unit,
-    ));
+    let generation = service.start_tables(request).await?;
+    let body = ReceiverStream::new(generation.body).map(Ok::<_, actix_web::Error>);
 
-    if stream_content_type == OutputFormat::CSV {
-        let header = b"id,name,industry,revenue,employees,city,state,country\n";
-        progress.update_streamed(header.len());
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", format.content_type()))
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", format.file_name()),
+        ))
+        .insert_header(("X-Request-Id", generation.job_id.to_string()))
+        .streaming(body))
+}
 
-        tx.send(Ok(Bytes::from(header.to_vec()))).await.ok();
-    } else {
-        tx.send(Ok(Bytes::from(b"[ ".to_vec()))).await.ok();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn healthz_always_answers_ok() {
+        let app = test::init_service(App::new().route("/healthz", web::get().to(healthz))).await;
+        let request = test::TestRequest::get().uri("/healthz").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "ok");
     }
 
-    progress.print_header(stream_content_type);
+    #[actix_web::test]
+    async fn readyz_waits_for_the_pools_and_fails_once_draining() {
+        let config = ServerConfig::default();
+        let locale = config.default_locale();
+        let pools = Arc::new(PoolCache::new(100, pools::PoolOptions::default()));
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::from(pools.clone()))
+                .app_data(web::Data::from(coordinator.clone()))
+                .route("/readyz", web::get().to(readyz)),
+        )
+        .await;
+        let readyz = || async {
+            let request = test::TestRequest::get().uri("/readyz").to_request();
+            let response = test::call_service(&app, request).await;
+            (response.status(), test::read_body(response).await)
+        };
 
-    tokio::spawn(async move {
-        let seed: u64 = rand::thread_rng().gen();
+        assert_eq!(
+            readyz().await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "warming up data pools".into()
+            )
+        );
+        pools.warm_up(locale);
+        pools.until_ready(locale).await;
+        assert_eq!(readyz().await, (StatusCode::OK, "ready".into()));
 
-        let other_prog = progress.clone();
+        coordinator.begin_shutdown();
+        assert_eq!(
+            readyz().await,
+            (StatusCode::SERVICE_UNAVAILABLE, "shutting down".into())
+        );
+    }
 
-        let (chunk_tx, chunk_rx) = std_mpsc::sync_channel(0);
+    #[actix_web::test]
+    async fn capabilities_describe_what_generate_accepts() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ServerConfig::default()))
+                .route("/capabilities", web::get().to(capabilities))
+                .route("/formats", web::get().to(capabilities)),
+        )
+        .await;
+        let request = test::TestRequest::get().uri("/capabilities").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(response).await;
 
-        std::thread::spawn(move || {
-            let data_pools = DataPools::new();
-            let mut initial_generator = StreamGenerator::new(
-                ChaCha8Rng::seed_from_u64(seed),
-                &data_pools,
-                pretty_print,
-                stream_content_type,
-                chunk_size,
-            );
+        let formats: Vec<_> = body["formats"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|format| {
+                assert!(format["content_type"].is_string());
+                format["name"].as_str().unwrap()
+            })
+            .collect();
+        assert_eq!(formats, ["json", "csv", "protobuf"]);
+        assert_eq!(body["default_format"], "json");
+        assert_eq!(body["default_locale"], "en");
+        assert_eq!(body["locales"].as_array().unwrap().len(), Locale::ALL.len());
+        assert_eq!(body["datasets"][0], "business");
+        assert_eq!(
+            body["datasets"].as_array().unwrap().len(),
+            Dataset::ALL.len()
+        );
+        assert_eq!(
+            body["size_units"],
+            serde_json::json!(["b", "kb", "mb", "gb", "tb"])
+        );
+        assert_eq!(body["max_request_size"], 1024_u64.pow(4));
 
-            if let Some(chunk) = initial_generator.generate_kickoff_chunk() {
-                other_prog.update(chunk.len());
-                other_prog.print_progress();
-                chunk_tx.send(chunk).ok();
-            }
-
-            let chunks: Vec<_> = (0..num_chunks).collect();
-            chunks.into_par_iter().for_each(|i| {
-                let chunk_rng = ChaCha8Rng::seed_from_u64(seed.wrapping_add(i as u64));
-                let current_chunk_size = if i == num_chunks - 1 {
-                    size_info.total_size - (i * CHUNK_SIZE)
-                } else {
-                    CHUNK_SIZE
-                };
-                let mut generator = StreamGenerator::new(
-                    chunk_rng,
-                    &data_pools,
-                    pretty_print,
-                    stream_content_type,
-                    current_chunk_size,
-                );
-
-                while let Some(chunk) = generator.generate_chunk() {
-                    other_prog.update(chunk.len());
-                    other_prog.print_progress();
-
-                    if chunk_tx.send(chunk).is_err() {
-                        break;
-                    }
-                }
-            });
-        });
-
-        for chunk in chunk_rx {
-            progress.update_streamed(chunk.len());
-            progress.print_progress();
-
-            if sender.send(Ok(Bytes::from(chunk))).await.is_err() {
-                break;
-            }
+        let parameters = body["parameters"].as_array().unwrap();
+        for parameter in parameters {
+            assert!(parameter["required"].is_boolean());
+            assert!(!parameter["description"].as_str().unwrap().is_empty());
         }
-        if stream_content_type == OutputFormat::JSON {
-            tx.send(Ok(Bytes::from(b"  ]".to_vec()))).await.ok();
+        let required: Vec<_> = parameters
+            .iter()
+            .filter(|parameter| parameter["required"] == true)
+            .map(|parameter| &parameter["name"])
+            .collect();
+        assert_eq!(required, ["size"]);
+        for name in [
+            "dataset",
+            "pool.<field>",
+            "null.<field>",
+            "nest",
+            "series",
+            "unique",
+        ] {
+            assert!(
+                parameters.iter().any(|parameter| parameter["name"] == name),
+                "{}",
+                name
+            );
         }
-        progress.print_progress();
-    });
 
-    Ok(HttpResponse::Ok()
-        .insert_header(("Content-Type", stream_content_type.content_type()))
-        .streaming(stream))
+        let request = test::TestRequest::get().uri("/formats").to_request();
+        let alias: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(alias, body);
+    }
 }
@@ -0,0 +1,275 @@
+use crate::processing::OutputFormat;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use std::fmt::Write;
+use std::time::Duration;
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+/// Upper bounds of the generation throughput histogram, in bytes per second.
+const THROUGHPUT_BUCKETS: [f64; 10] = [1e6, 5e6, 10e6, 50e6, 100e6, 250e6, 500e6, 1e9, 2.5e9, 5e9];
+
+#[derive(Default)]
+pub struct FormatMetrics {
+    pub requests: AtomicU64,
+    pub bytes_generated: AtomicU64,
+    pub bytes_streamed: AtomicU64,
+    pub records: AtomicU64,
+}
+
+pub struct Histogram {
+    buckets: [AtomicU64; THROUGHPUT_BUCKETS.len()],
+    count: AtomicU64,
+    sum_bits: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_bits: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        for (bucket, bound) in self.buckets.iter().zip(THROUGHPUT_BUCKETS) {
+            if value <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            })
+            .ok();
+    }
+}
+
+/// Process-wide counters exported on `/metrics`.
+///
+/// `ProgressInfo` feeds the per-format byte and record counters as it updates
+/// its own, so the two never disagree.
+pub struct Metrics {
+    json: FormatMetrics,
+    csv: FormatMetrics,
+    protobuf: FormatMetrics,
+    pub active_streams: AtomicU64,
+    pub client_aborts: AtomicU64,
+    backpressure_nanos: AtomicU64,
+    pub throughput: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            json: FormatMetrics::default(),
+            csv: FormatMetrics::default(),
+            protobuf: FormatMetrics::default(),
+            active_streams: AtomicU64::new(0),
+            client_aborts: AtomicU64::new(0),
+            backpressure_nanos: AtomicU64::new(0),
+            throughput: Histogram::new(),
+        }
+    }
+
+    pub fn format(&self, format: OutputFormat) -> &FormatMetrics {
+        match format {
+            OutputFormat::JSON => &self.json,
+            OutputFormat::CSV => &self.csv,
+            OutputFormat::PROTOBUF => &self.protobuf,
+        }
+    }
+
+    pub fn add_backpressure(&self, waited: Duration) {
+        self.backpressure_nanos
+            .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Marks a stream as active until the returned guard is dropped.
+    pub fn stream_started(&self, format: OutputFormat) -> ActiveStream<'_> {
+        self.format(format).requests.fetch_add(1, Ordering::Relaxed);
+        self.active_streams.fetch_add(1, Ordering::Relaxed);
+        ActiveStream { metrics: self }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(4096);
+
+        let counters: [(&str, &str, FormatField); 4] = [
+            ("fjg_requests_total", "Generation requests started.", |m| {
+                &m.requests
+            }),
+            (
+                "fjg_bytes_generated_total",
+                "Bytes produced by the generators.",
+                |m| &m.bytes_generated,
+            ),
+            (
+                "fjg_bytes_streamed_total",
+                "Bytes handed to the response stream.",
+                |m| &m.bytes_streamed,
+            ),
+            (
+                "fjg_records_total",
+                "Records produced by the generators.",
+                |m| &m.records,
+            ),
+        ];
+
+        for (name, help, field) in counters {
+            write_header(&mut out, name, "counter", help);
+            for format in OutputFormat::ALL {
+                let value = field(self.format(format)).load(Ordering::Relaxed);
+                let label = format.to_string().to_lowercase();
+                let _ = writeln!(out, "{}{{format=\"{}\"}} {}", name, label, value);
+            }
+        }
+
+        write_header(
+            &mut out,
+            "fjg_active_streams",
+            "gauge",
+            "Streams currently in flight.",
+        );
+        let _ = writeln!(
+            out,
+            "fjg_active_streams {}",
+            self.active_streams.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut out,
+            "fjg_client_aborts_total",
+            "counter",
+            "Streams ended early because the client went away.",
+        );
+        let _ = writeln!(
+            out,
+            "fjg_client_aborts_total {}",
+            self.client_aborts.load(Ordering::Relaxed)
+        );
+
+        write_header(
+            &mut out,
+            "fjg_channel_backpressure_seconds_total",
+            "counter",
+            "Time spent waiting on a full response channel.",
+        );
+        let _ = writeln!(
+            out,
+            "fjg_channel_backpressure_seconds_total {}",
+            self.backpressure_nanos.load(Ordering::Relaxed) as f64 / 1e9
+        );
+
+        let name = "fjg_generation_throughput_bytes_per_second";
+        write_header(
+            &mut out,
+            name,
+            "histogram",
+            "Per-request generation throughput.",
+        );
+        for (bucket, bound) in self.throughput.buckets.iter().zip(THROUGHPUT_BUCKETS) {
+            let value = bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, value);
+        }
+        let count = self.throughput.count.load(Ordering::Relaxed);
+        let sum = f64::from_bits(self.throughput.sum_bits.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+
+        out
+    }
+}
+
+type FormatField = fn(&FormatMetrics) -> &AtomicU64;
+
+pub fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub struct ActiveStream<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Drop for ActiveStream<'_> {
+    fn drop(&mut self) {
+        self.metrics.active_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(out: &str) -> Vec<&str> {
+        out.lines().filter(|line| !line.starts_with('#')).collect()
+    }
+
+    #[test]
+    fn counters_are_labelled_by_format() {
+        let metrics = Metrics::new();
+        metrics
+            .format(OutputFormat::CSV)
+            .bytes_generated
+            .fetch_add(42, Ordering::Relaxed);
+        metrics.client_aborts.fetch_add(3, Ordering::Relaxed);
+        metrics.add_backpressure(Duration::from_millis(1500));
+
+        let out = metrics.render();
+        let lines = lines(&out);
+        assert!(lines.contains(&"fjg_bytes_generated_total{format=\"csv\"} 42"));
+        assert!(lines.contains(&"fjg_bytes_generated_total{format=\"json\"} 0"));
+        assert!(lines.contains(&"fjg_bytes_generated_total{format=\"protobuf\"} 0"));
+        assert!(lines.contains(&"fjg_client_aborts_total 3"));
+        assert!(lines.contains(&"fjg_channel_backpressure_seconds_total 1.5"));
+        assert!(out.contains("# HELP fjg_records_total Records produced by the generators.\n# TYPE fjg_records_total counter\n"));
+    }
+
+    #[test]
+    fn active_streams_count_until_dropped() {
+        let metrics = Metrics::new();
+        let stream = metrics.stream_started(OutputFormat::JSON);
+        assert!(lines(&metrics.render()).contains(&"fjg_active_streams 1"));
+        assert!(lines(&metrics.render()).contains(&"fjg_requests_total{format=\"json\"} 1"));
+        drop(stream);
+        assert!(lines(&metrics.render()).contains(&"fjg_active_streams 0"));
+    }
+
+    #[test]
+    fn throughput_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+        for value in [2e6, 75e6, 1e10] {
+            metrics.throughput.observe(value);
+        }
+
+        let out = metrics.render();
+        let name = "fjg_generation_throughput_bytes_per_second";
+        let lines = lines(&out);
+        for (bound, count) in [
+            ("1000000", 0),
+            ("5000000", 1),
+            ("100000000", 2),
+            ("5000000000", 2),
+            ("+Inf", 3),
+        ] {
+            let line = format!("{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+            assert!(lines.contains(&line.as_str()), "{}", line);
+        }
+        assert!(lines.contains(&format!("{}_count 3", name).as_str()));
+        assert!(lines.contains(&format!("{}_sum 10077000000", name).as_str()));
+        assert!(out.contains(&format!("# TYPE {} histogram\n", name)));
+    }
+}
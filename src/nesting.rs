@@ -0,0 +1,365 @@
+use crate::generator::CSV_HEADER;
+use bytes::Bytes;
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// Deepest a record may nest, counting the record's own fields as depth 0.
+pub const MAX_DEPTH: u8 = 3;
+/// Longest a `contacts` or `tags` array may be.
+pub const MAX_ARRAY_LENGTH: u8 = 16;
+
+/// A nested structure that can be added to each record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Nested {
+    /// Groups `city`, `state` and `country` under an `address` object.
+    Address,
+    /// A `contacts` array of people.
+    Contacts,
+    /// A `tags` array of words.
+    Tags,
+}
+
+impl Nested {
+    pub const ALL: [Nested; 3] = [Nested::Address, Nested::Contacts, Nested::Tags];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Nested::Address => "address",
+            Nested::Contacts => "contacts",
+            Nested::Tags => "tags",
+        }
+    }
+}
+
+impl FromStr for Nested {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        Nested::ALL
+            .into_iter()
+            .find(|nested| nested.as_str() == name)
+            .ok_or_else(|| format!("Unknown nested structure '{}'", s))
+    }
+}
+
+impl fmt::Display for Nested {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How many entries an array gets, drawn uniformly from `min..=max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lengths {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl Lengths {
+    pub const DEFAULT_CONTACTS: Lengths = Lengths { min: 1, max: 3 };
+    pub const DEFAULT_TAGS: Lengths = Lengths { min: 0, max: 4 };
+}
+
+impl FromStr for Lengths {
+    type Err = String;
+
+    /// Reads `length` or `min:max`, e.g. `2` or `0:5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let length = |part: &str| {
+            part.trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|length| *length <= MAX_ARRAY_LENGTH)
+                .ok_or_else(|| {
+                    format!(
+                        "Array lengths must be 0 to {}, got '{}'",
+                        MAX_ARRAY_LENGTH, part
+                    )
+                })
+        };
+        let (min, max) = match s.split_once(':') {
+            Some((min, max)) => (length(min)?, length(max)?),
+            None => (length(s)?, length(s)?),
+        };
+        if min > max {
+            return Err(format!("Minimum length is above the maximum in '{}'", s));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for Lengths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}:{}", self.min, self.max)
+        }
+    }
+}
+
+/// How CSV, which has no nesting, writes nested structures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flatten {
+    /// One column per leaf, named by its path, e.g. `contacts.0.email`.
+    /// Arrays get a column for every entry up to their longest length.
+    #[default]
+    Dotted,
+    /// One column per nested structure, holding it as compact JSON.
+    Json,
+}
+
+impl FromStr for Flatten {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dotted" => Ok(Flatten::Dotted),
+            "json" => Ok(Flatten::Json),
+            _ => Err(format!(
+                "Unknown flattening '{}', expected dotted or json",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Flatten {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Flatten::Dotted => write!(f, "dotted"),
+            Flatten::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Which nested structures records carry, and how they are laid out. Flat
+/// by default, which is the record every format has always written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NestingOptions {
+    pub address: bool,
+    /// Lengths of the `contacts` array, if records have one.
+    pub contacts: Option<Lengths>,
+    /// Lengths of the `tags` array, if records have one.
+    pub tags: Option<Lengths>,
+    /// How deep contacts go: `1` makes them email strings, `2` objects with
+    /// a `name` and `email`, and `3` also gives each an `address` object.
+    pub depth: u8,
+    pub flatten: Flatten,
+}
+
+impl Default for NestingOptions {
+    fn default() -> Self {
+        Self {
+            address: false,
+            contacts: None,
+            tags: None,
+            depth: 2,
+            flatten: Flatten::Dotted,
+        }
+    }
+}
+
+impl NestingOptions {
+    pub fn is_nested(&self) -> bool {
+        self.address || self.contacts.is_some() || self.tags.is_some()
+    }
+
+    /// Adds `nested` with its default lengths, keeping any already set.
+    pub fn enable(&mut self, nested: Nested) {
+        match nested {
+            Nested::Address => self.address = true,
+            Nested::Contacts => {
+                self.contacts.get_or_insert(Lengths::DEFAULT_CONTACTS);
+            }
+            Nested::Tags => {
+                self.tags.get_or_insert(Lengths::DEFAULT_TAGS);
+            }
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: u8) -> Result<(), String> {
+        if !(1..=MAX_DEPTH).contains(&depth) {
+            return Err(format!("depth must be 1 to {}, got {}", MAX_DEPTH, depth));
+        }
+        self.depth = depth;
+        Ok(())
+    }
+
+    /// Draws the array entries of one record. Records without arrays use no
+    /// randomness, so flat records come out as they always have.
+    pub(crate) fn draw<R: Rng>(&self, rng: &mut R) -> NestedDraws {
+        let mut draws = NestedDraws::default();
+        if let Some(lengths) = self.contacts {
+            let len = rng.gen_range(lengths.min..=lengths.max);
+            draws.contacts = (0..len).map(|_| rng.gen()).collect();
+        }
+        if let Some(lengths) = self.tags {
+            let len = rng.gen_range(lengths.min..=lengths.max);
+            draws.tags = (0..len).map(|_| rng.gen()).collect();
+        }
+        draws
+    }
+
+    /// The CSV header row for records nested this way.
+    pub fn csv_header(&self) -> Bytes {
+        if !self.is_nested() {
+            return Bytes::from_static(CSV_HEADER);
+        }
+
+        let mut columns: Vec<String> = ["id", "name", "industry", "revenue", "employees"]
+            .map(str::to_owned)
+            .into();
+        let place = ["city", "state", "country"];
+        match (self.address, self.flatten) {
+            (false, _) => columns.extend(place.map(str::to_owned)),
+            (true, Flatten::Dotted) => {
+                columns.extend(place.map(|field| format!("address.{}", field)))
+            }
+            (true, Flatten::Json) => columns.push("address".to_owned()),
+        }
+        if let Some(lengths) = self.contacts {
+            match self.flatten {
+                Flatten::Dotted => {
+                    let fields: &[&str] = match self.depth {
+                        1 => &[""],
+                        2 => &[".name", ".email"],
+                        _ => &[".name", ".email", ".address.city", ".address.country"],
+                    };
+                    for i in 0..lengths.max {
+                        columns.extend(
+                            fields
+                                .iter()
+                                .map(|field| format!("contacts.{}{}", i, field)),
+                        );
+                    }
+                }
+                Flatten::Json => columns.push("contacts".to_owned()),
+            }
+        }
+        if let Some(lengths) = self.tags {
+            match self.flatten {
+                Flatten::Dotted => columns.extend((0..lengths.max).map(|i| format!("tags.{}", i))),
+                Flatten::Json => columns.push("tags".to_owned()),
+            }
+        }
+
+        let mut header = columns.join(",");
+        header.push('\n');
+        Bytes::from(header)
+    }
+}
+
+impl fmt::Display for NestingOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_nested() {
+            return f.write_str("flat");
+        }
+        let mut parts = Vec::new();
+        if self.address {
+            parts.push("address".to_owned());
+        }
+        if let Some(lengths) = self.contacts {
+            parts.push(format!("contacts={}", lengths));
+        }
+        if let Some(lengths) = self.tags {
+            parts.push(format!("tags={}", lengths));
+        }
+        write!(
+            f,
+            "{}, depth {}, {} CSV",
+            parts.join(", "),
+            self.depth,
+            self.flatten
+        )
+    }
+}
+
+/// The random draws behind one record's arrays: four pool draws per contact,
+/// for its name, email, city and country, and one per tag.
+#[derive(Debug, Default)]
+pub(crate) struct NestedDraws {
+    pub contacts: Vec<[u32; 4]>,
+    pub tags: Vec<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn lengths_parse_single_values_and_ranges() {
+        assert_eq!("2".parse(), Ok(Lengths { min: 2, max: 2 }));
+        assert_eq!("0:5".parse(), Ok(Lengths { min: 0, max: 5 }));
+        for spec in ["5:1", "0:17", "-1", "x", "1:2:3"] {
+            assert!(spec.parse::<Lengths>().is_err(), "{}", spec);
+        }
+        assert_eq!(Lengths { min: 1, max: 3 }.to_string(), "1:3");
+    }
+
+    #[test]
+    fn draws_stay_within_the_lengths() {
+        let mut nesting = NestingOptions {
+            contacts: Some(Lengths { min: 1, max: 3 }),
+            ..Default::default()
+        };
+        nesting.enable(Nested::Tags);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut seen = [false; 4];
+        for _ in 0..1000 {
+            let draws = nesting.draw(&mut rng);
+            assert!((1..=3).contains(&draws.contacts.len()));
+            assert!(draws.tags.len() <= 4);
+            seen[draws.contacts.len()] = true;
+        }
+        assert_eq!(seen, [false, true, true, true]);
+    }
+
+    #[test]
+    fn flat_records_draw_no_randomness() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut untouched = rng.clone();
+        let address_only = NestingOptions {
+            address: true,
+            ..Default::default()
+        };
+        for nesting in [NestingOptions::default(), address_only] {
+            let draws = nesting.draw(&mut rng);
+            assert!(draws.contacts.is_empty() && draws.tags.is_empty());
+        }
+        assert_eq!(rng.gen::<u64>(), untouched.gen::<u64>());
+    }
+
+    #[test]
+    fn csv_headers_flatten_every_leaf() {
+        assert_eq!(NestingOptions::default().csv_header(), CSV_HEADER);
+
+        let mut nesting = NestingOptions {
+            address: true,
+            contacts: Some(Lengths { min: 0, max: 2 }),
+            tags: Some(Lengths { min: 1, max: 1 }),
+            ..Default::default()
+        };
+        assert_eq!(
+            nesting.csv_header(),
+            "id,name,industry,revenue,employees,address.city,address.state,address.country,\
+             contacts.0.name,contacts.0.email,contacts.1.name,contacts.1.email,tags.0\n"
+        );
+        nesting.depth = 1;
+        nesting.address = false;
+        assert_eq!(
+            nesting.csv_header(),
+            "id,name,industry,revenue,employees,city,state,country,contacts.0,contacts.1,tags.0\n"
+        );
+        nesting.flatten = Flatten::Json;
+        nesting.address = true;
+        assert_eq!(
+            nesting.csv_header(),
+            "id,name,industry,revenue,employees,address,contacts,tags\n"
+        );
+    }
+}
@@ -0,0 +1,628 @@
+use rand::Rng;
+use std::f64::consts::{FRAC_1_SQRT_2, TAU};
+use std::fmt;
+use std::str::FromStr;
+
+/// A record field holding a generated number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NumericField {
+    Revenue,
+    Employees,
+}
+
+impl NumericField {
+    pub const ALL: [NumericField; 2] = [NumericField::Revenue, NumericField::Employees];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NumericField::Revenue => "revenue",
+            NumericField::Employees => "employees",
+        }
+    }
+}
+
+impl FromStr for NumericField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        NumericField::ALL
+            .into_iter()
+            .find(|field| field.as_str() == name)
+            .ok_or_else(|| format!("Unknown numeric field '{}'", s))
+    }
+}
+
+impl fmt::Display for NumericField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a numeric field's values are spread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumericDistribution {
+    /// Every value in `min..max` equally likely.
+    Uniform { min: f64, max: f64 },
+    /// A bell curve around `mean`, clamped to `min..=max`.
+    Normal {
+        mean: f64,
+        std_dev: f64,
+        min: f64,
+        max: f64,
+    },
+    /// Half the values below `median`, with a long right tail; `sigma` is the
+    /// standard deviation of the values' logarithm.
+    LogNormal { median: f64, sigma: f64 },
+    /// Values from `min` up, where a smaller `shape` gives a heavier tail.
+    Pareto { min: f64, shape: f64 },
+    /// Many small values and ever fewer large ones, averaging `mean`.
+    Exponential { mean: f64 },
+}
+
+impl NumericDistribution {
+    /// A value drawn from `rng`.
+    pub(crate) fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        match self {
+            NumericDistribution::Normal { .. } | NumericDistribution::LogNormal { .. } => {
+                self.at_score(standard_normals(rng).0)
+            }
+            _ => self.exceeded_by(1.0 - rng.gen::<f64>()),
+        }
+    }
+
+    /// The value at standard score `z`, so that values rise with `z`.
+    fn at_score(&self, z: f64) -> f64 {
+        match *self {
+            NumericDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => (mean + std_dev * z).clamp(min, max),
+            NumericDistribution::LogNormal { median, sigma } => median * (sigma * z).exp(),
+            _ => self.exceeded_by(0.5 * erfc(z * FRAC_1_SQRT_2)),
+        }
+    }
+
+    /// The value that a fraction `survival` in `(0, 1]` of all values exceed.
+    fn exceeded_by(&self, survival: f64) -> f64 {
+        match *self {
+            NumericDistribution::Uniform { min, max } => max - (max - min) * survival,
+            NumericDistribution::Pareto { min, shape } => min * survival.powf(-1.0 / shape),
+            NumericDistribution::Exponential { mean } => -mean * survival.ln(),
+            NumericDistribution::Normal { .. } | NumericDistribution::LogNormal { .. } => {
+                unreachable!("drawn from a standard normal instead")
+            }
+        }
+    }
+}
+
+impl FromStr for NumericDistribution {
+    type Err = String;
+
+    /// Reads `uniform:min:max`, `normal:mean:std_dev[:min:max]`,
+    /// `lognormal:median:sigma`, `pareto:min:shape` or `exponential:mean`,
+    /// e.g. `lognormal:2000000:1.2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default().to_lowercase();
+        let params = parts
+            .map(|part| {
+                part.parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| format!("Invalid number '{}' in '{}'", part, s))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let distribution = match (kind.as_str(), params.as_slice()) {
+            ("uniform", &[min, max]) => NumericDistribution::Uniform { min, max },
+            ("normal", &[mean, std_dev]) => NumericDistribution::Normal {
+                mean,
+                std_dev,
+                min: 0.0,
+                max: f64::INFINITY,
+            },
+            ("normal", &[mean, std_dev, min, max]) => NumericDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            },
+            ("lognormal", &[median, sigma]) => NumericDistribution::LogNormal { median, sigma },
+            ("pareto", &[min, shape]) => NumericDistribution::Pareto { min, shape },
+            ("exponential", &[mean]) => NumericDistribution::Exponential { mean },
+            ("uniform", _) => return Err(format!("Expected uniform:min:max, got '{}'", s)),
+            ("normal", _) => {
+                return Err(format!(
+                    "Expected normal:mean:std_dev or normal:mean:std_dev:min:max, got '{}'",
+                    s
+                ))
+            }
+            ("lognormal", _) => {
+                return Err(format!("Expected lognormal:median:sigma, got '{}'", s))
+            }
+            ("pareto", _) => return Err(format!("Expected pareto:min:shape, got '{}'", s)),
+            ("exponential", _) => return Err(format!("Expected exponential:mean, got '{}'", s)),
+            _ => return Err(format!("Unknown distribution '{}'", s)),
+        };
+
+        let valid = match distribution {
+            NumericDistribution::Uniform { min, max } => min < max,
+            NumericDistribution::Normal {
+                std_dev, min, max, ..
+            } => std_dev > 0.0 && min <= max,
+            NumericDistribution::LogNormal { median, sigma } => median > 0.0 && sigma > 0.0,
+            NumericDistribution::Pareto { min, shape } => min > 0.0 && shape > 0.0,
+            NumericDistribution::Exponential { mean } => mean > 0.0,
+        };
+        if !valid {
+            return Err(format!("Parameters out of range in '{}'", s));
+        }
+        Ok(distribution)
+    }
+}
+
+impl fmt::Display for NumericDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumericDistribution::Uniform { min, max } => write!(f, "uniform:{}:{}", min, max),
+            NumericDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } if *min == 0.0 && *max == f64::INFINITY => write!(f, "normal:{}:{}", mean, std_dev),
+            NumericDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => write!(f, "normal:{}:{}:{}:{}", mean, std_dev, min, max),
+            NumericDistribution::LogNormal { median, sigma } => {
+                write!(f, "lognormal:{}:{}", median, sigma)
+            }
+            NumericDistribution::Pareto { min, shape } => write!(f, "pareto:{}:{}", min, shape),
+            NumericDistribution::Exponential { mean } => write!(f, "exponential:{}", mean),
+        }
+    }
+}
+
+/// Most decimal places a revenue can be rounded to.
+pub const MAX_PLACES: u32 = 9;
+
+/// How revenue is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevenueType {
+    /// A single-precision float, optionally rounded to some decimal places.
+    F32 { places: Option<u32> },
+    /// A double-precision float, optionally rounded to some decimal places.
+    F64 { places: Option<u32> },
+    /// A fixed-point decimal string such as `"1234.50"`, exact to `places`.
+    Decimal { places: u32 },
+    /// A whole number of cents.
+    Cents,
+}
+
+impl RevenueType {
+    /// True if the value is written as a string, and quoted in JSON.
+    pub fn is_string(&self) -> bool {
+        matches!(self, RevenueType::Decimal { .. })
+    }
+
+    /// `value` rounded to this type's decimal places, if it has any.
+    pub fn rounded(&self, value: f64) -> f64 {
+        let places = match *self {
+            RevenueType::F32 { places } | RevenueType::F64 { places } => places,
+            RevenueType::Decimal { places } => Some(places),
+            RevenueType::Cents => Some(2),
+        };
+        match places {
+            Some(places) => {
+                let scale = 10f64.powi(places as i32);
+                let units = (value * scale).round();
+                // Too large to scale, and so a whole number already.
+                if units.is_finite() {
+                    units / scale
+                } else {
+                    value
+                }
+            }
+            None => value,
+        }
+    }
+}
+
+impl FromStr for RevenueType {
+    type Err = String;
+
+    /// Reads `f32[:places]`, `f64[:places]`, `decimal[:places]` (2 places by
+    /// default) or `cents`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, places) = match s.split_once(':') {
+            Some((kind, places)) => {
+                let places = places
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|places| *places <= MAX_PLACES)
+                    .ok_or_else(|| {
+                        format!(
+                            "Decimal places must be 0 to {}, got '{}'",
+                            MAX_PLACES, places
+                        )
+                    })?;
+                (kind, Some(places))
+            }
+            None => (s, None),
+        };
+        match kind.to_lowercase().as_str() {
+            "f32" => Ok(RevenueType::F32 { places }),
+            "f64" => Ok(RevenueType::F64 { places }),
+            "decimal" => Ok(RevenueType::Decimal {
+                places: places.unwrap_or(2),
+            }),
+            "cents" if places.is_none() => Ok(RevenueType::Cents),
+            "cents" => Err("cents takes no decimal places".to_owned()),
+            _ => Err(format!("Unknown revenue type '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for RevenueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevenueType::F32 { places: None } => write!(f, "f32"),
+            RevenueType::F32 {
+                places: Some(places),
+            } => write!(f, "f32:{}", places),
+            RevenueType::F64 { places: None } => write!(f, "f64"),
+            RevenueType::F64 {
+                places: Some(places),
+            } => write!(f, "f64:{}", places),
+            RevenueType::Decimal { places } => write!(f, "decimal:{}", places),
+            RevenueType::Cents => write!(f, "cents"),
+        }
+    }
+}
+
+/// Reads a correlation coefficient between -1 and 1.
+pub fn parse_correlation(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|correlation| (-1.0..=1.0).contains(correlation))
+        .ok_or_else(|| format!("Correlation must be between -1 and 1, got '{}'", s))
+}
+
+/// How the numeric fields of a record are drawn and written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericOptions {
+    pub revenue: NumericDistribution,
+    pub employees: NumericDistribution,
+    pub revenue_type: RevenueType,
+    /// Correlation between employees and revenue, from -1 to 1, on the scale
+    /// of their standard scores. At 0.8, larger companies almost always earn
+    /// more; at 0, the two are independent.
+    pub correlation: f64,
+}
+
+impl Default for NumericOptions {
+    fn default() -> Self {
+        Self {
+            revenue: NumericDistribution::Uniform {
+                min: 100000.0,
+                max: 100000000.0,
+            },
+            employees: NumericDistribution::Uniform {
+                min: 10.0,
+                max: 10000.0,
+            },
+            revenue_type: RevenueType::F32 { places: None },
+            correlation: 0.0,
+        }
+    }
+}
+
+impl NumericOptions {
+    pub fn distribution(&self, field: NumericField) -> NumericDistribution {
+        match field {
+            NumericField::Revenue => self.revenue,
+            NumericField::Employees => self.employees,
+        }
+    }
+
+    pub fn set_distribution(&mut self, field: NumericField, distribution: NumericDistribution) {
+        match field {
+            NumericField::Revenue => self.revenue = distribution,
+            NumericField::Employees => self.employees = distribution,
+        }
+    }
+
+    /// Draws a record's revenue and employee count.
+    pub(crate) fn sample<R: Rng>(&self, rng: &mut R) -> (f64, u32) {
+        let (revenue, employees) = if self.correlation == 0.0 {
+            (self.revenue.sample(rng), self.employees.sample(rng))
+        } else {
+            // A Gaussian copula: correlated standard scores, each mapped
+            // through its field's own distribution.
+            let (employees, noise) = standard_normals(rng);
+            let revenue = self.correlation * employees
+                + (1.0 - self.correlation * self.correlation).sqrt() * noise;
+            (
+                self.revenue.at_score(revenue),
+                self.employees.at_score(employees),
+            )
+        };
+        (revenue, employees.round() as u32)
+    }
+}
+
+/// Two independent standard normal values, by the Box-Muller transform.
+fn standard_normals<R: Rng>(rng: &mut R) -> (f64, f64) {
+    let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+    let (sin, cos) = (TAU * rng.gen::<f64>()).sin_cos();
+    (radius * cos, radius * sin)
+}
+
+/// The complementary error function, to a relative error below 1.2e-7
+/// everywhere, so normal tail probabilities stay accurate.
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let poly = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ]
+    .iter()
+    .rev()
+    .fold(0.0, |acc, coefficient| coefficient + t * acc);
+    let tail = t * (poly - x * x).exp();
+    if x >= 0.0 {
+        tail
+    } else {
+        2.0 - tail
+    }
+}
+
+/// Formats revenue as its `RevenueType` asks, without allocating.
+#[derive(Default)]
+pub(crate) struct RevenueBuffer {
+    float: dtoa::Buffer,
+    integer: itoa::Buffer,
+    decimal: [u8; 32],
+}
+
+impl RevenueBuffer {
+    /// Floats beyond their type's range are written as its largest finite
+    /// value, as JSON has no infinity; a heavy-tailed distribution can draw
+    /// them, and an `f32` overflows from about `3.4e38`.
+    pub(crate) fn format(&mut self, revenue: f64, revenue_type: RevenueType) -> &str {
+        match revenue_type {
+            RevenueType::F32 { .. } => self
+                .float
+                .format((revenue_type.rounded(revenue) as f32).clamp(f32::MIN, f32::MAX)),
+            RevenueType::F64 { .. } => self
+                .float
+                .format(revenue_type.rounded(revenue).clamp(f64::MIN, f64::MAX)),
+            RevenueType::Decimal { places } => {
+                let units = scaled(revenue, places);
+                let mut integer = itoa::Buffer::new();
+                let digits = integer.format(units.unsigned_abs()).as_bytes();
+                // At least one digit before the point, e.g. `0.05`.
+                let width = digits.len().max(places as usize + 1);
+                let mut len = 0;
+                if units < 0 {
+                    self.decimal[0] = b'-';
+                    len = 1;
+                }
+                for i in 0..width {
+                    if i == width - places as usize {
+                        self.decimal[len] = b'.';
+                        len += 1;
+                    }
+                    let padding = width - digits.len();
+                    self.decimal[len] = if i < padding {
+                        b'0'
+                    } else {
+                        digits[i - padding]
+                    };
+                    len += 1;
+                }
+                std::str::from_utf8(&self.decimal[..len]).expect("ASCII digits")
+            }
+            RevenueType::Cents => self.integer.format(scaled(revenue, 2)),
+        }
+    }
+}
+
+/// `value` as a whole number of `10^-places` units, saturating at the
+/// bounds of `i64`.
+pub(crate) fn scaled(value: f64, places: u32) -> i64 {
+    (value * 10f64.powi(places as i32)).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const DRAWS: usize = 20_000;
+
+    fn draws(distribution: NumericDistribution) -> Vec<f64> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut values: Vec<f64> = (0..DRAWS).map(|_| distribution.sample(&mut rng)).collect();
+        values.sort_by(f64::total_cmp);
+        values
+    }
+
+    #[test]
+    fn distributions_parse_check_and_round_trip() {
+        for spec in [
+            "uniform:10:20",
+            "normal:50:5",
+            "normal:50:5:40:60",
+            "lognormal:2000000:1.2",
+            "pareto:1000:1.5",
+            "exponential:300",
+        ] {
+            let distribution: NumericDistribution = spec.parse().unwrap();
+            assert_eq!(distribution.to_string(), spec);
+        }
+        assert_eq!(
+            "Normal:1:2".parse(),
+            Ok(NumericDistribution::Normal {
+                mean: 1.0,
+                std_dev: 2.0,
+                min: 0.0,
+                max: f64::INFINITY
+            })
+        );
+
+        for spec in [
+            "uniform:20:10",
+            "uniform:10",
+            "normal:50:0",
+            "normal:50:5:60:40",
+            "normal:50:5:40",
+            "lognormal:0:1",
+            "pareto:1000:0",
+            "exponential:-1",
+            "exponential:inf",
+            "exponential:x",
+            "gamma:1:2",
+        ] {
+            assert!(spec.parse::<NumericDistribution>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn samples_follow_their_distribution() {
+        let uniform = draws(NumericDistribution::Uniform {
+            min: 10.0,
+            max: 20.0,
+        });
+        assert!(uniform[0] >= 10.0 && uniform[DRAWS - 1] < 20.0);
+        assert!((uniform[DRAWS / 2] - 15.0).abs() < 0.2);
+
+        let normal = draws(NumericDistribution::Normal {
+            mean: 50.0,
+            std_dev: 5.0,
+            min: 45.0,
+            max: 70.0,
+        });
+        assert_eq!(normal[0], 45.0);
+        assert!(normal[DRAWS - 1] <= 70.0);
+        assert!((normal[DRAWS / 2] - 50.0).abs() < 0.2);
+
+        let lognormal = draws(NumericDistribution::LogNormal {
+            median: 1000.0,
+            sigma: 1.0,
+        });
+        assert!((lognormal[DRAWS / 2] / 1000.0 - 1.0).abs() < 0.05);
+        let mean = lognormal.iter().sum::<f64>() / DRAWS as f64;
+        assert!(mean > lognormal[DRAWS / 2] * 1.4, "{}", mean);
+
+        let pareto = draws(NumericDistribution::Pareto {
+            min: 100.0,
+            shape: 2.0,
+        });
+        assert!(pareto[0] >= 100.0);
+        // Half the values exceed min * 2^(1/shape).
+        assert!((pareto[DRAWS / 2] / (100.0 * 2f64.sqrt()) - 1.0).abs() < 0.05);
+
+        let exponential = draws(NumericDistribution::Exponential { mean: 300.0 });
+        let mean = exponential.iter().sum::<f64>() / DRAWS as f64;
+        assert!(exponential[0] >= 0.0);
+        assert!((mean / 300.0 - 1.0).abs() < 0.05, "{}", mean);
+    }
+
+    #[test]
+    fn correlation_ties_revenue_to_employees() {
+        let rank_correlation = |correlation: f64| {
+            let options = NumericOptions {
+                revenue: "lognormal:2000000:1".parse().unwrap(),
+                employees: "pareto:10:1.5".parse().unwrap(),
+                correlation,
+                ..NumericOptions::default()
+            };
+            let mut rng = ChaCha8Rng::seed_from_u64(11);
+            let pairs: Vec<(f64, u32)> = (0..DRAWS).map(|_| options.sample(&mut rng)).collect();
+            // The share of pairs of records ordered the same way by both.
+            let mut agree = 0;
+            for pair in pairs.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                if (a.0 - b.0) * (a.1 as f64 - b.1 as f64) > 0.0 {
+                    agree += 1;
+                }
+            }
+            agree as f64 / (DRAWS - 1) as f64
+        };
+
+        assert!(rank_correlation(0.9) > 0.75);
+        assert!(rank_correlation(-0.9) < 0.2);
+        let independent = rank_correlation(0.0);
+        assert!((0.35..0.6).contains(&independent), "{}", independent);
+        assert!(parse_correlation("1.5").is_err());
+        assert_eq!(parse_correlation("-0.5"), Ok(-0.5));
+    }
+
+    #[test]
+    fn revenue_types_parse_and_format() {
+        assert_eq!("f32".parse(), Ok(RevenueType::F32 { places: None }));
+        assert_eq!("F64:3".parse(), Ok(RevenueType::F64 { places: Some(3) }));
+        assert_eq!("decimal".parse(), Ok(RevenueType::Decimal { places: 2 }));
+        assert_eq!("cents".parse(), Ok(RevenueType::Cents));
+        for spec in ["decimal:10", "f32:x", "cents:2", "i64"] {
+            assert!(spec.parse::<RevenueType>().is_err(), "{}", spec);
+        }
+        for spec in ["f32", "f64:3", "decimal:0", "cents"] {
+            assert_eq!(spec.parse::<RevenueType>().unwrap().to_string(), spec);
+        }
+
+        let mut buffer = RevenueBuffer::default();
+        let decimal = |places| RevenueType::Decimal { places };
+        assert_eq!(buffer.format(1234.5, decimal(2)), "1234.50");
+        assert_eq!(buffer.format(0.05, decimal(2)), "0.05");
+        assert_eq!(buffer.format(-0.05, decimal(3)), "-0.050");
+        assert_eq!(buffer.format(7.6, decimal(0)), "8");
+        assert_eq!(buffer.format(1234.567, RevenueType::Cents), "123457");
+        assert_eq!(
+            buffer.format(1234.5678, RevenueType::F64 { places: Some(2) }),
+            "1234.57"
+        );
+        assert!(decimal(2).is_string() && !RevenueType::Cents.is_string());
+    }
+
+    #[test]
+    fn revenue_beyond_its_type_stays_finite() {
+        let mut buffer = RevenueBuffer::default();
+        let f32 = RevenueType::F32 { places: None };
+        let f64 = RevenueType::F64 { places: Some(2) };
+        assert_eq!(buffer.format(1e300, f32), "3.4028235e38");
+        assert_eq!(buffer.format(-1e300, f32).parse(), Ok(f32::MIN));
+        assert_eq!(buffer.format(f64::INFINITY, f64).parse(), Ok(f64::MAX));
+        assert_eq!(buffer.format(1e307, f64).parse(), Ok(1e307));
+
+        // Pareto with a small shape overflows f32 within a few thousand draws.
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let pareto = NumericDistribution::Pareto {
+            min: 1e30,
+            shape: 0.05,
+        };
+        for _ in 0..DRAWS {
+            let revenue = pareto.sample(&mut rng);
+            for revenue_type in [f32, f64] {
+                let written: f64 = buffer.format(revenue, revenue_type).parse().unwrap();
+                assert!(written.is_finite(), "{} as {}", revenue, revenue_type);
+            }
+        }
+    }
+}
@@ -0,0 +1,1040 @@
+use crate::config::ServerConfig;
+use crate::datasets::Dataset;
+use crate::gaps::{Gap, GapRates};
+pub use crate::generator::Framing;
+use crate::jobs::{JobRegistry, JobState};
+use crate::metrics::METRICS;
+use crate::nesting::{Nested, NestingOptions};
+use crate::numeric::{parse_correlation, NumericField, NumericOptions};
+use crate::pools::{Distribution, Pool, PoolField, PoolOptions, PoolSource};
+use crate::processing::{Locale, OutputFormat, PoolCache, StreamGenerator};
+use crate::relational::{TableOptions, Tables};
+use crate::scheduler::{Admission, AdmissionError, Scheduler};
+use crate::shutdown::ShutdownCoordinator;
+use crate::temporal::{TimeKind, TimeOptions, TimeSeries};
+use crate::unique::{KeySpace, UniqueFields};
+use crate::util::{get_size_info, ProgressInfo, SizeInfo};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::sync::mpsc::{channel, Receiver};
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+
+/// The parsed parameters of a generation request.
+#[derive(Debug)]
+pub struct GenerationRequest {
+    pub format: OutputFormat,
+    pub pretty: bool,
+    pub size: SizeInfo,
+    pub framing: Framing,
+    pub dataset: Dataset,
+    pub locale: Locale,
+    /// Pools from `pool.<field>` parameters and distributions from
+    /// `dist.<field>` parameters, for this request only.
+    pub pools: PoolOptions,
+    pub numbers: NumericOptions,
+    pub gaps: GapRates,
+    pub nesting: NestingOptions,
+    pub times: TimeOptions,
+    pub unique: UniqueFields,
+}
+
+impl GenerationRequest {
+    /// Reads the `/generate` query parameters, enforcing `max_request_size`.
+    pub fn from_query(params: &HashMap<String, String>, config: &ServerConfig) -> Result<Self> {
+        let format = parse_format(params, config)?;
+        let pretty = params.get("pretty").is_some_and(|v| v == "true");
+
+        let size = get_size_info(params.get("size"))?;
+        let dataset = parse_dataset(params, format)?;
+        let locale = parse_locale(params, config)?;
+        let pools = pool_options(params, config)?;
+        let numbers = numeric_options(params, config)?;
+        let gaps = gap_rates(params, config)?;
+        let nesting = nesting_options(params)?;
+        if nesting.is_nested() && format == OutputFormat::PROTOBUF {
+            bail!("Nested records need json or csv output");
+        }
+        let times = time_options(params)?;
+        if times.is_enabled() && format == OutputFormat::PROTOBUF {
+            bail!("Time fields need json or csv output");
+        }
+        let unique = unique_fields(params, &gaps, &nesting)?;
+
+        Ok(Self {
+            pretty,
+            dataset,
+            locale,
+            pools,
+            numbers,
+            gaps,
+            nesting,
+            times,
+            unique,
+            ..Self::new(format, size, config)?
+        })
+    }
+
+    /// A request for `size` bytes of `format`, enforcing `max_request_size`.
+    pub fn new(format: OutputFormat, size: SizeInfo, config: &ServerConfig) -> Result<Self> {
+        if size.total_size > config.max_request_size {
+            bail!(
+                "Requested size exceeds the maximum of {} bytes",
+                config.max_request_size
+            );
+        }
+
+        Ok(Self {
+            format,
+            pretty: false,
+            size,
+            framing: Framing::Document,
+            dataset: Dataset::Business,
+            locale: config.default_locale(),
+            pools: PoolOptions::default(),
+            numbers: config.numbers,
+            gaps: config.gaps,
+            nesting: NestingOptions::default(),
+            times: TimeOptions::default(),
+            unique: UniqueFields::default(),
+        })
+    }
+
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// The response's Content-Type. Log lines are plain text whatever the
+    /// format.
+    pub fn content_type(&self) -> &'static str {
+        if self.dataset.is_log() {
+            "text/plain; charset=utf-8"
+        } else {
+            self.format.content_type()
+        }
+    }
+}
+
+/// The parsed parameters of a `/tables` request.
+#[derive(Debug)]
+pub struct TablesRequest {
+    pub tables: TableOptions,
+    pub seed: Option<u64>,
+    pub locale: Locale,
+    pub pools: PoolOptions,
+}
+
+impl TablesRequest {
+    /// Reads `companies`, `ratio.employees`, `ratio.orders`, `format` and
+    /// `seed`, along with the pool and numeric parameters `/generate` takes,
+    /// enforcing `max_request_size` on the estimated output.
+    pub fn from_query(params: &HashMap<String, String>, config: &ServerConfig) -> Result<Self> {
+        let companies = params
+            .get("companies")
+            .ok_or_else(|| anyhow!("Missing companies parameter"))?;
+        let mut tables = TableOptions::new(
+            companies
+                .parse()
+                .map_err(|_| anyhow!("Invalid companies '{}'", companies))?,
+        );
+        for (key, ratio) in [
+            ("ratio.employees", &mut tables.employees_per_company),
+            ("ratio.orders", &mut tables.orders_per_company),
+        ] {
+            if let Some(value) = params.get(key) {
+                *ratio = value
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid {} '{}'", key, value))?;
+            }
+        }
+        if let Some(format) = params.get("format") {
+            tables.format = format.parse().map_err(|e: String| anyhow!(e))?;
+        }
+        tables.numbers = numeric_options(params, config)?;
+        tables.validate().map_err(|e| anyhow!(e))?;
+        if tables.estimated_size() > config.max_request_size {
+            bail!(
+                "Requested tables exceed the maximum of {} bytes",
+                config.max_request_size
+            );
+        }
+
+        let seed = params
+            .get("seed")
+            .map(|seed| seed.parse().map_err(|_| anyhow!("Invalid seed '{}'", seed)))
+            .transpose()?;
+        Ok(Self {
+            tables,
+            seed,
+            locale: parse_locale(params, config)?,
+            pools: pool_options(params, config)?,
+        })
+    }
+}
+
+/// Parameters that shape business records, besides `null.`, `missing.` and
+/// `empty.` rates and time fields.
+const BUSINESS_PARAMS: [&str; 10] = [
+    "nest",
+    "contacts",
+    "tags",
+    "depth",
+    "flatten",
+    "unique",
+    "dist.revenue",
+    "dist.employees",
+    "revenue_type",
+    "correlation",
+];
+
+/// Reads the `dataset` parameter. The built-in datasets have fixed fields, so
+/// they refuse the parameters that shape business records, and every time
+/// parameter but `range.timestamp`, `series` and `jitter`, which place their
+/// own time field.
+pub fn parse_dataset(params: &HashMap<String, String>, format: OutputFormat) -> Result<Dataset> {
+    let dataset = match params.get("dataset") {
+        Some(name) => name.parse().map_err(|e: String| anyhow!(e))?,
+        None => Dataset::Business,
+    };
+    if dataset == Dataset::Business {
+        return Ok(dataset);
+    }
+    if format == OutputFormat::PROTOBUF {
+        bail!("The {} dataset needs json or csv output", dataset);
+    }
+    let business = params
+        .keys()
+        .filter(|key| {
+            BUSINESS_PARAMS.contains(&key.as_str())
+                || key
+                    .split_once('.')
+                    .is_some_and(|(gap, _)| gap.parse::<Gap>().is_ok())
+                || *key == "time"
+                || key.starts_with("time_format.")
+                || (key.starts_with("range.") && *key != "range.timestamp")
+        })
+        .min();
+    if let Some(key) = business {
+        bail!("'{}' only applies to the business dataset", key);
+    }
+    Ok(dataset)
+}
+
+/// Reads the `format` parameter, falling back to the server's default.
+pub fn parse_format(
+    params: &HashMap<String, String>,
+    config: &ServerConfig,
+) -> Result<OutputFormat> {
+    match params.get("format") {
+        Some(format) => format.parse().map_err(|e: String| anyhow!(e)),
+        None => Ok(config.default_format),
+    }
+}
+
+/// Reads the `locale` parameter, falling back to the server's default.
+pub fn parse_locale(params: &HashMap<String, String>, config: &ServerConfig) -> Result<Locale> {
+    match params.get("locale") {
+        Some(locale) => locale.parse().map_err(|e: String| anyhow!(e)),
+        None => Ok(config.default_locale()),
+    }
+}
+
+/// Loads the pools named by `pool.<field>=<file>` parameters from the
+/// configured `pool_dir`, and reads `dist.<field>=<distribution>` parameters.
+/// A CSV column is picked with `<file>#<column>`.
+pub fn pool_options(
+    params: &HashMap<String, String>,
+    config: &ServerConfig,
+) -> Result<PoolOptions> {
+    let mut options = PoolOptions::default();
+    for (key, value) in params {
+        if let Some(field) = key.strip_prefix("pool.") {
+            let field: PoolField = field.parse().map_err(|e: String| anyhow!(e))?;
+            let Some(dir) = &config.pool_dir else {
+                bail!("Per-request pools are disabled, the server has no pool_dir");
+            };
+            let source = PoolSource::at(field, value).within(dir)?;
+            options.overrides.push((field, load_request_pool(&source)?));
+        } else if let Some(field) = key.strip_prefix("dist.") {
+            if field.parse::<NumericField>().is_ok() {
+                continue;
+            }
+            let field: PoolField = field.parse().map_err(|e: String| anyhow!(e))?;
+            let distribution: Distribution = value
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid {}: {}", key, e))?;
+            options.distributions.push((field, distribution));
+        }
+    }
+    Ok(options)
+}
+
+/// Pools read for `pool.<field>` parameters, by file and column, with the
+/// modification time of the file they were read from.
+type LoadedPools = HashMap<(PathBuf, Option<String>), (SystemTime, Arc<Pool>)>;
+
+lazy_static! {
+    static ref REQUEST_POOLS: Mutex<LoadedPools> = Mutex::new(HashMap::new());
+}
+
+/// Loads a per-request pool, reusing the last load of the same file while it
+/// is unchanged, so requests naming a pool do not each read and parse it.
+fn load_request_pool(source: &PoolSource) -> Result<Arc<Pool>> {
+    let key = (source.path.clone(), source.column.clone());
+    let modified = std::fs::metadata(&source.path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if let Some(modified) = modified {
+        if let Some((loaded, pool)) = REQUEST_POOLS.lock().get(&key) {
+            if *loaded == modified {
+                return Ok(pool.clone());
+            }
+        }
+    }
+
+    let pool = Arc::new(source.load()?);
+    debug!(%source, values = pool.len(), "loaded request pool");
+    if let Some(modified) = modified {
+        REQUEST_POOLS.lock().insert(key, (modified, pool.clone()));
+    }
+    Ok(pool)
+}
+
+/// Reads `dist.revenue`, `dist.employees`, `revenue_type` and `correlation`
+/// parameters over the server's settings.
+pub fn numeric_options(
+    params: &HashMap<String, String>,
+    config: &ServerConfig,
+) -> Result<NumericOptions> {
+    let mut numbers = config.numbers;
+    for field in NumericField::ALL {
+        let key = format!("dist.{}", field);
+        if let Some(spec) = params.get(&key) {
+            let distribution = spec
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid {}: {}", key, e))?;
+            numbers.set_distribution(field, distribution);
+        }
+    }
+    if let Some(revenue_type) = params.get("revenue_type") {
+        numbers.revenue_type = revenue_type
+            .parse()
+            .map_err(|e: String| anyhow!("Invalid revenue_type: {}", e))?;
+    }
+    if let Some(correlation) = params.get("correlation") {
+        numbers.correlation = parse_correlation(correlation).map_err(|e| anyhow!(e))?;
+    }
+    Ok(numbers)
+}
+
+/// Reads `null.<field>`, `missing.<field>` and `empty.<field>` rates over the
+/// server's. A `*` field sets every field, before any named ones.
+pub fn gap_rates(params: &HashMap<String, String>, config: &ServerConfig) -> Result<GapRates> {
+    let mut rates: Vec<_> = params
+        .iter()
+        .filter_map(|(key, rate)| {
+            let (gap, field) = key.split_once('.')?;
+            Some((key, gap.parse::<Gap>().ok()?, field, rate))
+        })
+        .collect();
+    rates.sort_by_key(|(_, _, field, _)| *field != "*");
+
+    let mut gaps = config.gaps;
+    for (key, gap, field, rate) in rates {
+        gaps.set_rate(gap, field, rate)
+            .map_err(|e| anyhow!("Invalid {}: {}", key, e))?;
+    }
+    Ok(gaps)
+}
+
+/// Reads `nest=address,contacts,tags`, the `contacts` and `tags` array
+/// lengths, which add their array on their own, `depth` and `flatten`.
+pub fn nesting_options(params: &HashMap<String, String>) -> Result<NestingOptions> {
+    let mut nesting = NestingOptions::default();
+    if let Some(list) = params.get("nest") {
+        for nested in list.split(',').filter(|nested| !nested.is_empty()) {
+            nesting.enable(nested.trim().parse::<Nested>().map_err(|e| anyhow!(e))?);
+        }
+    }
+    if let Some(lengths) = params.get("contacts") {
+        nesting.contacts = Some(
+            lengths
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid contacts: {}", e))?,
+        );
+    }
+    if let Some(lengths) = params.get("tags") {
+        nesting.tags = Some(
+            lengths
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid tags: {}", e))?,
+        );
+    }
+    if let Some(depth) = params.get("depth") {
+        let depth = depth
+            .parse()
+            .map_err(|_| anyhow!("Invalid depth '{}'", depth))?;
+        nesting.set_depth(depth).map_err(|e| anyhow!(e))?;
+    }
+    if let Some(flatten) = params.get("flatten") {
+        nesting.flatten = flatten.parse().map_err(|e: String| anyhow!(e))?;
+    }
+    Ok(nesting)
+}
+
+/// Reads `time=date,datetime,timestamp`, then `range.<kind>=start..end` and
+/// `time_format.<kind>`, which add their field on their own, and `series`
+/// and `jitter`, which make `timestamp` a time series.
+pub fn time_options(params: &HashMap<String, String>) -> Result<TimeOptions> {
+    let mut times = TimeOptions::default();
+    if let Some(list) = params.get("time") {
+        for kind in list.split(',').filter(|kind| !kind.is_empty()) {
+            times.enable(kind.trim().parse::<TimeKind>().map_err(|e| anyhow!(e))?);
+        }
+    }
+    for kind in TimeKind::ALL {
+        if let Some(range) = params.get(&format!("range.{}", kind)) {
+            times.enable(kind).range = range
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid range.{}: {}", kind, e))?;
+        }
+        if let Some(format) = params.get(&format!("time_format.{}", kind)) {
+            times.enable(kind).format = format
+                .parse()
+                .map_err(|e: String| anyhow!("Invalid time_format.{}: {}", kind, e))?;
+        }
+    }
+    if let Some(rate) = params.get("series") {
+        let rate = rate
+            .parse()
+            .map_err(|_| anyhow!("Invalid series rate '{}'", rate))?;
+        let jitter = match params.get("jitter") {
+            Some(jitter) => jitter
+                .parse()
+                .map_err(|_| anyhow!("Invalid jitter '{}'", jitter))?,
+            None => 0.0,
+        };
+        times.set_series(TimeSeries::new(rate, jitter).map_err(|e| anyhow!(e))?);
+    } else if params.contains_key("jitter") {
+        bail!("jitter needs a series rate");
+    }
+    Ok(times)
+}
+
+/// Reads `unique=name,email`, refusing fields that could not be kept unique
+/// with the given gaps and nesting.
+pub fn unique_fields(
+    params: &HashMap<String, String>,
+    gaps: &GapRates,
+    nesting: &NestingOptions,
+) -> Result<UniqueFields> {
+    let mut unique = UniqueFields::default();
+    if let Some(spec) = params.get("unique") {
+        unique.set_spec(spec).map_err(|e| anyhow!(e))?;
+        unique.validate(gaps, nesting).map_err(|e| anyhow!(e))?;
+    }
+    Ok(unique)
+}
+
+/// Why a generation could not be started.
+#[derive(Debug)]
+pub enum StartError {
+    ShuttingDown,
+    WarmingUp {
+        retry_after: u64,
+    },
+    NotAdmitted {
+        job_id: u64,
+        retry_after: u64,
+        reason: AdmissionError,
+    },
+}
+
+impl std::fmt::Display for StartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartError::ShuttingDown => write!(f, "Server is shutting down"),
+            StartError::WarmingUp { .. } => write!(f, "Data pools are still warming up"),
+            StartError::NotAdmitted { reason, .. } => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl ResponseError for StartError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            StartError::ShuttingDown | StartError::WarmingUp { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            StartError::NotAdmitted { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            StartError::ShuttingDown => {}
+            StartError::WarmingUp { retry_after } => {
+                response.insert_header(("Retry-After", retry_after.to_string()));
+            }
+            StartError::NotAdmitted {
+                job_id,
+                retry_after,
+                ..
+            } => {
+                response
+                    .insert_header(("Retry-After", retry_after.to_string()))
+                    .insert_header(("X-Request-Id", job_id.to_string()));
+            }
+        }
+        response.body(self.to_string())
+    }
+}
+
+/// A running generation. `body` yields the framed output in order, one whole
+/// chunk at a time, and closes when the generation ends; dropping it aborts the
+/// generation. Once `body` has closed, `progress` holds the final job state.
+pub struct Generation {
+    pub job_id: u64,
+    pub format: OutputFormat,
+    pub body: Receiver<Bytes>,
+    pub progress: Arc<ProgressInfo>,
+}
+
+/// Everything needed to admit and run generations, shared by every endpoint
+/// that streams generated data.
+#[derive(Clone)]
+pub struct GenerationService {
+    config: Arc<ServerConfig>,
+    scheduler: Arc<Scheduler>,
+    registry: Arc<JobRegistry>,
+    pools: Arc<PoolCache>,
+    coordinator: Arc<ShutdownCoordinator>,
+}
+
+impl GenerationService {
+    pub fn new(
+        config: Arc<ServerConfig>,
+        scheduler: Arc<Scheduler>,
+        registry: Arc<JobRegistry>,
+        pools: Arc<PoolCache>,
+        coordinator: Arc<ShutdownCoordinator>,
+    ) -> Self {
+        Self {
+            config,
+            scheduler,
+            registry,
+            pools,
+            coordinator,
+        }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Registers a job, waits for admission and starts the chunk pipeline.
+    ///
+    /// Records `job_id`, `format` and `size` on the current span, so callers
+    /// should run this inside their request span.
+    pub async fn start(&self, request: GenerationRequest) -> Result<Generation, StartError> {
+        let config = &self.config;
+        let chunk_bytes = config.chunk_size;
+        let stream_chunk_size = config.stream_chunk_size;
+
+        if self.coordinator.is_shutting_down() {
+            return Err(StartError::ShuttingDown);
+        }
+
+        let Some(data_pools) = self.pools.get(request.locale) else {
+            return Err(StartError::WarmingUp {
+                retry_after: config.retry_after,
+            });
+        };
+
+        let GenerationRequest {
+            format,
+            pretty,
+            size: size_info,
+            framing,
+            dataset,
+            pools,
+            numbers,
+            gaps,
+            nesting,
+            times,
+            unique,
+            ..
+        } = request;
+        let data_pools = if pools.is_empty() {
+            data_pools
+        } else {
+            Arc::new(data_pools.customize(&pools))
+        };
+        // Log lines come one per line whatever the request asked for.
+        let framing = if dataset.is_log() {
+            Framing::Lines
+        } else {
+            framing
+        };
+        let line_delimited = framing == Framing::Lines;
+        let pretty = pretty && !line_delimited;
+
+        let job_id = self.registry.next_id();
+        let span = Span::current();
+        span.record("job_id", job_id);
+        span.record("format", format.to_string());
+        span.record("size", size_info.total_size);
+
+        let progress = Arc::new(ProgressInfo::new(
+            job_id,
+            size_info.total_size,
+            size_info.multiplier,
+            size_info.unit.clone(),
+            format,
+        ));
+        self.registry.insert(progress.clone());
+        let job_progress = progress.clone();
+
+        let admission = self.admit(job_id).await?;
+        progress.set_state(JobState::Running);
+        let coordinator = self.coordinator.clone();
+        let registry = self.registry.clone();
+        let guard = coordinator.track();
+        info!("generation started");
+
+        let num_threads = admission.pool().current_num_threads();
+        let chunk_size = size_info.total_size / (num_threads as u64);
+        let num_chunks = size_info.total_size.div_ceil(chunk_bytes);
+        // A time series numbers records across the whole stream, so its
+        // records come from one generator, in order, instead of from chunks
+        // that finish in any order. Log lines rise in time the same way.
+        let ordered = times.series.is_some() || dataset.is_log();
+
+        let (tx, rx) = channel::<Bytes>(config.channel_depth);
+
+        let opening = framing.opening(format, dataset, &nesting, &times);
+        let marker = truncation_record(format, framing, dataset, opening.as_deref());
+        if let Some(opening) = opening {
+            if format == OutputFormat::CSV {
+                progress.update_streamed(opening.len());
+            }
+            if tx.send(opening).await.is_err() {
+                debug!("response stream closed before the opening bytes were sent");
+            }
+        }
+
+        tokio::spawn(
+            async move {
+                let _guard = guard;
+                let _active = METRICS.stream_started(format);
+                let seed: u64 = rand::thread_rng().gen();
+
+                let other_prog = progress.clone();
+
+                let (chunk_tx, mut chunk_rx) = channel::<Bytes>(1);
+
+                let generation_span = Span::current();
+                let cancel = coordinator.clone();
+                std::thread::spawn(move || {
+                    let pool = admission.pool();
+                    let mut initial_generator = StreamGenerator::new(
+                        ChaCha8Rng::seed_from_u64(seed),
+                        &data_pools,
+                        pretty,
+                        format,
+                        if ordered {
+                            size_info.total_size
+                        } else {
+                            chunk_size
+                        },
+                    )
+                    .with_target_chunk_size(stream_chunk_size)
+                    .with_line_delimited(line_delimited)
+                    .with_numbers(numbers)
+                    .with_gaps(gaps)
+                    .with_nesting(nesting)
+                    .with_times(times.clone())
+                    .with_dataset(dataset)
+                    .with_unique(
+                        unique,
+                        if ordered {
+                            KeySpace::default()
+                        } else {
+                            KeySpace::new(0, num_chunks + 1)
+                        },
+                    );
+
+                    let _pipeline =
+                        info_span!(parent: &generation_span, "chunk_pipeline", chunks = num_chunks)
+                            .entered();
+
+                    if let Some(chunk) = initial_generator.generate_kickoff_chunk() {
+                        other_prog.update(chunk.len());
+                        other_prog.update_records(initial_generator.last_chunk_records());
+                        if chunk_tx.blocking_send(chunk).is_err() {
+                            debug!("stream closed before the kickoff chunk was sent");
+                            return;
+                        }
+                    }
+
+                    if ordered {
+                        // Each chunk is still drawn in parallel on the pool.
+                        pool.install(|| {
+                            while let Some(chunk) = initial_generator.generate_chunk() {
+                                if cancel.is_cancelled() {
+                                    break;
+                                }
+                                other_prog.update(chunk.len());
+                                other_prog.update_records(initial_generator.last_chunk_records());
+
+                                if chunk_tx.blocking_send(chunk).is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                        return;
+                    }
+
+                    let chunks: Vec<_> = (0..num_chunks).collect();
+                    pool.install(|| {
+                        chunks.into_par_iter().for_each(|i| {
+                            let chunk_rng = ChaCha8Rng::seed_from_u64(seed.wrapping_add(i + 1));
+                            let current_chunk_size = if i == num_chunks - 1 {
+                                size_info.total_size - (i * chunk_bytes)
+                            } else {
+                                chunk_bytes
+                            };
+                            let mut generator = StreamGenerator::new(
+                                chunk_rng,
+                                &data_pools,
+                                pretty,
+                                format,
+                                current_chunk_size,
+                            )
+                            .with_target_chunk_size(stream_chunk_size)
+                            .with_line_delimited(line_delimited)
+                            .with_numbers(numbers)
+                            .with_gaps(gaps)
+                            .with_nesting(nesting)
+                            .with_times(times.clone())
+                            .with_dataset(dataset)
+                            .with_unique(unique, KeySpace::new(i + 1, num_chunks + 1));
+
+                            while let Some(chunk) = generator.generate_chunk() {
+                                if cancel.is_cancelled() {
+                                    break;
+                                }
+                                other_prog.update(chunk.len());
+                                other_prog.update_records(generator.last_chunk_records());
+
+                                if chunk_tx.blocking_send(chunk).is_err() {
+                                    break;
+                                }
+                            }
+                        })
+                    });
+                });
+
+                let stream_span = info_span!("stream");
+                let mut final_state = JobState::Completed;
+                async {
+                    while let Some(chunk) = chunk_rx.recv().await {
+                        progress.update_streamed(chunk.len());
+
+                        let waited = Instant::now();
+                        let sent = tx.send(chunk).await;
+                        METRICS.add_backpressure(waited.elapsed());
+
+                        if sent.is_err() {
+                            METRICS.client_aborts.fetch_add(1, Ordering::Relaxed);
+                            final_state = JobState::Aborted;
+                            warn!("client disconnected mid-stream");
+                            break;
+                        }
+                    }
+                    if final_state == JobState::Completed && coordinator.is_cancelled() {
+                        final_state = JobState::Truncated;
+                        warn!("stream truncated by server shutdown");
+                        if let Some(marker) = marker {
+                            if tx.send(marker).await.is_err() {
+                                final_state = JobState::Aborted;
+                            }
+                        }
+                    }
+                    if let Some(closing) = framing.closing(format) {
+                        if final_state != JobState::Aborted && tx.send(closing).await.is_err() {
+                            warn!("client disconnected before the closing bracket was sent");
+                            final_state = JobState::Aborted;
+                        }
+                    }
+                }
+                .instrument(stream_span)
+                .await;
+
+                progress.finish();
+                registry.finish(job_id, final_state);
+
+                let status = progress.status();
+                info!(
+                    state = ?final_state,
+                    bytes_streamed = status.bytes_streamed,
+                    records = status.records,
+                    elapsed_seconds = status.elapsed_seconds,
+                    "generation finished"
+                );
+            }
+            .in_current_span(),
+        );
+
+        Ok(Generation {
+            job_id,
+            format,
+            body: rx,
+            progress: job_progress,
+        })
+    }
+}
+
+impl GenerationService {
+    /// Waits for a generation slot for job `job_id`, marking the job rejected
+    /// if none comes.
+    pub(crate) async fn admit(&self, job_id: u64) -> Result<Admission, StartError> {
+        match self
+            .scheduler
+            .admit()
+            .instrument(info_span!("admission"))
+            .await
+        {
+            Ok(admission) => Ok(admission),
+            Err(reason) => {
+                warn!(error = %reason, "generation not admitted");
+                self.registry.finish(job_id, JobState::Rejected);
+                Err(StartError::NotAdmitted {
+                    job_id,
+                    retry_after: self.config.retry_after,
+                    reason,
+                })
+            }
+        }
+    }
+
+    /// Registers, admits and starts writing a relational dataset. The tables
+    /// are written one after the other, each chunk drawn on the admitted
+    /// pool, so the output is the same for the same seed.
+    pub async fn start_tables(&self, request: TablesRequest) -> Result<Generation, StartError> {
+        let config = &self.config;
+        if self.coordinator.is_shutting_down() {
+            return Err(StartError::ShuttingDown);
+        }
+        let Some(data_pools) = self.pools.get(request.locale) else {
+            return Err(StartError::WarmingUp {
+                retry_after: config.retry_after,
+            });
+        };
+
+        let TablesRequest {
+            tables,
+            seed,
+            pools,
+            ..
+        } = request;
+        let data_pools = if pools.is_empty() {
+            data_pools
+        } else {
+            Arc::new(data_pools.customize(&pools))
+        };
+        let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let format = tables.format.record_format();
+
+        let job_id = self.registry.next_id();
+        let span = Span::current();
+        span.record("job_id", job_id);
+        span.record("format", tables.format.as_str());
+
+        let progress = Arc::new(ProgressInfo::new(
+            job_id,
+            tables.estimated_size(),
+            1,
+            "b".to_owned(),
+            format,
+        ));
+        self.registry.insert(progress.clone());
+        let job_progress = progress.clone();
+
+        let admission = self.admit(job_id).await?;
+        progress.set_state(JobState::Running);
+        let coordinator = self.coordinator.clone();
+        let registry = self.registry.clone();
+        let guard = coordinator.track();
+        info!(seed, "table generation started");
+
+        let (tx, rx) = channel::<Bytes>(config.channel_depth);
+        let generation_span = Span::current();
+        std::thread::spawn(move || {
+            let _span = generation_span.entered();
+            let _guard = guard;
+            let _active = METRICS.stream_started(format);
+            let tables = Tables::new(tables, &data_pools, seed);
+            let mut final_state = JobState::Completed;
+            admission.pool().install(|| {
+                tables.write(|chunk, rows| {
+                    // An archive cut short is unreadable, so there is no
+                    // marker to add; the job state tells why it ended.
+                    if coordinator.is_cancelled() {
+                        final_state = JobState::Truncated;
+                        return false;
+                    }
+                    progress.update(chunk.len());
+                    progress.update_records(rows);
+                    progress.update_streamed(chunk.len());
+                    if tx.blocking_send(chunk).is_err() {
+                        METRICS.client_aborts.fetch_add(1, Ordering::Relaxed);
+                        final_state = JobState::Aborted;
+                        return false;
+                    }
+                    true
+                })
+            });
+
+            progress.finish();
+            registry.finish(job_id, final_state);
+            let status = progress.status();
+            info!(
+                state = ?final_state,
+                bytes_streamed = status.bytes_streamed,
+                records = status.records,
+                elapsed_seconds = status.elapsed_seconds,
+                "table generation finished"
+            );
+        });
+
+        Ok(Generation {
+            job_id,
+            format,
+            body: rx,
+            progress: job_progress,
+        })
+    }
+}
+
+/// Final record appended when shutdown cuts a stream short. Chunks always end on
+/// a record boundary, so this keeps the body valid JSON or CSV while telling the
+/// client the data is incomplete. The CSV row is padded to as many columns as
+/// `header` has. Protobuf has no record to spare for this, and a log is only
+/// lines in its own format, so callers check the job state instead.
+fn truncation_record(
+    format: OutputFormat,
+    framing: Framing,
+    dataset: Dataset,
+    header: Option<&[u8]>,
+) -> Option<Bytes> {
+    match (format, framing) {
+        _ if dataset.is_log() => None,
+        (OutputFormat::JSON, Framing::Document) => Some(Bytes::from_static(
+            b",{\"error\":\"truncated\",\"reason\":\"server shutting down\"}",
+        )),
+        (OutputFormat::JSON, Framing::Lines) => Some(Bytes::from_static(
+            b"{\"error\":\"truncated\",\"reason\":\"server shutting down\"}\n",
+        )),
+        (OutputFormat::CSV, _) => {
+            let columns = header.map_or(2, |header| {
+                header.iter().filter(|&&byte| byte == b',').count() + 1
+            });
+            let mut marker = BytesMut::from(&b"ERROR,truncated: server shutting down"[..]);
+            for _ in 2..columns {
+                marker.put_u8(b',');
+            }
+            marker.put_u8(b'\n');
+            Some(marker.freeze())
+        }
+        (OutputFormat::PROTOBUF, _) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &[(&str, &str)]) -> Result<GenerationRequest> {
+        let params = query
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        GenerationRequest::from_query(&params, &ServerConfig::default())
+    }
+
+    #[test]
+    fn formats_default_or_must_be_known() {
+        let request = parse(&[("size", "1kb")]).unwrap();
+        assert_eq!(request.format, ServerConfig::default().default_format);
+        let request = parse(&[("size", "1kb"), ("format", "CSV")]).unwrap();
+        assert_eq!(request.format, OutputFormat::CSV);
+
+        let error = parse(&[("size", "1kb"), ("format", "xml")]).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown format 'xml'");
+    }
+
+    #[test]
+    fn oversized_requests_are_refused() {
+        let error = parse(&[("size", "16777216tb")]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Failed to parse size: Size too large"
+        );
+        let error = parse(&[("size", "2tb")]).unwrap_err();
+        assert!(error.to_string().starts_with("Requested size exceeds"));
+    }
+
+    #[test]
+    fn request_pools_are_read_again_only_once_changed() {
+        let dir = std::env::temp_dir().join(format!(
+            "fast-json-gen-request-pools-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("industries.txt");
+        std::fs::write(&path, "Mining\nRetail\n").unwrap();
+        let config = ServerConfig {
+            pool_dir: Some(dir),
+            ..ServerConfig::default()
+        };
+        let params = HashMap::from([("pool.industry".to_owned(), "industries.txt".to_owned())]);
+
+        let first = pool_options(&params, &config)
+            .unwrap()
+            .overrides
+            .remove(0)
+            .1;
+        let second = pool_options(&params, &config)
+            .unwrap()
+            .overrides
+            .remove(0)
+            .1;
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.values(), ["Mining", "Retail"]);
+
+        std::fs::write(&path, "Shipping\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        let changed = pool_options(&params, &config)
+            .unwrap()
+            .overrides
+            .remove(0)
+            .1;
+        assert_eq!(changed.values(), ["Shipping"]);
+    }
+}
@@ -0,0 +1,864 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A record field whose values are drawn from a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PoolField {
+    Name,
+    Industry,
+    City,
+    State,
+    Country,
+    /// Names of the people in nested `contacts`.
+    Contact,
+    /// Emails of the people in nested `contacts`.
+    Email,
+    /// Words in nested `tags`.
+    Tag,
+}
+
+impl PoolField {
+    pub const ALL: [PoolField; 8] = [
+        PoolField::Name,
+        PoolField::Industry,
+        PoolField::City,
+        PoolField::State,
+        PoolField::Country,
+        PoolField::Contact,
+        PoolField::Email,
+        PoolField::Tag,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PoolField::Name => "name",
+            PoolField::Industry => "industry",
+            PoolField::City => "city",
+            PoolField::State => "state",
+            PoolField::Country => "country",
+            PoolField::Contact => "contact",
+            PoolField::Email => "email",
+            PoolField::Tag => "tag",
+        }
+    }
+}
+
+impl FromStr for PoolField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        PoolField::ALL
+            .into_iter()
+            .find(|field| field.as_str() == name)
+            .ok_or_else(|| format!("Unknown pool field '{}'", s))
+    }
+}
+
+impl fmt::Display for PoolField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Pools that replace the sampled ones for particular fields.
+pub type PoolOverrides = Vec<(PoolField, Arc<Pool>)>;
+
+/// How often each value of a pool comes up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    /// Every value equally often, ignoring any weights.
+    Uniform,
+    /// In proportion to the pool's weights; uniform for unweighted pools.
+    Weighted,
+    /// The value at rank `k` (counting from 1) comes up in proportion to
+    /// `1 / k^exponent`, so the first values dominate.
+    Zipf { exponent: f64 },
+    /// A bell curve over the pool's positions. `mean` and `std_dev` are
+    /// fractions of the pool length, so `0.5` is its middle.
+    Normal { mean: f64, std_dev: f64 },
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    /// Reads `uniform`, `weighted`, `zipf[:exponent]` or
+    /// `normal[:mean[:std_dev]]`, e.g. `zipf:1.2` or `normal:0.5:0.1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default().to_lowercase();
+        let mut param = |name: &str, default: f64| -> Result<f64, String> {
+            match parts.next() {
+                Some(value) => value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| format!("Invalid {} '{}' in '{}'", name, value, s)),
+                None => Ok(default),
+            }
+        };
+
+        let distribution = match kind.as_str() {
+            "uniform" => Distribution::Uniform,
+            "weighted" => Distribution::Weighted,
+            "zipf" => {
+                let exponent = param("exponent", 1.0)?;
+                if exponent <= 0.0 {
+                    return Err(format!("Zipf exponent must be positive, got {}", exponent));
+                }
+                Distribution::Zipf { exponent }
+            }
+            "normal" => {
+                let mean = param("mean", 0.5)?;
+                let std_dev = param("std_dev", 0.15)?;
+                if std_dev <= 0.0 {
+                    return Err(format!("std_dev must be positive, got {}", std_dev));
+                }
+                Distribution::Normal { mean, std_dev }
+            }
+            _ => return Err(format!("Unknown distribution '{}'", s)),
+        };
+        if parts.next().is_some() {
+            return Err(format!("Too many parameters in '{}'", s));
+        }
+        Ok(distribution)
+    }
+}
+
+impl Distribution {
+    /// Reads `field=distribution`, e.g. `industry=zipf:1.2`.
+    pub fn parse_for_field(spec: &str) -> Result<(PoolField, Self)> {
+        let (field, distribution) = spec
+            .split_once('=')
+            .with_context(|| format!("Expected field=distribution, got '{}'", spec))?;
+        let field = field.trim().parse().map_err(|e: String| anyhow!(e))?;
+        let distribution = distribution
+            .trim()
+            .parse()
+            .map_err(|e: String| anyhow!(e))?;
+        Ok((field, distribution))
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Distribution::Uniform => write!(f, "uniform"),
+            Distribution::Weighted => write!(f, "weighted"),
+            Distribution::Zipf { exponent } => write!(f, "zipf:{}", exponent),
+            Distribution::Normal { mean, std_dev } => write!(f, "normal:{}:{}", mean, std_dev),
+        }
+    }
+}
+
+/// Changes to the data pools for one generation: replacement pools, then
+/// distributions over whichever pool each field ends up with.
+#[derive(Debug, Clone, Default)]
+pub struct PoolOptions {
+    pub overrides: PoolOverrides,
+    pub distributions: Vec<(PoolField, Distribution)>,
+}
+
+impl PoolOptions {
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty() && self.distributions.is_empty()
+    }
+}
+
+/// The values a field is drawn from, optionally weighted.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    values: Arc<[String]>,
+    /// Running totals of the weights the pool was built with, if any.
+    weights: Option<Arc<[f64]>>,
+    /// Running totals `pick` samples from; `None` picks uniformly.
+    cumulative: Option<Arc<[f64]>>,
+}
+
+impl From<Vec<String>> for Pool {
+    /// An unweighted pool. `pick` panics if `values` is empty.
+    fn from(values: Vec<String>) -> Self {
+        Self {
+            values: values.into(),
+            weights: None,
+            cumulative: None,
+        }
+    }
+}
+
+impl Pool {
+    /// An unweighted pool, where every value is equally likely.
+    pub fn new(values: Vec<String>) -> Result<Self> {
+        if values.is_empty() {
+            bail!("Pool has no values");
+        }
+        Ok(values.into())
+    }
+
+    /// A pool where each value is picked in proportion to its weight.
+    pub fn weighted(entries: Vec<(String, f64)>) -> Result<Self> {
+        if entries.is_empty() {
+            bail!("Pool has no values");
+        }
+        if entries.iter().all(|(_, weight)| *weight == 1.0) {
+            return Ok(entries
+                .into_iter()
+                .map(|(value, _)| value)
+                .collect::<Vec<_>>()
+                .into());
+        }
+
+        let mut values = Vec::with_capacity(entries.len());
+        let mut weights = Vec::with_capacity(entries.len());
+        for (value, weight) in entries {
+            if !weight.is_finite() || weight < 0.0 {
+                bail!("Invalid weight {} for '{}'", weight, value);
+            }
+            values.push(value);
+            weights.push(weight);
+        }
+        let Some(cumulative) = running_totals(weights) else {
+            bail!("Pool weights add up to zero");
+        };
+
+        Ok(Self {
+            values: values.into(),
+            weights: Some(cumulative.clone()),
+            cumulative: Some(cumulative),
+        })
+    }
+
+    /// The same values, picked according to `distribution`. The values are
+    /// shared, not copied.
+    pub fn with_distribution(&self, distribution: Distribution) -> Self {
+        let len = self.values.len();
+        let cumulative = match distribution {
+            Distribution::Uniform => None,
+            Distribution::Weighted => self.weights.clone(),
+            Distribution::Zipf { exponent } => {
+                running_totals((1..=len).map(|rank| (rank as f64).powf(-exponent)))
+            }
+            Distribution::Normal { mean, std_dev } => {
+                let z = |i: usize| ((i as f64 + 0.5) / len as f64 - mean) / std_dev;
+                // Measured from the position nearest the mean, so a narrow
+                // curve cannot underflow to all zeroes.
+                let peak = (0..len).map(|i| z(i) * z(i)).fold(f64::INFINITY, f64::min);
+                running_totals((0..len).map(|i| (-0.5 * (z(i) * z(i) - peak)).exp()))
+            }
+        };
+
+        Self {
+            values: self.values.clone(),
+            weights: self.weights.clone(),
+            cumulative,
+        }
+    }
+
+    /// Reads a pool from a file, picking the format from its extension:
+    ///
+    /// - `.json`: an array of strings, or of `{"value": ..., "weight": ...}`
+    ///   objects.
+    /// - `.csv`: a header row, then one value per row from `column`, or from
+    ///   the first column when unset. A `weight` column, if present, holds
+    ///   the weights.
+    /// - anything else: one value per line. A line may end with a tab and a
+    ///   weight.
+    pub fn load(path: &Path, column: Option<&str>) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pool file {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        let entries = match extension.as_deref() {
+            Some("json") => parse_json(&contents),
+            Some("csv") => parse_csv(&contents, column),
+            _ if column.is_some() => Err(anyhow!("Only CSV pool files have columns")),
+            _ => Ok(parse_lines(&contents)),
+        };
+        entries
+            .and_then(Self::weighted)
+            .with_context(|| format!("Invalid pool file {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn is_weighted(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    /// Maps a uniformly random `draw` onto a value, honouring the weights.
+    #[inline]
+    pub fn pick(&self, draw: u32) -> &str {
+        let index = match &self.cumulative {
+            None => ((draw as u64 * self.values.len() as u64) >> 32) as usize,
+            Some(cumulative) => {
+                let total = cumulative[cumulative.len() - 1];
+                let target = draw as f64 / (u32::MAX as f64 + 1.0) * total;
+                cumulative
+                    .partition_point(|&running| running <= target)
+                    .min(cumulative.len() - 1)
+            }
+        };
+        &self.values[index]
+    }
+}
+
+/// Prefix sums of `weights`, or `None` if they add up to zero.
+fn running_totals(weights: impl IntoIterator<Item = f64>) -> Option<Arc<[f64]>> {
+    let mut total = 0.0;
+    let cumulative: Arc<[f64]> = weights
+        .into_iter()
+        .map(|weight| {
+            total += weight;
+            total
+        })
+        .collect();
+    (total > 0.0).then_some(cumulative)
+}
+
+fn parse_lines(contents: &str) -> Vec<(String, f64)> {
+    contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.rsplit_once('\t')
+                .and_then(|(value, weight)| Some((value, weight.trim().parse().ok()?)))
+                .map_or_else(
+                    || (line.to_owned(), 1.0),
+                    |(value, weight)| (value.to_owned(), weight),
+                )
+        })
+        .collect()
+}
+
+fn parse_json(contents: &str) -> Result<Vec<(String, f64)>> {
+    let Value::Array(items) = serde_json::from_str(contents)? else {
+        bail!("Expected a JSON array");
+    };
+
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| match item {
+            Value::String(value) => Ok((value, 1.0)),
+            Value::Object(mut object) => {
+                let value = match object.remove("value") {
+                    Some(Value::String(value)) => value,
+                    _ => bail!("Entry {} has no string \"value\"", i),
+                };
+                let weight = match object.get("weight") {
+                    Some(weight) => weight
+                        .as_f64()
+                        .ok_or_else(|| anyhow!("Entry {} has a non-numeric \"weight\"", i))?,
+                    None => 1.0,
+                };
+                Ok((value, weight))
+            }
+            _ => bail!("Entry {} is neither a string nor an object", i),
+        })
+        .collect()
+}
+
+fn parse_csv(contents: &str, column: Option<&str>) -> Result<Vec<(String, f64)>> {
+    let mut rows = contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .map(split_csv_row);
+    let header = rows.next().context("CSV file is empty")?;
+
+    let value_index = match column {
+        Some(column) => header
+            .iter()
+            .position(|name| name == column)
+            .with_context(|| format!("No column named '{}'", column))?,
+        None => 0,
+    };
+    let weight_index = header
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case("weight"))
+        .filter(|&index| index != value_index);
+
+    rows.enumerate()
+        .map(|(i, mut row)| {
+            let weight = match weight_index {
+                Some(index) => row
+                    .get(index)
+                    .and_then(|weight| weight.trim().parse().ok())
+                    .with_context(|| format!("Row {} has no valid weight", i + 2))?,
+                None => 1.0,
+            };
+            if value_index >= row.len() {
+                bail!("Row {} has no column {}", i + 2, value_index + 1);
+            }
+            Ok((row.swap_remove(value_index), weight))
+        })
+        .collect()
+}
+
+/// Splits one CSV row, honouring double quotes. Quoted fields may not span
+/// lines.
+pub(crate) fn split_csv_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// A pool file for one field, written `field=path`, or `field=path#column`
+/// to take a CSV column other than the first. Only a `#` after a `.csv` path
+/// starts a column, so other file names may contain one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolSource {
+    pub field: PoolField,
+    pub path: PathBuf,
+    pub column: Option<String>,
+}
+
+impl PoolSource {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (field, location) = spec
+            .split_once('=')
+            .with_context(|| format!("Expected field=path, got '{}'", spec))?;
+        let field = field.trim().parse().map_err(|e: String| anyhow!(e))?;
+        Ok(Self::at(field, location.trim()))
+    }
+
+    /// A source for `field` from `path` or `path#column`.
+    pub fn at(field: PoolField, location: &str) -> Self {
+        let is_csv = |path: &str| {
+            Path::new(path)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+        };
+        let (path, column) = match location.rsplit_once('#') {
+            Some((path, column)) if is_csv(path) && !column.is_empty() => {
+                (path, Some(column.to_owned()))
+            }
+            _ => (location, None),
+        };
+        Self {
+            field,
+            path: PathBuf::from(path),
+            column,
+        }
+    }
+
+    /// Resolves the path inside `dir`, refusing anything that could escape it.
+    pub fn within(mut self, dir: &Path) -> Result<Self> {
+        if !self
+            .path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!(
+                "Pool path '{}' must be relative to the pool directory",
+                self.path.display()
+            );
+        }
+        let path = dir.join(&self.path);
+        // A symlink inside the directory may still lead out of it. A path
+        // that does not resolve is left for `load` to report as missing.
+        self.path = match (path.canonicalize(), dir.canonicalize()) {
+            (Ok(resolved), Ok(root)) if !resolved.starts_with(&root) => bail!(
+                "Pool path '{}' leads outside the pool directory",
+                self.path.display()
+            ),
+            (Ok(resolved), Ok(_)) => resolved,
+            _ => path,
+        };
+        Ok(self)
+    }
+
+    pub fn load(&self) -> Result<Pool> {
+        Pool::load(&self.path, self.column.as_deref())
+            .with_context(|| format!("Failed to load the {} pool", self.field))
+    }
+}
+
+impl fmt::Display for PoolSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.field, self.path.display())?;
+        if let Some(column) = &self.column {
+            write!(f, "#{}", column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads every source, failing on the first that cannot be read.
+pub fn load_all(sources: &[PoolSource]) -> Result<PoolOverrides> {
+    sources
+        .iter()
+        .map(|source| Ok((source.field, Arc::new(source.load()?))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWS: u32 = 100_000;
+
+    /// How often each value comes up over evenly spaced draws.
+    fn counts(pool: &Pool) -> Vec<usize> {
+        let step = u32::MAX / DRAWS;
+        let mut counts = vec![0; pool.len()];
+        for i in 0..DRAWS {
+            let value = pool.pick(i * step + step / 2);
+            counts[pool.values().iter().position(|v| v == value).unwrap()] += 1;
+        }
+        counts
+    }
+
+    fn numbered(len: usize) -> Pool {
+        Pool::new((0..len).map(|i| i.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn distributions_parse_with_defaults_and_checks() {
+        assert_eq!("Uniform".parse(), Ok(Distribution::Uniform));
+        assert_eq!("weighted".parse(), Ok(Distribution::Weighted));
+        assert_eq!("zipf".parse(), Ok(Distribution::Zipf { exponent: 1.0 }));
+        assert_eq!("zipf:1.5".parse(), Ok(Distribution::Zipf { exponent: 1.5 }));
+        assert_eq!(
+            "normal".parse(),
+            Ok(Distribution::Normal {
+                mean: 0.5,
+                std_dev: 0.15
+            })
+        );
+        assert_eq!(
+            "normal:0.2:0.05".parse(),
+            Ok(Distribution::Normal {
+                mean: 0.2,
+                std_dev: 0.05
+            })
+        );
+
+        for spec in [
+            "zipf:0",
+            "zipf:-1",
+            "zipf:inf",
+            "zipf:x",
+            "normal:0.5:0",
+            "normal:0.5:0.1:1",
+            "uniform:1",
+            "pareto",
+        ] {
+            assert!(spec.parse::<Distribution>().is_err(), "{}", spec);
+        }
+
+        let (field, distribution) = Distribution::parse_for_field("industry=zipf:1.2").unwrap();
+        assert_eq!(field, PoolField::Industry);
+        assert_eq!(distribution, Distribution::Zipf { exponent: 1.2 });
+        assert_eq!(distribution.to_string().parse(), Ok(distribution));
+    }
+
+    #[test]
+    fn zipf_favours_the_first_ranks() {
+        let pool = numbered(10).with_distribution(Distribution::Zipf { exponent: 1.0 });
+        let shares = counts(&pool);
+        assert!(
+            shares.windows(2).all(|pair| pair[0] >= pair[1]),
+            "{:?}",
+            shares
+        );
+        // Rank 1 against rank 2 is 2:1 for an exponent of 1.
+        let ratio = shares[0] as f64 / shares[1] as f64;
+        assert!((ratio - 2.0).abs() < 0.05, "{}", ratio);
+        assert!(shares[9] > 0);
+
+        let steep = numbered(10).with_distribution(Distribution::Zipf { exponent: 3.0 });
+        assert!(counts(&steep)[0] > shares[0]);
+    }
+
+    #[test]
+    fn normal_peaks_at_its_mean_and_stays_in_bounds() {
+        let pool = numbered(100).with_distribution(Distribution::Normal {
+            mean: 0.25,
+            std_dev: 0.05,
+        });
+        let shares = counts(&pool);
+        let peak = (0..100).max_by_key(|&i| shares[i]).unwrap();
+        assert!((23..=26).contains(&peak), "{}", peak);
+        // About 95% of draws fall within two standard deviations.
+        let within: usize = shares[15..35].iter().sum();
+        assert!(within as f64 > 0.94 * DRAWS as f64, "{}", within);
+
+        // A curve far narrower than one position, or centred off the pool,
+        // still picks its nearest value instead of giving up.
+        let narrow = numbered(100).with_distribution(Distribution::Normal {
+            mean: 0.505,
+            std_dev: 1e-9,
+        });
+        assert_eq!(counts(&narrow)[50], DRAWS as usize);
+        let outside = numbered(100).with_distribution(Distribution::Normal {
+            mean: 5.0,
+            std_dev: 0.01,
+        });
+        assert_eq!(counts(&outside)[99], DRAWS as usize);
+        assert_eq!(outside.pick(u32::MAX), "99");
+    }
+
+    #[test]
+    fn uniform_ignores_weights_that_weighted_keeps() {
+        let pool = Pool::weighted(vec![("a".to_owned(), 1.0), ("b".to_owned(), 3.0)]).unwrap();
+
+        let weighted = counts(&pool.with_distribution(Distribution::Weighted));
+        assert!((weighted[1] as f64 / weighted[0] as f64 - 3.0).abs() < 0.05);
+        assert_eq!(weighted, counts(&pool));
+
+        let uniform = pool.with_distribution(Distribution::Uniform);
+        assert!(counts(&uniform).iter().all(|&n| n.abs_diff(50_000) <= 1));
+        assert!(uniform.is_weighted());
+        // Weighted on an unweighted pool is uniform.
+        let plain = numbered(4).with_distribution(Distribution::Weighted);
+        assert!(counts(&plain).iter().all(|&n| n.abs_diff(25_000) <= 1));
+    }
+
+    /// The directory pool files are written to for this test run.
+    fn pool_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fast-json-gen-pools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn pool_file(name: &str, contents: &str) -> PathBuf {
+        let path = pool_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load_error(name: &str, contents: &str, column: Option<&str>) -> String {
+        format!(
+            "{:#}",
+            Pool::load(&pool_file(name, contents), column).unwrap_err()
+        )
+    }
+
+    #[test]
+    fn line_files_hold_one_value_per_line() {
+        let path = pool_file("plain.txt", "Acme\r\n\n  \nGlobex\nInitech\n");
+        let pool = Pool::load(&path, None).unwrap();
+        assert_eq!(pool.values(), ["Acme", "Globex", "Initech"]);
+        assert!(!pool.is_weighted());
+
+        // A trailing tab and number is a weight, anything else stays in the value.
+        let path = pool_file("weighted.txt", "Acme\t3\nGlobex\nInitech\tCorp\n");
+        let pool = Pool::load(&path, None).unwrap();
+        assert_eq!(pool.values(), ["Acme", "Globex", "Initech\tCorp"]);
+        assert!(pool.is_weighted());
+        let shares = counts(&pool);
+        assert!((shares[0] as f64 / shares[1] as f64 - 3.0).abs() < 0.05);
+
+        let error = load_error("column.txt", "Acme\n", Some("name"));
+        assert!(
+            error.contains("Only CSV pool files have columns"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn csv_files_take_a_column_and_its_weights() {
+        let path = pool_file(
+            "cities.csv",
+            "name,city,Weight\nAcme,\"Paris, TX\",1\r\nGlobex,Oslo,4\n",
+        );
+        let first = Pool::load(&path, None).unwrap();
+        assert_eq!(first.values(), ["Acme", "Globex"]);
+        let cities = Pool::load(&path, Some("city")).unwrap();
+        assert_eq!(cities.values(), ["Paris, TX", "Oslo"]);
+        assert!(cities.is_weighted());
+        let shares = counts(&cities);
+        assert!((shares[1] as f64 / shares[0] as f64 - 4.0).abs() < 0.05);
+
+        let error = format!("{:#}", Pool::load(&path, Some("zip")).unwrap_err());
+        assert!(error.contains("No column named 'zip'"), "{}", error);
+        let error = load_error("short.csv", "name,city\nAcme\n", Some("city"));
+        assert!(error.contains("Row 2 has no column 2"), "{}", error);
+        let error = load_error("badweight.csv", "name,weight\nAcme,heavy\n", None);
+        assert!(error.contains("Row 2 has no valid weight"), "{}", error);
+    }
+
+    #[test]
+    fn json_files_hold_strings_or_weighted_objects() {
+        let path = pool_file("plain.json", r#"["Acme", "Globex"]"#);
+        let pool = Pool::load(&path, None).unwrap();
+        assert_eq!(pool.values(), ["Acme", "Globex"]);
+        assert!(!pool.is_weighted());
+
+        let path = pool_file(
+            "weighted.JSON",
+            r#"[{"value": "Acme", "weight": 2}, {"value": "Globex"}]"#,
+        );
+        let pool = Pool::load(&path, None).unwrap();
+        assert_eq!(pool.values(), ["Acme", "Globex"]);
+        assert!(pool.is_weighted());
+    }
+
+    #[test]
+    fn empty_and_malformed_files_are_refused() {
+        for (name, contents, expected) in [
+            ("empty.txt", "\n\n", "Pool has no values"),
+            ("empty.csv", "", "CSV file is empty"),
+            ("header.csv", "name\n", "Pool has no values"),
+            ("empty.json", "[]", "Pool has no values"),
+            ("broken.json", "[\"Acme\"", "EOF while parsing"),
+            ("object.json", "{}", "Expected a JSON array"),
+            (
+                "number.json",
+                "[1]",
+                "Entry 0 is neither a string nor an object",
+            ),
+            (
+                "novalue.json",
+                r#"[{"weight": 1}]"#,
+                "Entry 0 has no string",
+            ),
+            (
+                "zero.txt",
+                "Acme\t0\nGlobex\t0\n",
+                "Pool weights add up to zero",
+            ),
+            ("negative.txt", "Acme\t-1\n", "Invalid weight -1 for 'Acme'"),
+        ] {
+            let error = load_error(name, contents, None);
+            assert!(error.contains("Invalid pool file"), "{}", error);
+            assert!(error.contains(expected), "{}: {}", name, error);
+        }
+
+        let error = format!(
+            "{:#}",
+            Pool::load(&pool_dir().join("missing.txt"), None).unwrap_err()
+        );
+        assert!(error.contains("Failed to read pool file"), "{}", error);
+    }
+
+    #[test]
+    fn request_sources_stay_inside_the_pool_directory() {
+        let dir = pool_dir();
+        let source = PoolSource::parse("city = lists/cities.csv#city").unwrap();
+        assert_eq!(source.field, PoolField::City);
+        assert_eq!(source.column.as_deref(), Some("city"));
+        assert_eq!(source.to_string(), "city=lists/cities.csv#city");
+        let source = source.within(&dir).unwrap();
+        assert_eq!(source.path, dir.join("lists/cities.csv"));
+
+        // Only a CSV file has columns, so any other `#` is part of the name.
+        let source = PoolSource::at(PoolField::Name, "names#2.txt");
+        assert_eq!(
+            (source.path.to_str(), source.column),
+            (Some("names#2.txt"), None)
+        );
+        let source = PoolSource::at(PoolField::Name, "v#2/names.csv#name");
+        assert_eq!(source.path, Path::new("v#2/names.csv"));
+        assert_eq!(source.column.as_deref(), Some("name"));
+        let source = PoolSource::at(PoolField::Name, "names.csv#")
+            .within(&dir)
+            .unwrap();
+        assert_eq!(source.path, dir.join("names.csv#"));
+
+        for escape in [
+            "../cities.csv",
+            "lists/../../cities.csv",
+            "./cities.csv",
+            "/etc/passwd",
+        ] {
+            let error = PoolSource::at(PoolField::City, escape)
+                .within(&dir)
+                .unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("must be relative to the pool directory"),
+                "{}",
+                escape
+            );
+        }
+
+        #[cfg(unix)]
+        {
+            let outside = std::env::temp_dir()
+                .join(format!("fast-json-gen-outside-{}.txt", std::process::id()));
+            std::fs::write(&outside, "secret\n").unwrap();
+            let link = dir.join("linked.txt");
+            let _ = std::fs::remove_file(&link);
+            std::os::unix::fs::symlink(&outside, &link).unwrap();
+            let error = PoolSource::at(PoolField::City, "linked.txt")
+                .within(&dir)
+                .unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("leads outside the pool directory"),
+                "{}",
+                error
+            );
+
+            let inside = pool_file("inside.txt", "Paris\n");
+            std::fs::remove_file(&link).unwrap();
+            std::os::unix::fs::symlink(&inside, &link).unwrap();
+            let source = PoolSource::at(PoolField::City, "linked.txt")
+                .within(&dir)
+                .unwrap();
+            assert_eq!(source.load().unwrap().values(), ["Paris"]);
+        }
+    }
+
+    #[test]
+    fn load_all_fails_on_the_first_unreadable_source() {
+        let industries = pool_file("industries.txt", "Mining\nRetail\n");
+        let names = pool_file("names.csv", "name\nAcme\n");
+        let sources = [
+            PoolSource::at(PoolField::Industry, industries.to_str().unwrap()),
+            PoolSource::at(PoolField::Name, names.to_str().unwrap()),
+        ];
+        let overrides = load_all(&sources).unwrap();
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides[0].0, PoolField::Industry);
+        assert_eq!(overrides[0].1.values(), ["Mining", "Retail"]);
+        assert_eq!(overrides[1].1.values(), ["Acme"]);
+
+        let missing = PoolSource::at(PoolField::City, "no-such-dir/cities.txt").within(&pool_dir());
+        let sources = [sources[0].clone(), missing.unwrap()];
+        let error = format!("{:#}", load_all(&sources).unwrap_err());
+        assert!(
+            error.starts_with("Failed to load the city pool"),
+            "{}",
+            error
+        );
+    }
+}
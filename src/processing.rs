@@ -1,31 +1,427 @@
+use crate::datasets::{self, Dataset, DatasetRecord};
+use crate::gaps::{Gap, GapRates, Gaps};
+use crate::nesting::{Flatten, NestedDraws, NestingOptions};
+use crate::numeric::{scaled, NumericOptions, RevenueBuffer, RevenueType};
+use crate::pools::{Pool, PoolField, PoolOptions};
+use crate::temporal::{TimeBuffer, TimeKind, TimeOptions, TimeValues};
+use crate::unique::{KeySpace, UniqueFields};
 use bytes::{BufMut, Bytes, BytesMut};
-#[cfg(target_arch = "x86_64")]
-use fake::{
-    faker::{address::en::*, company::en::*},
-    Fake,
-};
+use fake::faker::address::raw::{CityName, CountryName, StateName};
+use fake::faker::company::raw::{Buzzword, CompanyName, Industry};
+use fake::faker::impls::address::CityNameGenFn;
+use fake::faker::internet::raw::SafeEmail;
+use fake::faker::name::raw::Name;
+use fake::locales::{Data, AR_SA, DE_DE, EN, FR_FR, JA_JP, PT_BR, PT_PT, ZH_CN, ZH_TW};
+use fake::Fake;
 use rand::Rng;
 use rand_chacha::ChaCha8Rng;
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
-};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::Serialize;
-use std::simd::{cmp::SimdPartialEq, u8x32, u8x64};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(feature = "simd")]
+use std::simd::{
+    cmp::{SimdPartialEq, SimdPartialOrd},
+    u8x32,
+};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::{info, info_span};
 
+/// Bytes scanned at once by the `simd` fast paths.
+#[cfg(any(feature = "simd", test))]
 const BYTE_COUNT: usize = 32;
-const POOL_SIZE: i32 = 1000;
-const OPTIMAL_CHUNK_SIZE: u64 = 16 * 1024;
+pub const POOL_SIZE: usize = 1000;
+pub const OPTIMAL_CHUNK_SIZE: u64 = 16 * 1024;
 const MAX_RECORDS_PER_CHUNK: u64 = (256 * 1024 * 1024) / 100;
+/// A field of a generated record, in the order fields are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordField {
+    Name,
+    Industry,
+    Revenue,
+    Employees,
+    City,
+    State,
+    Country,
+    /// The nested `contacts` array, when records have one.
+    Contacts,
+    /// The nested `tags` array, when records have one.
+    Tags,
+    /// The `date` time field, when records have one.
+    Date,
+    /// The `datetime` time field, when records have one.
+    DateTime,
+    /// The `timestamp` time field, when records have one.
+    Timestamp,
+}
+
+impl RecordField {
+    pub const ALL: [RecordField; 12] = [
+        RecordField::Name,
+        RecordField::Industry,
+        RecordField::Revenue,
+        RecordField::Employees,
+        RecordField::City,
+        RecordField::State,
+        RecordField::Country,
+        RecordField::Contacts,
+        RecordField::Tags,
+        RecordField::Date,
+        RecordField::DateTime,
+        RecordField::Timestamp,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordField::Name => "name",
+            RecordField::Industry => "industry",
+            RecordField::Revenue => "revenue",
+            RecordField::Employees => "employees",
+            RecordField::City => "city",
+            RecordField::State => "state",
+            RecordField::Country => "country",
+            RecordField::Contacts => "contacts",
+            RecordField::Tags => "tags",
+            RecordField::Date => "date",
+            RecordField::DateTime => "datetime",
+            RecordField::Timestamp => "timestamp",
+        }
+    }
+}
+
+impl FromStr for RecordField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        RecordField::ALL
+            .into_iter()
+            .find(|field| field.as_str() == name)
+            .ok_or_else(|| format!("Unknown field '{}'", s))
+    }
+}
+
+impl fmt::Display for RecordField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[repr(align(64))]
 pub struct BusinessLocationRef<'a> {
-    name: &'a str,
-    industry: &'a str,
-    revenue: f32,
+    name: Cow<'a, str>,
+    industry: Cow<'a, str>,
+    revenue: f64,
     employees: u32,
+    city: Cow<'a, str>,
+    state: Cow<'a, str>,
+    country: Cow<'a, str>,
+    gaps: Gaps,
+    contacts: Vec<ContactRef<'a>>,
+    tags: Vec<Cow<'a, str>>,
+    times: TimeValues,
+}
+
+/// One entry of a record's nested `contacts` array.
+pub struct ContactRef<'a> {
+    name: Cow<'a, str>,
+    email: Cow<'a, str>,
     city: &'a str,
-    state: &'a str,
     country: &'a str,
 }
+
+impl BusinessLocationRef<'_> {
+    fn gap(&self, field: RecordField) -> Option<Gap> {
+        self.gaps[field as usize]
+    }
+}
+
+/// One record drawn by a `StreamGenerator`, ready to write.
+enum DrawnRecord<'a> {
+    Location(BusinessLocationRef<'a>),
+    Dataset(DatasetRecord<'a>),
+}
+
+/// The random draws behind one record: one per pooled field, so fields vary
+/// independently, plus the numeric values, any gaps, any nested arrays and
+/// any time fields.
+struct RecordDraws {
+    pooled: [u32; 5],
+    revenue: f64,
+    employees: u32,
+    gaps: Gaps,
+    nested: NestedDraws,
+    times: TimeValues,
+}
+
+impl RecordDraws {
+    fn new(
+        rng: &mut ChaCha8Rng,
+        index: u64,
+        numbers: &NumericOptions,
+        gaps: &GapRates,
+        nesting: &NestingOptions,
+        times: &TimeOptions,
+    ) -> Self {
+        let pooled = rng.gen();
+        let (revenue, employees) = numbers.sample(rng);
+        let gaps = gaps.draw(rng);
+        let nested = nesting.draw(rng);
+        Self {
+            pooled,
+            revenue,
+            employees,
+            gaps,
+            nested,
+            times: times.draw(index, rng),
+        }
+    }
+
+    /// The record the draws pick from `pools`, its unique fields made unique
+    /// with `key`.
+    fn location<'a>(
+        self,
+        pools: &'a DataPools,
+        unique: &UniqueFields,
+        key: u64,
+    ) -> BusinessLocationRef<'a> {
+        let [name, industry, city, state, country] = self.pooled;
+        let pick = |field, draw| unique.value(field, pools.get(field).pick(draw), key);
+        let entry = |field, draw, position| {
+            let key = KeySpace::entry_key(key, position);
+            unique.value(field, pools.get(field).pick(draw), key)
+        };
+        BusinessLocationRef {
+            name: pick(PoolField::Name, name),
+            industry: pick(PoolField::Industry, industry),
+            revenue: self.revenue,
+            employees: self.employees,
+            city: pick(PoolField::City, city),
+            state: pick(PoolField::State, state),
+            country: pick(PoolField::Country, country),
+            gaps: self.gaps,
+            contacts: self
+                .nested
+                .contacts
+                .into_iter()
+                .enumerate()
+                .map(|(position, [name, email, city, country])| ContactRef {
+                    name: entry(PoolField::Contact, name, position),
+                    email: entry(PoolField::Email, email, position),
+                    city: pools.cities.pick(city),
+                    country: pools.countries.pick(country),
+                })
+                .collect(),
+            tags: self
+                .nested
+                .tags
+                .into_iter()
+                .enumerate()
+                .map(|(position, tag)| entry(PoolField::Tag, tag, position))
+                .collect(),
+            times: self.times,
+        }
+    }
+}
+
+/// Writes one `RecordBatch.records` entry: field 1, length-delimited, holding
+/// an encoded `BusinessLocation`. Empty strings are skipped as proto3 would,
+/// and so is any field with a gap. Revenue goes in the field for its type,
+/// after the string fields when it is not the `f32` field 3.
+fn write_location_protobuf(
+    location: &BusinessLocationRef,
+    revenue_type: RevenueType,
+    revenue_buffer: &mut RevenueBuffer,
+    buffer: &mut BytesMut,
+) {
+    const NAME: u8 = 1 << 3 | 2;
+    const INDUSTRY: u8 = 2 << 3 | 2;
+    const REVENUE: u8 = 3 << 3 | 5;
+    const EMPLOYEES: u8 = 4 << 3;
+    const CITY: u8 = 5 << 3 | 2;
+    const STATE: u8 = 6 << 3 | 2;
+    const COUNTRY: u8 = 7 << 3 | 2;
+    const REVENUE_F64: u8 = 8 << 3 | 1;
+    const REVENUE_DECIMAL: u8 = 9 << 3 | 2;
+    const REVENUE_CENTS: u8 = 10 << 3;
+
+    let text = |field, value| match location.gap(field) {
+        Some(_) => "",
+        None => value,
+    };
+    let strings = [
+        (NAME, text(RecordField::Name, &location.name)),
+        (INDUSTRY, text(RecordField::Industry, &location.industry)),
+        (CITY, text(RecordField::City, &location.city)),
+        (STATE, text(RecordField::State, &location.state)),
+        (COUNTRY, text(RecordField::Country, &location.country)),
+    ];
+    let revenue_type = location
+        .gap(RecordField::Revenue)
+        .is_none()
+        .then_some(revenue_type);
+    let employees = location
+        .gap(RecordField::Employees)
+        .is_none()
+        .then_some(location.employees as u64);
+
+    let revenue_len = match revenue_type {
+        None => 0,
+        Some(RevenueType::F32 { .. }) => 1 + 4,
+        Some(RevenueType::F64 { .. }) => 1 + 8,
+        Some(revenue_type @ RevenueType::Decimal { .. }) => {
+            let len = revenue_buffer.format(location.revenue, revenue_type).len();
+            1 + varint_len(len as u64) + len
+        }
+        Some(RevenueType::Cents) => 1 + varint_len(scaled(location.revenue, 2) as u64),
+    };
+    let mut message_len = revenue_len + employees.map_or(0, |value| 1 + varint_len(value));
+    for (_, value) in strings.iter().filter(|(_, value)| !value.is_empty()) {
+        message_len += 1 + varint_len(value.len() as u64) + value.len();
+    }
+
+    buffer.reserve(1 + varint_len(message_len as u64) + message_len);
+    buffer.put_u8(1 << 3 | 2);
+    put_varint(buffer, message_len as u64);
+
+    for (tag, value) in &strings[..2] {
+        put_string_field(buffer, *tag, value);
+    }
+    if let Some(revenue_type @ RevenueType::F32 { .. }) = revenue_type {
+        buffer.put_u8(REVENUE);
+        buffer.put_f32_le(revenue_type.rounded(location.revenue) as f32);
+    }
+    if let Some(employees) = employees {
+        buffer.put_u8(EMPLOYEES);
+        put_varint(buffer, employees);
+    }
+    for (tag, value) in &strings[2..] {
+        put_string_field(buffer, *tag, value);
+    }
+    match revenue_type {
+        None | Some(RevenueType::F32 { .. }) => {}
+        Some(revenue_type @ RevenueType::F64 { .. }) => {
+            buffer.put_u8(REVENUE_F64);
+            buffer.put_f64_le(revenue_type.rounded(location.revenue));
+        }
+        Some(revenue_type @ RevenueType::Decimal { .. }) => {
+            let value = revenue_buffer.format(location.revenue, revenue_type);
+            buffer.put_u8(REVENUE_DECIMAL);
+            put_varint(buffer, value.len() as u64);
+            buffer.put_slice(value.as_bytes());
+        }
+        Some(RevenueType::Cents) => {
+            buffer.put_u8(REVENUE_CENTS);
+            put_varint(buffer, scaled(location.revenue, 2) as u64);
+        }
+    }
+}
+
+fn put_string_field(buffer: &mut BytesMut, tag: u8, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    buffer.put_u8(tag);
+    put_varint(buffer, value.len() as u64);
+    buffer.put_slice(value.as_bytes());
+}
+
+fn put_varint(buffer: &mut BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buffer.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.put_u8(value as u8);
+}
+
+fn varint_len(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// True if `bytes` holds one of `specials` or a byte below `below`, checking
+/// whole `BYTE_COUNT` lanes at a time.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn contains_any<const N: usize>(bytes: &[u8], specials: [u8; N], below: u8) -> bool {
+    let mut lanes = bytes.chunks_exact(BYTE_COUNT);
+    for lane in &mut lanes {
+        let lane = u8x32::from_slice(lane);
+        let mut hits = lane.simd_lt(u8x32::splat(below));
+        for special in specials {
+            hits |= lane.simd_eq(u8x32::splat(special));
+        }
+        if hits.any() {
+            return true;
+        }
+    }
+    lanes
+        .remainder()
+        .iter()
+        .any(|byte| *byte < below || specials.contains(byte))
+}
+
+/// Scalar fallback for stable toolchains.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+fn contains_any<const N: usize>(bytes: &[u8], specials: [u8; N], below: u8) -> bool {
+    bytes
+        .iter()
+        .any(|byte| *byte < below || specials.contains(byte))
+}
+
+/// Appends `value` as a quoted JSON string. Quotes, backslashes and control
+/// characters are escaped; multibyte UTF-8 is valid JSON as it is.
+#[inline(always)]
+pub(crate) fn put_json_string(buffer: &mut BytesMut, value: &str) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    let bytes = value.as_bytes();
+    buffer.put_u8(b'"');
+    if !contains_any(bytes, *b"\"\\", 0x20) {
+        buffer.extend_from_slice(bytes);
+    } else {
+        for &byte in bytes {
+            match byte {
+                b'"' => buffer.put_slice(b"\\\""),
+                b'\\' => buffer.put_slice(b"\\\\"),
+                b'\n' => buffer.put_slice(b"\\n"),
+                b'\r' => buffer.put_slice(b"\\r"),
+                b'\t' => buffer.put_slice(b"\\t"),
+                0..0x20 => {
+                    buffer.put_slice(b"\\u00");
+                    buffer.put_u8(HEX[(byte >> 4) as usize]);
+                    buffer.put_u8(HEX[(byte & 0xf) as usize]);
+                }
+                _ => buffer.put_u8(byte),
+            }
+        }
+    }
+    buffer.put_u8(b'"');
+}
+
+/// Appends `value` as a CSV field, quoted as RFC 4180 requires when it holds
+/// a comma, quote or line break.
+#[inline(always)]
+pub(crate) fn put_csv_field(buffer: &mut BytesMut, value: &str) {
+    let bytes = value.as_bytes();
+    if !contains_any(bytes, *b",\"\n\r", 0) {
+        buffer.extend_from_slice(bytes);
+        return;
+    }
+
+    buffer.put_u8(b'"');
+    for part in bytes.split_inclusive(|&byte| byte == b'"') {
+        buffer.put_slice(part);
+        if part.last() == Some(&b'"') {
+            buffer.put_u8(b'"');
+        }
+    }
+    buffer.put_u8(b'"');
+}
+
 #[repr(align(64))]
 pub struct StreamGenerator<'a> {
     rng: ChaCha8Rng,
@@ -33,8 +429,25 @@ pub struct StreamGenerator<'a> {
     pretty: bool,
     format: OutputFormat,
     json_patterns: JsonPatterns,
+    numbers: NumericOptions,
+    gaps: GapRates,
+    nesting: NestingOptions,
+    times: TimeOptions,
+    unique: UniqueFields,
+    keys: KeySpace,
+    dataset: Dataset,
+    dataset_buffers: datasets::Buffers,
+    revenue_buffer: RevenueBuffer,
+    time_buffer: TimeBuffer,
+    /// Nested JSON on its way into a CSV cell.
+    scratch: BytesMut,
     bytes_generated: u64,
     chunk_size: u64,
+    target_chunk_size: u64,
+    last_chunk_records: u64,
+    line_delimited: bool,
+    records_generated: u64,
+    record_limit: u64,
 }
 
 impl<'a> StreamGenerator<'a> {
@@ -50,68 +463,208 @@ impl<'a> StreamGenerator<'a> {
             pools,
             pretty,
             format,
-            json_patterns: JsonPatterns::new(),
+            json_patterns: JsonPatterns::new(pretty),
+            numbers: NumericOptions::default(),
+            gaps: GapRates::default(),
+            nesting: NestingOptions::default(),
+            times: TimeOptions::default(),
+            unique: UniqueFields::default(),
+            keys: KeySpace::default(),
+            dataset: Dataset::default(),
+            dataset_buffers: datasets::Buffers::default(),
+            revenue_buffer: RevenueBuffer::default(),
+            time_buffer: TimeBuffer::default(),
+            scratch: BytesMut::new(),
             bytes_generated: 0,
             chunk_size,
+            target_chunk_size: OPTIMAL_CHUNK_SIZE,
+            last_chunk_records: 0,
+            line_delimited: false,
+            records_generated: 0,
+            record_limit: u64::MAX,
         }
     }
 
+    /// Number of records in the chunk most recently returned.
+    pub fn last_chunk_records(&self) -> u64 {
+        self.last_chunk_records
+    }
+
+    pub fn with_target_chunk_size(mut self, target_chunk_size: u64) -> Self {
+        self.target_chunk_size = target_chunk_size.max(1);
+        self
+    }
+
+    /// Ends every JSON record with a newline instead of separating records with
+    /// commas, so the output is newline-delimited JSON. CSV is unaffected.
+    pub fn with_line_delimited(mut self, line_delimited: bool) -> Self {
+        self.line_delimited = line_delimited;
+        self
+    }
+
+    /// Draws and writes revenue and employees as `numbers` says.
+    pub fn with_numbers(mut self, numbers: NumericOptions) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    /// Leaves fields null, missing or empty at the rates in `gaps`.
+    pub fn with_gaps(mut self, gaps: GapRates) -> Self {
+        self.gaps = gaps;
+        self
+    }
+
+    /// Adds nested objects and arrays to every record, flattened for CSV.
+    /// Protobuf records keep their fixed message, so they ignore this.
+    pub fn with_nesting(mut self, nesting: NestingOptions) -> Self {
+        self.nesting = nesting;
+        self
+    }
+
+    /// Adds date, datetime and timestamp fields to every record. A time
+    /// series numbers records from this generator's first, so callers that
+    /// want one across a whole stream generate it all from one generator.
+    /// Protobuf records ignore this too.
+    pub fn with_times(mut self, times: TimeOptions) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// Makes the values of `unique` fields unique across every generator of
+    /// an output, this one being generator `keys.stream` of `keys.streams`.
+    pub fn with_unique(mut self, unique: UniqueFields, keys: KeySpace) -> Self {
+        self.unique = unique;
+        self.keys = keys;
+        self
+    }
+
+    /// Writes records of `dataset` instead of companies. Every dataset but
+    /// business keeps its own fields, so the other options shaping records
+    /// only apply to business, besides times, whose range and series place
+    /// each dataset's own time field. Protobuf has no messages for them. Log
+    /// lines are always line-delimited, and rise in time from this
+    /// generator's first record like a time series.
+    pub fn with_dataset(mut self, dataset: Dataset) -> Self {
+        self.dataset = dataset;
+        self
+    }
+
+    /// Stops after `records` records, whichever of this and `chunk_size` is
+    /// reached first.
+    pub fn with_record_limit(mut self, records: u64) -> Self {
+        self.record_limit = records;
+        self
+    }
+
+    /// Draws record `index` of this generator's sequence from stream `index`
+    /// of its generator, so any record can be drawn on its own.
+    fn location_at(&self, index: u64) -> BusinessLocationRef<'a> {
+        let mut rng = self.rng.clone();
+        rng.set_stream(index);
+        RecordDraws::new(
+            &mut rng,
+            index,
+            &self.numbers,
+            &self.gaps,
+            &self.nesting,
+            &self.times,
+        )
+        .location(self.pools, &self.unique, self.keys.key(index))
+    }
+
+    /// Draws record `index` like `location_at`, of whichever dataset this
+    /// generator writes.
+    fn draw_at(&self, index: u64) -> DrawnRecord<'a> {
+        match self.dataset {
+            Dataset::Business => DrawnRecord::Location(self.location_at(index)),
+            dataset => {
+                let mut rng = self.rng.clone();
+                rng.set_stream(index);
+                DrawnRecord::Dataset(dataset.draw(
+                    &mut rng,
+                    index,
+                    self.keys.key(index),
+                    self.pools,
+                    &self.times,
+                ))
+            }
+        }
+    }
+
+    /// Writes one record, ending a CSV row with its newline but leaving JSON
+    /// separators to the caller.
+    fn write_record(&mut self, record: &DrawnRecord, buffer: &mut BytesMut) {
+        match record {
+            DrawnRecord::Location(location) => match self.format {
+                OutputFormat::JSON => self.write_location_json_simd(location, buffer),
+                OutputFormat::CSV => self.write_location_csv_simd(location, buffer),
+                OutputFormat::PROTOBUF => write_location_protobuf(
+                    location,
+                    self.numbers.revenue_type,
+                    &mut self.revenue_buffer,
+                    buffer,
+                ),
+            },
+            DrawnRecord::Dataset(record) => {
+                record.write(self.format, self.pretty, &mut self.dataset_buffers, buffer)
+            }
+        }
+    }
+
+    fn is_line_delimited(&self) -> bool {
+        self.line_delimited || self.dataset.is_log()
+    }
+
+    fn is_done(&self) -> bool {
+        self.bytes_generated >= self.chunk_size || self.records_generated >= self.record_limit
+    }
+
     #[inline]
     pub fn generate_chunk(&mut self) -> Option<Bytes> {
-        if self.bytes_generated >= self.chunk_size {
+        if self.is_done() {
             return None;
         }
 
-        let chunk_target = (OPTIMAL_CHUNK_SIZE).min(self.chunk_size - self.bytes_generated);
-        let max_records = (chunk_target / 100).min(MAX_RECORDS_PER_CHUNK);
+        let chunk_target = self
+            .target_chunk_size
+            .min(self.chunk_size - self.bytes_generated);
+        // At least one record, or the last few bytes would never be drawn.
+        let max_records = (chunk_target / 100)
+            .clamp(1, MAX_RECORDS_PER_CHUNK)
+            .min(self.record_limit - self.records_generated);
 
-        let random_numbers: Vec<_> = (0..max_records)
+        // Record N of this generator always comes from stream N, so chunks
+        // never repeat each other and `record_at` gives the same records.
+        let first = self.records_generated;
+        let records: Vec<_> = (first..first + max_records)
             .into_par_iter()
-            .map(|offset| {
-                let mut local_rng = self.rng.clone();
-                local_rng.set_stream(offset);
-                (
-                    offset,
-                    local_rng.gen_range(0..100),
-                    local_rng.gen_range(100000.0..100000000.0),
-                    local_rng.gen_range(10..10000),
-                    local_rng.gen_range(0..5),
-                )
-            })
+            .map(|index| self.draw_at(index))
             .collect();
 
-        let locations: Vec<_> = random_numbers
-            .into_par_iter()
-            .map(
-                |(_, base_random, revenue, employees, country_idx)| BusinessLocationRef {
-                    name: &self.pools.names[base_random],
-                    industry: &self.pools.industries[base_random],
-                    revenue,
-                    employees,
-                    city: &self.pools.cities[base_random],
-                    state: &self.pools.states[base_random],
-                    country: &self.pools.countries[country_idx],
-                },
-            )
-            .collect();
+        let mut buffer = BytesMut::with_capacity(self.target_chunk_size as usize);
+        self.last_chunk_records = 0;
 
-        let mut buffer = BytesMut::with_capacity(OPTIMAL_CHUNK_SIZE as usize);
-
-        for location in locations {
+        for record in records {
             let start_len = buffer.len();
 
             match self.format {
+                OutputFormat::JSON if self.is_line_delimited() => {
+                    self.write_record(&record, &mut buffer);
+                    buffer.put_u8(b'\n');
+                }
                 OutputFormat::JSON => {
                     buffer.put_u8(b',');
-                    self.write_location_json_simd(&location, &mut buffer);
+                    self.write_record(&record, &mut buffer);
                 }
-                OutputFormat::CSV => {
-                    self.write_location_csv_simd(&location, &mut buffer);
+                OutputFormat::CSV | OutputFormat::PROTOBUF => {
+                    self.write_record(&record, &mut buffer);
                 }
             }
 
             let bytes_written = buffer.len() - start_len;
             self.bytes_generated += bytes_written as u64;
+            self.records_generated += 1;
+            self.last_chunk_records += 1;
 
             if self.bytes_generated >= self.chunk_size {
                 break;
@@ -127,34 +680,19 @@ impl<'a> StreamGenerator<'a> {
 
     #[inline]
     pub fn generate_kickoff_chunk(&mut self) -> Option<Bytes> {
-        if self.bytes_generated >= self.chunk_size {
+        if self.is_done() {
             return None;
         }
 
-        let base_random = self.rng.gen_range(0..100);
-        let revenue = self.rng.gen_range(100000.0..100000000.0);
-        let employees = self.rng.gen_range(10..10000);
-        let country_idx = self.rng.gen_range(0..5);
-
-        let location = BusinessLocationRef {
-            name: &self.pools.names[base_random],
-            industry: &self.pools.industries[base_random],
-            revenue,
-            employees,
-            city: &self.pools.cities[base_random],
-            state: &self.pools.states[base_random],
-            country: &self.pools.countries[country_idx],
-        };
+        let record = self.draw_at(self.records_generated);
 
         let mut buffer = BytesMut::with_capacity(256);
+        self.records_generated += 1;
+        self.last_chunk_records = 1;
 
-        match self.format {
-            OutputFormat::JSON => {
-                self.write_location_json_simd(&location, &mut buffer);
-            }
-            OutputFormat::CSV => {
-                self.write_location_csv_simd(&location, &mut buffer);
-            }
+        self.write_record(&record, &mut buffer);
+        if self.format == OutputFormat::JSON && self.is_line_delimited() {
+            buffer.put_u8(b'\n');
         }
 
         if !buffer.is_empty() {
@@ -164,6 +702,20 @@ impl<'a> StreamGenerator<'a> {
         }
     }
 
+    /// Writes record `index` of this generator's sequence on its own, with no
+    /// separator or trailing newline. The same seed and index always give the
+    /// same record, so a stream can be resumed from any position.
+    pub fn record_at(&mut self, index: u64) -> Bytes {
+        let record = self.draw_at(index);
+
+        let mut buffer = BytesMut::with_capacity(256);
+        self.write_record(&record, &mut buffer);
+        if self.format == OutputFormat::CSV && buffer.last() == Some(&b'\n') {
+            buffer.truncate(buffer.len() - 1);
+        }
+        buffer.freeze()
+    }
+
     pub fn estimate_objects_per_chunk(&self) -> u64 {
         let avg_object_size = match self.format {
             OutputFormat::JSON => {
@@ -174,6 +726,7 @@ impl<'a> StreamGenerator<'a> {
                 }
             }
             OutputFormat::CSV => 100,
+            OutputFormat::PROTOBUF => 90,
         };
 
         self.chunk_size / avg_object_size
@@ -185,167 +738,105 @@ impl<'a> StreamGenerator<'a> {
         location: &BusinessLocationRef,
         buffer: &mut BytesMut,
     ) {
-        const WIDE_BYTE_COUNT: usize = 64;
-        const PARALLEL_THRESHOLD: usize = 1024;
-        const CACHE_LINE_SIZE: usize = 64;
-
-        buffer.put_u8(b'{');
-
-        let mut emp_buf = itoa::Buffer::new();
-        let mut rev_buf = dtoa::Buffer::new();
-
-        let revenue_str = rev_buf.format(location.revenue);
-        let employees_str = emp_buf.format(location.employees);
-
-        let (separator, ending) = (
-            self.json_patterns.separator_compact,
-            self.json_patterns.ending_compact,
-        );
-
-        let numeric_values = [revenue_str, employees_str];
-        let total_numeric_len: usize = numeric_values.iter().map(|s| s.len()).sum();
-
-        if total_numeric_len > PARALLEL_THRESHOLD {
-            let numeric_outputs: Vec<_> = numeric_values
-                .par_iter()
-                .enumerate()
-                .map(|(i, value)| {
-                    let mut local_buffer = BytesMut::with_capacity(
-                        value.len()
-                            + self.json_patterns.unquoted_field_patterns[i].prefix_len
-                            + separator.len(),
-                    );
-                    if i > 0 {
-                        local_buffer.extend_from_slice(&separator[..]);
-                    }
-                    local_buffer.extend_from_slice(
-                        &self.json_patterns.unquoted_field_patterns[i].prefix
-                            [..self.json_patterns.unquoted_field_patterns[i].prefix_len],
-                    );
-                    local_buffer.extend_from_slice(value.as_bytes());
-                    local_buffer
-                })
-                .collect();
+        let revenue_type = self.numbers.revenue_type;
+        let mut employees = itoa::Buffer::new();
+        let values = [
+            &*location.name,
+            &location.industry,
+            self.revenue_buffer.format(location.revenue, revenue_type),
+            employees.format(location.employees),
+            &location.city,
+            &location.state,
+            &location.country,
+        ];
 
-            for output in numeric_outputs {
-                buffer.extend_from_slice(&output[..]);
+        let patterns = &self.json_patterns;
+        let mut first = true;
+        let mut key = |buffer: &mut BytesMut, key: &[u8]| {
+            if !first {
+                buffer.put_slice(patterns.separator);
             }
+            first = false;
+            buffer.put_slice(key);
+        };
+
+        buffer.put_slice(patterns.opening);
+        // City, state and country move into `address` when it is nested.
+        let flat = if self.nesting.address {
+            4
         } else {
-            for (i, value) in numeric_values.iter().enumerate() {
-                if i > 0 {
-                    buffer.extend_from_slice(&separator[..]);
+            values.len()
+        };
+        for (i, (name, value)) in JSON_KEYS.iter().zip(values).enumerate().take(flat) {
+            let gap = location.gaps[i];
+            if gap == Some(Gap::Missing) {
+                continue;
+            }
+            key(buffer, name);
+            match gap {
+                Some(Gap::Null) => buffer.put_slice(b"null"),
+                Some(_) => buffer.put_slice(b"\"\""),
+                None if JSON_NUMERIC_FIELDS.contains(&i)
+                    && !(i == 2 && revenue_type.is_string()) =>
+                {
+                    buffer.put_slice(value.as_bytes())
                 }
-                buffer.extend_from_slice(
-                    &self.json_patterns.unquoted_field_patterns[i].prefix
-                        [..self.json_patterns.unquoted_field_patterns[i].prefix_len],
-                );
-                buffer.extend_from_slice(value.as_bytes());
+                None => put_json_string(buffer, value),
             }
         }
 
-        let string_values = [
-            location.name,
-            location.industry,
-            location.city,
-            location.state,
-            location.country,
-        ];
-
-        let total_string_len: usize = string_values.iter().map(|s| s.len()).sum();
-
-        if total_string_len > PARALLEL_THRESHOLD {
-            let string_fields_output: Vec<_> = string_values
-                .par_iter()
-                .zip(self.json_patterns.quoted_field_patterns.par_iter())
-                .map(|(value, pattern)| {
-                    let mut local_buffer = BytesMut::with_capacity(
-                        value.len() + pattern.prefix.len() + pattern.suffix.len(),
-                    );
-                    local_buffer.extend_from_slice(&pattern.prefix[..]);
-
-                    let bytes = value.as_bytes();
-                    let chunks = bytes.chunks(WIDE_BYTE_COUNT);
-
-                    for chunk in chunks {
-                        if chunk.len() == WIDE_BYTE_COUNT {
-                            let simd_chunk = u8x64::from_slice(chunk);
-                            let escape_mask = simd_chunk.simd_eq(u8x64::splat(b'"'))
-                                | simd_chunk.simd_eq(u8x64::splat(b'\\'))
-                                | simd_chunk.simd_eq(u8x64::splat(b'\n'));
-
-                            if escape_mask.any() {
-                                for (_, &byte) in chunk.iter().enumerate() {
-                                    if byte == b'"' || byte == b'\\' || byte == b'\n' {
-                                        local_buffer.put_u8(b'\\');
-                                    }
-                                    local_buffer.put_u8(byte);
-                                }
-                            } else {
-                                local_buffer.extend_from_slice(&simd_chunk.to_array());
-                            }
-                        } else if chunk.len() >= BYTE_COUNT {
-                            let simd_chunk = u8x32::from_slice(&chunk[..BYTE_COUNT]);
-                            let escape_mask = simd_chunk.simd_eq(u8x32::splat(b'"'))
-                                | simd_chunk.simd_eq(u8x32::splat(b'\\'))
-                                | simd_chunk.simd_eq(u8x32::splat(b'\n'));
-
-                            if escape_mask.any() {
-                                for (_, &byte) in chunk[..BYTE_COUNT].iter().enumerate() {
-                                    if byte == b'"' || byte == b'\\' || byte == b'\n' {
-                                        local_buffer.put_u8(b'\\');
-                                    }
-                                    local_buffer.put_u8(byte);
-                                }
-                            } else {
-                                local_buffer.extend_from_slice(&simd_chunk.to_array());
-                            }
-                            local_buffer.extend_from_slice(&chunk[BYTE_COUNT..]);
-                        } else {
-                            local_buffer.extend_from_slice(chunk);
-                        }
-                    }
-
-                    local_buffer.extend_from_slice(&pattern.suffix[..]);
-                    local_buffer
-                })
-                .collect();
-
-            for output in string_fields_output {
-                if buffer.len() % CACHE_LINE_SIZE == 0 {
-                    buffer.extend_from_slice(&separator[..]);
-                    buffer.extend_from_slice(&output[..]);
-                } else {
-                    let padding = CACHE_LINE_SIZE - (buffer.len() % CACHE_LINE_SIZE);
-                    buffer.extend_from_slice(&separator[..]);
-                    buffer.extend_from_slice(&output[..padding]);
-                    buffer.extend_from_slice(&output[padding..]);
+        let nesting = &self.nesting;
+        if nesting.address {
+            key(buffer, b"\"address\": ");
+            write_address_json(location, self.pretty, 2, buffer);
+        }
+        if nesting.contacts.is_some() {
+            let gap = location.gap(RecordField::Contacts);
+            if gap != Some(Gap::Missing) {
+                key(buffer, b"\"contacts\": ");
+                match gap {
+                    Some(gap) => put_json_gap(buffer, gap, b"[]"),
+                    None => write_contacts_json(
+                        &location.contacts,
+                        nesting.depth,
+                        self.pretty,
+                        2,
+                        buffer,
+                    ),
                 }
             }
-        } else {
-            for (pattern, value) in self
-                .json_patterns
-                .quoted_field_patterns
-                .iter()
-                .zip(string_values.iter())
-            {
-                buffer.extend_from_slice(&separator[..]);
-                buffer.extend_from_slice(&pattern.prefix[..]);
-
-                let bytes = value.as_bytes();
-                let chunks = bytes.chunks(BYTE_COUNT);
-                for chunk in chunks {
-                    if chunk.len() == BYTE_COUNT {
-                        let simd_chunk = u8x32::from_slice(chunk);
-                        buffer.extend_from_slice(&simd_chunk.to_array());
-                    } else {
-                        buffer.extend_from_slice(chunk);
-                    }
+        }
+        if nesting.tags.is_some() {
+            let gap = location.gap(RecordField::Tags);
+            if gap != Some(Gap::Missing) {
+                key(buffer, b"\"tags\": ");
+                match gap {
+                    Some(gap) => put_json_gap(buffer, gap, b"[]"),
+                    None => write_tags_json(&location.tags, self.pretty, 2, buffer),
                 }
-                buffer.extend_from_slice(&pattern.suffix[..]);
             }
         }
-
-        buffer.extend_from_slice(&ending[..]);
+        for kind in self.times.kinds() {
+            let gap = location.gap(kind.field());
+            if gap == Some(Gap::Missing) {
+                continue;
+            }
+            key(buffer, TIME_KEYS[kind as usize]);
+            if let Some(gap) = gap {
+                put_json_gap(buffer, gap, b"\"\"");
+                continue;
+            }
+            let format = &self.times.field(kind).expect("kinds are enabled").format;
+            let value = self
+                .time_buffer
+                .format(location.times[kind as usize], kind, format);
+            if format.is_string() {
+                put_json_string(buffer, value);
+            } else {
+                buffer.put_slice(value.as_bytes());
+            }
+        }
+        buffer.put_slice(patterns.ending);
     }
 
     #[inline]
@@ -356,106 +847,329 @@ impl<'a> StreamGenerator<'a> {
     ) {
         buffer.put_u8(b',');
 
+        let mut employees = itoa::Buffer::new();
         let string_fields = [
-            location.name,
-            location.industry,
-            &location.revenue.to_string(),
-            &location.employees.to_string(),
-            location.city,
-            location.state,
-            location.country,
+            &*location.name,
+            &location.industry,
+            self.revenue_buffer
+                .format(location.revenue, self.numbers.revenue_type),
+            employees.format(location.employees),
+            &location.city,
+            &location.state,
+            &location.country,
         ];
 
-        for (i, field) in string_fields.iter().enumerate() {
-            let bytes = field.as_bytes();
-            let chunks = bytes.chunks(BYTE_COUNT);
-
-            for chunk in chunks {
-                if chunk.len() == BYTE_COUNT {
-                    let simd_chunk = u8x32::from_slice(chunk);
-                    buffer.extend_from_slice(&simd_chunk.to_array());
-                } else {
-                    buffer.extend_from_slice(chunk);
+        // Flattened to JSON, city, state and country share one `address` cell.
+        let flat = if self.nesting.address && self.nesting.flatten == Flatten::Json {
+            4
+        } else {
+            string_fields.len()
+        };
+        for (i, field) in string_fields.iter().enumerate().take(flat) {
+            if i > 0 {
+                buffer.put_u8(b',');
+            }
+            // Null and missing both leave the cell empty; an empty value is
+            // quoted so it can be told apart.
+            match location.gaps[i] {
+                Some(Gap::Null | Gap::Missing) => {}
+                Some(Gap::Empty) => buffer.put_slice(b"\"\""),
+                None => put_csv_field(buffer, field),
+            }
+        }
+        if self.nesting.is_nested() {
+            write_nested_csv(location, &self.nesting, &mut self.scratch, buffer);
+        }
+        for kind in self.times.kinds() {
+            buffer.put_u8(b',');
+            match location.gap(kind.field()) {
+                Some(Gap::Null | Gap::Missing) => {}
+                Some(Gap::Empty) => buffer.put_slice(b"\"\""),
+                None => {
+                    let format = &self.times.field(kind).expect("kinds are enabled").format;
+                    let value =
+                        self.time_buffer
+                            .format(location.times[kind as usize], kind, format);
+                    put_csv_field(buffer, value);
                 }
             }
+        }
 
-            if i < string_fields.len() - 1 {
-                buffer.put_u8(b',');
+        buffer.put_u8(b'\n');
+    }
+}
+
+/// An object or array below the top level of a JSON record. Pretty-printed,
+/// its entries at `level` are indented by `2 + 2 * level` spaces, so they
+/// line up with the record's own fields at level 1.
+struct JsonContainer {
+    level: usize,
+    pretty: bool,
+    empty: bool,
+}
+
+impl JsonContainer {
+    fn open(buffer: &mut BytesMut, bracket: u8, pretty: bool, level: usize) -> Self {
+        buffer.put_u8(bracket);
+        Self {
+            level,
+            pretty,
+            empty: true,
+        }
+    }
+
+    /// Starts the next entry.
+    fn entry(&mut self, buffer: &mut BytesMut) {
+        if !self.empty {
+            buffer.put_u8(b',');
+        }
+        self.empty = false;
+        if self.pretty {
+            put_indent(buffer, self.level);
+        }
+    }
+
+    /// Starts the next entry with `key`, given with its quotes and colon.
+    fn key(&mut self, buffer: &mut BytesMut, key: &[u8]) {
+        self.entry(buffer);
+        buffer.put_slice(key);
+    }
+
+    fn close(self, buffer: &mut BytesMut, bracket: u8) {
+        if self.pretty && !self.empty {
+            put_indent(buffer, self.level - 1);
+        }
+        buffer.put_u8(bracket);
+    }
+}
+
+fn put_indent(buffer: &mut BytesMut, level: usize) {
+    buffer.put_u8(b'\n');
+    buffer.put_bytes(b' ', 2 + 2 * level);
+}
+
+/// Writes the JSON value a gap leaves, with `empty` for an empty one.
+fn put_json_gap(buffer: &mut BytesMut, gap: Gap, empty: &[u8]) {
+    match gap {
+        Gap::Null => buffer.put_slice(b"null"),
+        Gap::Missing | Gap::Empty => buffer.put_slice(empty),
+    }
+}
+
+/// Writes `{"city": ..., "state": ..., "country": ...}` with the record's
+/// gaps, its entries at `level`.
+fn write_address_json(
+    location: &BusinessLocationRef,
+    pretty: bool,
+    level: usize,
+    buffer: &mut BytesMut,
+) {
+    let place = [
+        (RecordField::City, &location.city),
+        (RecordField::State, &location.state),
+        (RecordField::Country, &location.country),
+    ];
+    let mut address = JsonContainer::open(buffer, b'{', pretty, level);
+    for (field, value) in place {
+        match location.gap(field) {
+            Some(Gap::Missing) => {}
+            Some(gap) => {
+                address.key(buffer, JSON_KEYS[field as usize]);
+                put_json_gap(buffer, gap, b"\"\"");
+            }
+            None => {
+                address.key(buffer, JSON_KEYS[field as usize]);
+                put_json_string(buffer, value);
             }
         }
+    }
+    address.close(buffer, b'}');
+}
 
-        buffer.put_u8(b'\n');
+/// Writes a `contacts` array, its entries at `level`: emails at depth 1,
+/// `name` and `email` objects at depth 2, and those with an `address` object
+/// at depth 3.
+fn write_contacts_json(
+    contacts: &[ContactRef],
+    depth: u8,
+    pretty: bool,
+    level: usize,
+    buffer: &mut BytesMut,
+) {
+    let mut array = JsonContainer::open(buffer, b'[', pretty, level);
+    for contact in contacts {
+        array.entry(buffer);
+        if depth == 1 {
+            put_json_string(buffer, &contact.email);
+            continue;
+        }
+
+        let mut object = JsonContainer::open(buffer, b'{', pretty, level + 1);
+        object.key(buffer, b"\"name\": ");
+        put_json_string(buffer, &contact.name);
+        object.key(buffer, b"\"email\": ");
+        put_json_string(buffer, &contact.email);
+        if depth >= 3 {
+            object.key(buffer, b"\"address\": ");
+            let mut address = JsonContainer::open(buffer, b'{', pretty, level + 2);
+            address.key(buffer, b"\"city\": ");
+            put_json_string(buffer, contact.city);
+            address.key(buffer, b"\"country\": ");
+            put_json_string(buffer, contact.country);
+            address.close(buffer, b'}');
+        }
+        object.close(buffer, b'}');
     }
+    array.close(buffer, b']');
 }
 
-pub struct JsonPatterns {
-    separator_compact: [u8; 32],
-    ending_compact: [u8; 32],
-    quoted_field_patterns: [QuotedFieldPattern; 5],
-    unquoted_field_patterns: [UnquotedFieldPattern; 3],
+/// Writes a `tags` array, its entries at `level`.
+fn write_tags_json(tags: &[Cow<str>], pretty: bool, level: usize, buffer: &mut BytesMut) {
+    let mut array = JsonContainer::open(buffer, b'[', pretty, level);
+    for tag in tags {
+        array.entry(buffer);
+        put_json_string(buffer, tag);
+    }
+    array.close(buffer, b']');
 }
 
-impl JsonPatterns {
-    pub fn new() -> Self {
-        let mut aligned = AlignedPatterns {
-            numeric_prefixes: [[0; 64]; 3],
-            string_prefixes: [[0; 64]; 5],
-            string_suffixes: [[0; 64]; 5],
-        };
+/// Writes the CSV cells of a record's nested structures after its flat ones:
+/// a column per leaf when dotted, or compact JSON cells, quoted as CSV needs.
+fn write_nested_csv(
+    location: &BusinessLocationRef,
+    nesting: &NestingOptions,
+    scratch: &mut BytesMut,
+    buffer: &mut BytesMut,
+) {
+    let mut json_cell = |buffer: &mut BytesMut, write: &dyn Fn(&mut BytesMut)| {
+        scratch.clear();
+        write(scratch);
+        buffer.put_u8(b',');
+        put_csv_field(
+            buffer,
+            std::str::from_utf8(scratch).expect("JSON is written as UTF-8"),
+        );
+    };
 
-        for (i, field) in ["id", "revenue", "employees"].iter().enumerate() {
-            aligned.numeric_prefixes[i][0] = b'"';
-            aligned.numeric_prefixes[i][1..1 + field.len()].copy_from_slice(field.as_bytes());
-            aligned.numeric_prefixes[i][1 + field.len()..1 + field.len() + 3]
-                .copy_from_slice(b"\": ");
+    if nesting.address && nesting.flatten == Flatten::Json {
+        json_cell(buffer, &|scratch| {
+            write_address_json(location, false, 0, scratch)
+        });
+    }
+
+    if let Some(lengths) = nesting.contacts {
+        let gap = location.gap(RecordField::Contacts);
+        match (nesting.flatten, gap) {
+            (Flatten::Dotted, _) => {
+                let contacts = if gap.is_some() {
+                    &[][..]
+                } else {
+                    &location.contacts[..]
+                };
+                let columns = match nesting.depth {
+                    1 => 1,
+                    2 => 2,
+                    _ => 4,
+                };
+                for i in 0..lengths.max as usize {
+                    let Some(contact) = contacts.get(i) else {
+                        buffer.put_bytes(b',', columns);
+                        continue;
+                    };
+                    let values = [
+                        &*contact.name,
+                        &contact.email,
+                        contact.city,
+                        contact.country,
+                    ];
+                    let values = if columns == 1 {
+                        &values[1..2]
+                    } else {
+                        &values[..columns]
+                    };
+                    for value in values {
+                        buffer.put_u8(b',');
+                        put_csv_field(buffer, value);
+                    }
+                }
+            }
+            (Flatten::Json, Some(Gap::Null | Gap::Missing)) => buffer.put_u8(b','),
+            (Flatten::Json, Some(Gap::Empty)) => buffer.put_slice(b",[]"),
+            (Flatten::Json, None) => json_cell(buffer, &|scratch| {
+                write_contacts_json(&location.contacts, nesting.depth, false, 0, scratch)
+            }),
         }
+    }
 
-        for (i, field) in ["name", "industry", "city", "state", "country"]
-            .iter()
-            .enumerate()
-        {
-            aligned.string_prefixes[i][0] = b'"';
-            aligned.string_prefixes[i][1..1 + field.len()].copy_from_slice(field.as_bytes());
-            aligned.string_prefixes[i][1 + field.len()..1 + field.len() + 4]
-                .copy_from_slice(b"\": \"");
-            aligned.string_suffixes[i][0] = b'"';
+    if let Some(lengths) = nesting.tags {
+        let gap = location.gap(RecordField::Tags);
+        match (nesting.flatten, gap) {
+            (Flatten::Dotted, _) => {
+                let tags = if gap.is_some() {
+                    &[][..]
+                } else {
+                    &location.tags[..]
+                };
+                for i in 0..lengths.max as usize {
+                    buffer.put_u8(b',');
+                    if let Some(tag) = tags.get(i) {
+                        put_csv_field(buffer, tag);
+                    }
+                }
+            }
+            (Flatten::Json, Some(Gap::Null | Gap::Missing)) => buffer.put_u8(b','),
+            (Flatten::Json, Some(Gap::Empty)) => buffer.put_slice(b",[]"),
+            (Flatten::Json, None) => json_cell(buffer, &|scratch| {
+                write_tags_json(&location.tags, false, 0, scratch)
+            }),
         }
+    }
+}
 
-        // Initialize separators
-        let mut separator_pretty = [0u8; 32];
-        separator_pretty[..6].copy_from_slice(b",\n    ");
-        let mut separator_compact = [0u8; 32];
-        separator_compact[0] = b',';
+/// Keys of a JSON record, in the order fields are written.
+const JSON_KEYS: [&[u8]; 7] = [
+    b"\"name\": ",
+    b"\"industry\": ",
+    b"\"revenue\": ",
+    b"\"employees\": ",
+    b"\"city\": ",
+    b"\"state\": ",
+    b"\"country\": ",
+];
+/// Keys of a JSON record's time fields, indexed by `TimeKind`.
+const TIME_KEYS: [&[u8]; TimeKind::ALL.len()] =
+    [b"\"date\": ", b"\"datetime\": ", b"\"timestamp\": "];
+/// Positions in `JSON_KEYS` whose values are written unquoted, unless revenue
+/// is a decimal string.
+const JSON_NUMERIC_FIELDS: [usize; 2] = [2, 3];
 
-        let mut ending_pretty = [0u8; 32];
-        ending_pretty[..4].copy_from_slice(b"\n  }");
-        let mut ending_compact = [0u8; 32];
-        ending_compact[0] = b'}';
+/// The bytes around a JSON record's fields, compact or pretty-printed.
+pub struct JsonPatterns {
+    opening: &'static [u8],
+    separator: &'static [u8],
+    ending: &'static [u8],
+}
 
-        Self {
-            separator_compact,
-            ending_compact,
-            quoted_field_patterns: aligned
-                .string_prefixes
-                .iter()
-                .zip(aligned.string_suffixes.iter())
-                .map(|(prefix, suffix)| QuotedFieldPattern {
-                    prefix: prefix[..32].try_into().unwrap(),
-                    suffix: suffix[..32].try_into().unwrap(),
-                })
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-            unquoted_field_patterns: aligned
-                .numeric_prefixes
-                .iter()
-                .map(|prefix| UnquotedFieldPattern {
-                    prefix: prefix[..32].try_into().unwrap(),
-                    prefix_len: 32,
-                })
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
+impl Default for JsonPatterns {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl JsonPatterns {
+    pub fn new(pretty: bool) -> Self {
+        if pretty {
+            Self {
+                opening: b"{\n    ",
+                separator: b",\n    ",
+                ending: b"\n  }",
+            }
+        } else {
+            Self {
+                opening: b"{",
+                separator: b",",
+                ending: b"}",
+            }
         }
     }
 }
@@ -472,68 +1186,553 @@ pub struct BusinessLocation {
     pub country: String,
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OutputFormat {
     JSON,
     CSV,
+    /// Records as `RecordBatch.records` entries, see `proto/generator.proto`.
+    /// Any run of whole records is itself an encoded `RecordBatch`.
+    PROTOBUF,
 }
 
 impl OutputFormat {
-    pub fn from_str(s: &str) -> Self {
+    pub const ALL: [OutputFormat; 3] = [
+        OutputFormat::JSON,
+        OutputFormat::CSV,
+        OutputFormat::PROTOBUF,
+    ];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "csv" => OutputFormat::CSV,
-            _ => OutputFormat::JSON,
+            "json" => Ok(OutputFormat::JSON),
+            "csv" => Ok(OutputFormat::CSV),
+            "protobuf" | "proto" => Ok(OutputFormat::PROTOBUF),
+            other => Err(format!("Unknown format '{}'", other)),
         }
     }
+}
+
+impl OutputFormat {
     pub fn to_string(&self) -> &str {
         match self {
             OutputFormat::JSON => "JSON",
             OutputFormat::CSV => "CSV",
+            OutputFormat::PROTOBUF => "PROTOBUF",
         }
     }
 
-    pub fn content_type(&self) -> &str {
+    pub fn content_type(&self) -> &'static str {
         match self {
             OutputFormat::JSON => "application/json",
             OutputFormat::CSV => "text/csv",
+            OutputFormat::PROTOBUF => "application/x-protobuf",
         }
     }
 }
 
+/// The `fake` locale data pools are sampled from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locale {
+    En,
+    FrFr,
+    DeDe,
+    JaJp,
+    ZhCn,
+    ZhTw,
+    PtBr,
+    PtPt,
+    ArSa,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 9] = [
+        Locale::En,
+        Locale::FrFr,
+        Locale::DeDe,
+        Locale::JaJp,
+        Locale::ZhCn,
+        Locale::ZhTw,
+        Locale::PtBr,
+        Locale::PtPt,
+        Locale::ArSa,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::FrFr => "fr_FR",
+            Locale::DeDe => "de_DE",
+            Locale::JaJp => "ja_JP",
+            Locale::ZhCn => "zh_CN",
+            Locale::ZhTw => "zh_TW",
+            Locale::PtBr => "pt_BR",
+            Locale::PtPt => "pt_PT",
+            Locale::ArSa => "ar_SA",
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Accepts `fr_FR`, `fr-fr` and the like. Any English variant, such as
+    /// `en_US`, maps to `en`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.replace('-', "_").to_lowercase();
+        if name == "en" || name.starts_with("en_") {
+            return Ok(Locale::En);
+        }
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.as_str().to_lowercase() == name)
+            .ok_or_else(|| format!("Unknown locale '{}'", s))
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The pools each record's strings are borrowed from. Pools are shared, so
+/// replacing a few with `with_overrides` is cheap.
 #[repr(align(64))]
+#[derive(Clone)]
 pub struct DataPools {
-    pub names: Vec<String>,
-    pub cities: Vec<String>,
-    pub states: Vec<String>,
-    pub countries: Vec<String>,
-    pub industries: Vec<String>,
+    pub names: Arc<Pool>,
+    pub cities: Arc<Pool>,
+    pub states: Arc<Pool>,
+    pub countries: Arc<Pool>,
+    pub industries: Arc<Pool>,
+    pub contacts: Arc<Pool>,
+    pub emails: Arc<Pool>,
+    pub tags: Arc<Pool>,
+}
+
+impl Default for DataPools {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DataPools {
     pub fn new() -> Self {
+        Self::with_size(POOL_SIZE)
+    }
+
+    pub fn with_size(pool_size: usize) -> Self {
+        Self::with_locale(pool_size, Locale::En, None)
+    }
+
+    /// Samples the pools from `seed`, so the same seed always gives the same pools.
+    pub fn with_seed(pool_size: usize, seed: u64) -> Self {
+        Self::with_locale(pool_size, Locale::En, Some(seed))
+    }
+
+    /// Samples the pools from `locale`'s data, from `seed` if one is given.
+    /// Fields a locale has no data for fall back to English, as in `fake`.
+    pub fn with_locale(pool_size: usize, locale: Locale, seed: Option<u64>) -> Self {
+        use fake::rand::{rngs::StdRng, SeedableRng};
+        match seed {
+            Some(seed) => Self::sample(pool_size, locale, &mut StdRng::seed_from_u64(seed)),
+            None => Self::sample(pool_size, locale, &mut fake::rand::rng()),
+        }
+    }
+
+    fn sample<R: fake::Rng>(pool_size: usize, locale: Locale, rng: &mut R) -> Self {
+        match locale {
+            Locale::En => Self::with_rng(pool_size, EN, rng),
+            Locale::FrFr => Self::with_rng(pool_size, FR_FR, rng),
+            Locale::DeDe => Self::with_rng(pool_size, DE_DE, rng),
+            Locale::JaJp => Self::with_rng(pool_size, JA_JP, rng),
+            Locale::ZhCn => Self::with_rng(pool_size, ZH_CN, rng),
+            Locale::ZhTw => Self::with_rng(pool_size, ZH_TW, rng),
+            Locale::PtBr => Self::with_rng(pool_size, PT_BR, rng),
+            Locale::PtPt => Self::with_rng(pool_size, PT_PT, rng),
+            Locale::ArSa => Self::with_rng(pool_size, AR_SA, rng),
+        }
+    }
+
+    fn with_rng<L, R>(pool_size: usize, locale: L, rng: &mut R) -> Self
+    where
+        L: Data + CityNameGenFn + Copy,
+        R: fake::Rng,
+    {
+        let mut sample = |count: usize, fake: &dyn Fn(&mut R) -> String| {
+            Arc::new(Pool::from(
+                (0..count).map(|_| fake(rng)).collect::<Vec<_>>(),
+            ))
+        };
         DataPools {
-            names: (0..POOL_SIZE).map(|_| CompanyName().fake()).collect(),
-            industries: (0..POOL_SIZE).map(|_| Industry().fake()).collect(),
-            cities: (0..POOL_SIZE).map(|_| CityName().fake()).collect(),
-            states: (0..POOL_SIZE).map(|_| StateName().fake()).collect(),
-            countries: (0..50).map(|_| CountryName().fake()).collect(),
+            names: sample(pool_size, &|rng| CompanyName(locale).fake_with_rng(rng)),
+            industries: sample(pool_size, &|rng| Industry(locale).fake_with_rng(rng)),
+            cities: sample(pool_size, &|rng| CityName(locale).fake_with_rng(rng)),
+            states: sample(pool_size, &|rng| StateName(locale).fake_with_rng(rng)),
+            countries: sample(50, &|rng| CountryName(locale).fake_with_rng(rng)),
+            contacts: sample(pool_size, &|rng| Name(locale).fake_with_rng(rng)),
+            emails: sample(pool_size, &|rng| SafeEmail(locale).fake_with_rng(rng)),
+            tags: sample(100, &|rng| Buzzword(locale).fake_with_rng(rng)),
         }
     }
+
+    pub fn get(&self, field: PoolField) -> &Arc<Pool> {
+        match field {
+            PoolField::Name => &self.names,
+            PoolField::Industry => &self.industries,
+            PoolField::City => &self.cities,
+            PoolField::State => &self.states,
+            PoolField::Country => &self.countries,
+            PoolField::Contact => &self.contacts,
+            PoolField::Email => &self.emails,
+            PoolField::Tag => &self.tags,
+        }
+    }
+
+    pub fn set(&mut self, field: PoolField, pool: Arc<Pool>) {
+        let slot = match field {
+            PoolField::Name => &mut self.names,
+            PoolField::Industry => &mut self.industries,
+            PoolField::City => &mut self.cities,
+            PoolField::State => &mut self.states,
+            PoolField::Country => &mut self.countries,
+            PoolField::Contact => &mut self.contacts,
+            PoolField::Email => &mut self.emails,
+            PoolField::Tag => &mut self.tags,
+        };
+        *slot = pool;
+    }
+
+    /// These pools with some fields' pools replaced.
+    pub fn with_overrides(&self, overrides: &[(PoolField, Arc<Pool>)]) -> Self {
+        let mut pools = self.clone();
+        for (field, pool) in overrides {
+            pools.set(*field, pool.clone());
+        }
+        pools
+    }
+
+    /// These pools with `options` applied: overrides first, then
+    /// distributions over the resulting pools.
+    pub fn customize(&self, options: &PoolOptions) -> Self {
+        let mut pools = self.with_overrides(&options.overrides);
+        for (field, distribution) in &options.distributions {
+            let pool = pools.get(*field).with_distribution(*distribution);
+            pools.set(*field, Arc::new(pool));
+        }
+        pools
+    }
 }
 
-#[derive(Debug)]
-struct QuotedFieldPattern {
-    prefix: [u8; 32],
-    suffix: [u8; 32],
+/// Process-wide data pools, one set per locale. Each set is built once on a
+/// background thread, either at startup or when a request first asks for its
+/// locale.
+pub struct PoolCache {
+    pool_size: usize,
+    options: Arc<PoolOptions>,
+    pools: Mutex<HashMap<Locale, Arc<OnceLock<Arc<DataPools>>>>>,
 }
-#[derive(Debug)]
-struct UnquotedFieldPattern {
-    prefix: [u8; 32],
-    prefix_len: usize,
+
+impl PoolCache {
+    /// `options` apply to the pools of every locale, e.g. replacing a field's
+    /// sampled values with a pool file.
+    pub fn new(pool_size: usize, options: PoolOptions) -> Self {
+        Self {
+            pool_size,
+            options: Arc::new(options),
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts building the pools for `locale` on a background thread, unless
+    /// they are already built or being built.
+    pub fn warm_up(&self, locale: Locale) {
+        let slot = {
+            let mut pools = self.pools.lock().unwrap();
+            if pools.contains_key(&locale) {
+                return;
+            }
+            pools.entry(locale).or_default().clone()
+        };
+        let pool_size = self.pool_size;
+        let options = self.options.clone();
+        std::thread::spawn(move || {
+            let _span = info_span!("pool_construction", pool_size, %locale).entered();
+            let started = Instant::now();
+            slot.get_or_init(|| {
+                Arc::new(DataPools::with_locale(pool_size, locale, None).customize(&options))
+            });
+            info!(
+                pool_size,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "data pools warmed up"
+            );
+        });
+    }
+
+    /// Pools for `locale` sampled from `seed`, with the same size and options
+    /// as the cached ones. They are built on every call, not cached, so the
+    /// same seed gives the same pools on every instance and after a restart.
+    pub fn seeded(&self, locale: Locale, seed: u64) -> DataPools {
+        DataPools::with_locale(self.pool_size, locale, Some(seed)).customize(&self.options)
+    }
+
+    pub fn is_ready(&self, locale: Locale) -> bool {
+        self.lookup(locale).is_some()
+    }
+
+    /// The pools for `locale`, or `None` while they are being built. Asking
+    /// for a locale that has not been built yet starts building it.
+    pub fn get(&self, locale: Locale) -> Option<Arc<DataPools>> {
+        let pools = self.lookup(locale);
+        if pools.is_none() {
+            self.warm_up(locale);
+        }
+        pools
+    }
+
+    fn lookup(&self, locale: Locale) -> Option<Arc<DataPools>> {
+        self.pools.lock().unwrap().get(&locale)?.get().cloned()
+    }
 }
-#[repr(align(64))]
-struct AlignedPatterns {
-    numeric_prefixes: [[u8; 64]; 3],
-    string_prefixes: [[u8; 64]; 5],
-    string_suffixes: [[u8; 64]; 5],
+
+#[cfg(all(test, feature = "server"))]
+impl PoolCache {
+    /// A small cache with the pools for `locale` already built.
+    pub(crate) async fn ready(locale: Locale) -> Arc<Self> {
+        let pools = Arc::new(Self::new(100, PoolOptions::default()));
+        pools.warm_up(locale);
+        pools.until_ready(locale).await;
+        pools
+    }
+
+    /// Waits for the pools `warm_up` started building for `locale`.
+    pub(crate) async fn until_ready(&self, locale: Locale) {
+        while !self.is_ready(locale) {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn json(value: &str) -> String {
+        let mut buffer = BytesMut::new();
+        put_json_string(&mut buffer, value);
+        String::from_utf8(buffer.to_vec()).unwrap()
+    }
+
+    fn csv(value: &str) -> String {
+        let mut buffer = BytesMut::new();
+        put_csv_field(&mut buffer, value);
+        String::from_utf8(buffer.to_vec()).unwrap()
+    }
+
+    /// `special` at every position of a string a few lanes long, so both the
+    /// lanes and the remainder get checked.
+    fn placements(special: &str) -> impl Iterator<Item = String> + '_ {
+        (0..3 * BYTE_COUNT).map(move |at| {
+            let mut value = "x".repeat(3 * BYTE_COUNT);
+            value.insert_str(at, special);
+            value
+        })
+    }
+
+    #[test]
+    fn lane_scans_agree_with_a_byte_by_byte_scan() {
+        let scalar = |bytes: &[u8], specials: &[u8], below: u8| {
+            bytes
+                .iter()
+                .any(|byte| *byte < below || specials.contains(byte))
+        };
+        for len in 1..=2 * BYTE_COUNT + 1 {
+            for at in 0..len {
+                for byte in 0..=u8::MAX {
+                    let mut bytes = vec![b'x'; len];
+                    bytes[at] = byte;
+                    assert_eq!(
+                        contains_any(&bytes, *b"\"\\", 0x20),
+                        scalar(&bytes, b"\"\\", 0x20),
+                        "{:?} at {} of {}",
+                        byte,
+                        at,
+                        len
+                    );
+                    assert_eq!(
+                        contains_any(&bytes, *b",\"\n\r", 0),
+                        scalar(&bytes, b",\"\n\r", 0),
+                        "{:?} at {} of {}",
+                        byte,
+                        at,
+                        len
+                    );
+                }
+            }
+        }
+        assert!(!contains_any(&[], *b"\"", 0x20));
+    }
+
+    #[test]
+    fn json_strings_escape_quotes_backslashes_and_controls() {
+        assert_eq!(json("plain"), r#""plain""#);
+        assert_eq!(json(r#"say "hi" \ now"#), r#""say \"hi\" \\ now""#);
+        assert_eq!(json("a\nb\rc\td"), r#""a\nb\rc\td""#);
+        assert_eq!(json("\u{0}\u{1f}\u{7f}"), "\"\\u0000\\u001f\u{7f}\"");
+
+        for value in ["\"", "\\", "\n", "\u{1}", "東京", "😀"]
+            .into_iter()
+            .flat_map(placements)
+        {
+            let parsed: String = serde_json::from_str(&json(&value)).unwrap();
+            assert_eq!(parsed, value);
+        }
+    }
+
+    #[test]
+    fn json_strings_keep_multibyte_text_as_it_is() {
+        for value in ["東京都", "São Paulo", "الرياض", "Zürich 😀"] {
+            assert_eq!(json(value), format!("\"{}\"", value));
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_they_need_it() {
+        assert_eq!(csv("plain"), "plain");
+        assert_eq!(csv("東京都"), "東京都");
+        assert_eq!(csv("a,b"), "\"a,b\"");
+        assert_eq!(csv(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(csv("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv("cr\r"), "\"cr\r\"");
+        assert_eq!(csv("tab\tis fine"), "tab\tis fine");
+
+        for value in [",", "\"", "\n", "\r"].into_iter().flat_map(placements) {
+            let field = csv(&value);
+            let inner = &field[1..field.len() - 1];
+            assert!(
+                field.starts_with('"') && field.ends_with('"'),
+                "{:?}",
+                field
+            );
+            assert_eq!(inner.replace("\"\"", "\""), value);
+        }
+    }
+
+    fn nested() -> NestingOptions {
+        NestingOptions {
+            address: true,
+            contacts: Some(crate::nesting::Lengths { min: 0, max: 3 }),
+            tags: Some(crate::nesting::Lengths { min: 1, max: 2 }),
+            depth: 3,
+            flatten: Flatten::Dotted,
+        }
+    }
+
+    #[test]
+    fn nested_records_are_valid_json_compact_or_pretty() {
+        let pools = DataPools::with_seed(100, 1);
+        let mut gaps = GapRates::default();
+        gaps.set(RecordField::City, Gap::Missing, 0.3).unwrap();
+        gaps.set(RecordField::Contacts, Gap::Null, 0.2).unwrap();
+        gaps.set(RecordField::Tags, Gap::Empty, 0.2).unwrap();
+        for pretty in [false, true] {
+            let mut generator = StreamGenerator::new(
+                ChaCha8Rng::seed_from_u64(1),
+                &pools,
+                pretty,
+                OutputFormat::JSON,
+                u64::MAX,
+            )
+            .with_nesting(nested())
+            .with_gaps(gaps);
+            for index in 0..200 {
+                let record: serde_json::Value =
+                    serde_json::from_slice(&generator.record_at(index)).unwrap();
+                assert!(record.get("city").is_none());
+                assert!(record["address"]["country"].is_string());
+                match &record["contacts"] {
+                    serde_json::Value::Null => {}
+                    contacts => {
+                        let contacts = contacts.as_array().unwrap();
+                        assert!(contacts.len() <= 3);
+                        for contact in contacts {
+                            assert!(contact["email"].is_string());
+                            assert!(contact["address"]["city"].is_string());
+                        }
+                    }
+                }
+                assert!(record["tags"].as_array().unwrap().len() <= 2);
+            }
+        }
+    }
+
+    #[test]
+    fn flattened_csv_rows_match_the_header() {
+        let pools = DataPools::with_seed(100, 1);
+        for flatten in [Flatten::Dotted, Flatten::Json] {
+            let nesting = NestingOptions {
+                flatten,
+                ..nested()
+            };
+            let header = nesting.csv_header();
+            let columns = header.split(|&byte| byte == b',').count();
+            let mut generator = StreamGenerator::new(
+                ChaCha8Rng::seed_from_u64(1),
+                &pools,
+                false,
+                OutputFormat::CSV,
+                u64::MAX,
+            )
+            .with_nesting(nesting);
+            for index in 0..100 {
+                let record = generator.record_at(index);
+                let cells = crate::pools::split_csv_row(std::str::from_utf8(&record).unwrap());
+                assert_eq!(cells.len(), columns, "{:?}", cells);
+                if flatten == Flatten::Json {
+                    let tags: Vec<String> = serde_json::from_str(&cells[7]).unwrap();
+                    assert!(!tags.is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn records_in_other_scripts_are_valid_json() {
+        for locale in [Locale::JaJp, Locale::ArSa, Locale::ZhTw, Locale::FrFr] {
+            let pools = DataPools::with_locale(100, locale, Some(1));
+            let mut generator = StreamGenerator::new(
+                ChaCha8Rng::seed_from_u64(1),
+                &pools,
+                false,
+                OutputFormat::JSON,
+                u64::MAX,
+            );
+            for index in 0..100 {
+                let record = generator.record_at(index);
+                serde_json::from_slice::<serde_json::Value>(&record).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_reach_the_size_even_when_little_is_left() {
+        let pools = DataPools::with_locale(100, Locale::En, Some(1));
+        for format in OutputFormat::ALL {
+            for size in [1, 99, 150, 1000] {
+                let mut generator =
+                    StreamGenerator::new(ChaCha8Rng::seed_from_u64(1), &pools, false, format, size);
+                let mut written = 0;
+                while let Some(chunk) = generator.generate_chunk() {
+                    written += chunk.len() as u64;
+                }
+                assert!(written >= size, "{} of {} as {:?}", written, size, format);
+            }
+        }
+    }
 }
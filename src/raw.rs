@@ -0,0 +1,302 @@
+use crate::config::BindAddress;
+use crate::pipeline::{GenerationRequest, GenerationService};
+use crate::shutdown::ShutdownCoordinator;
+use actix_web::web;
+use anyhow::{bail, Context};
+use bytes::{Buf, Bytes};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, IoSlice};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinSet;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HANDSHAKE_LEN: u64 = 4096;
+/// Most chunks gathered into a single `writev`.
+const MAX_IOVECS: usize = 64;
+
+/// Reads `/generate`-style parameters from a handshake line such as
+/// `size=1gb&format=csv`. Pairs may also be separated by whitespace, and are
+/// percent-decoded like a query string, so `%20` or `+` stands for a space.
+pub fn parse_params(line: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    for query in line.split_whitespace() {
+        let web::Query(pairs) = web::Query::<Vec<(String, String)>>::from_query(query)
+            .with_context(|| format!("Invalid parameters '{}'", query))?;
+        params.extend(pairs);
+    }
+    Ok(params)
+}
+
+trait RawStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> RawStream for T {}
+
+/// A bound raw producer socket.
+pub enum RawListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl RawListener {
+    pub async fn bind(addr: &BindAddress) -> io::Result<Self> {
+        match addr {
+            BindAddress::Tcp(addr) => Ok(RawListener::Tcp(TcpListener::bind(addr).await?)),
+            #[cfg(unix)]
+            BindAddress::Unix(path) => {
+                Ok(RawListener::Unix(UnixListener::bind(path)?, path.clone()))
+            }
+        }
+    }
+
+    async fn accept(&self) -> io::Result<(Box<dyn RawStream>, String)> {
+        match self {
+            RawListener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Ok((Box::new(stream), peer.to_string()))
+            }
+            #[cfg(unix)]
+            RawListener::Unix(listener, path) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Box::new(stream), format!("unix:{}", path.display())))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RawListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawListener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "tcp"),
+            },
+            #[cfg(unix)]
+            RawListener::Unix(_, path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Accepts raw producer connections until shutdown begins, then waits for the
+/// open ones to drain or be truncated like HTTP streams.
+///
+/// Each connection gets one generation, configured by `raw_defaults` when set
+/// or otherwise by a handshake line, and is closed once it has been written.
+pub async fn serve(
+    listener: RawListener,
+    service: GenerationService,
+    coordinator: Arc<ShutdownCoordinator>,
+) {
+    info!(addr = %listener, "raw producer listening");
+    let mut connections = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            _ = coordinator.shutdown_started() => break,
+            Some(_) = connections.join_next() => {}
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    let span = info_span!(
+                        "raw_generate",
+                        %peer,
+                        job_id = tracing::field::Empty,
+                        format = tracing::field::Empty,
+                        size = tracing::field::Empty
+                    );
+                    connections.spawn(handle(stream, service.clone()).instrument(span));
+                }
+                Err(e) => warn!(error = %e, "failed to accept a raw producer connection"),
+            },
+        }
+    }
+
+    #[cfg(unix)]
+    if let RawListener::Unix(_, path) = &listener {
+        let _ = std::fs::remove_file(path);
+    }
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
+
+async fn handle(mut stream: Box<dyn RawStream>, service: GenerationService) {
+    let request = async {
+        let params = match &service.config().raw_defaults {
+            Some(defaults) => parse_params(defaults)?,
+            None => read_handshake(&mut stream).await?,
+        };
+        GenerationRequest::from_query(&params, service.config())
+    }
+    .instrument(info_span!("parse_params"))
+    .await;
+
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return reject(&mut stream, format!("{:#}", e)).await,
+    };
+    let generation = match service.start(request).await {
+        Ok(generation) => generation,
+        Err(e) => return reject(&mut stream, e.to_string()).await,
+    };
+
+    match write_body(&mut stream, generation.body).await {
+        Ok(written) => debug!(bytes = written, "raw stream written"),
+        Err(e) => debug!(error = %e, "raw client went away"),
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Reads the single line of parameters a client sends before the stream starts.
+async fn read_handshake(
+    stream: &mut Box<dyn RawStream>,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut reader = BufReader::new(stream.take(MAX_HANDSHAKE_LEN));
+    let mut line = String::new();
+    let read = tokio::time::timeout(HANDSHAKE_TIMEOUT, reader.read_line(&mut line))
+        .await
+        .context("Timed out waiting for the handshake line")?
+        .context("Failed to read the handshake line")?;
+
+    if read == 0 {
+        bail!("Connection closed before the handshake line");
+    }
+    if !line.ends_with('\n') && read as u64 == MAX_HANDSHAKE_LEN {
+        bail!("Handshake line is longer than {} bytes", MAX_HANDSHAKE_LEN);
+    }
+    parse_params(line.trim())
+}
+
+/// Tells the client why no stream is coming, as a single `ERR` line.
+async fn reject(stream: &mut Box<dyn RawStream>, message: String) {
+    warn!(error = %message, "raw producer request rejected");
+    let _ = stream
+        .write_all(format!("ERR {}\n", message).as_bytes())
+        .await;
+    let _ = stream.shutdown().await;
+}
+
+/// Writes chunks as they arrive, gathering whatever is already queued behind
+/// the first one into a single `writev`.
+async fn write_body<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    mut body: Receiver<Bytes>,
+) -> io::Result<u64> {
+    let mut batch = Vec::with_capacity(MAX_IOVECS);
+    let mut written = 0;
+
+    while let Some(chunk) = body.recv().await {
+        batch.push(chunk);
+        while batch.len() < MAX_IOVECS {
+            match body.try_recv() {
+                Ok(chunk) => batch.push(chunk),
+                Err(_) => break,
+            }
+        }
+        batch.retain(|chunk| !chunk.is_empty());
+        written += write_all_vectored(writer, &mut batch).await?;
+        batch.clear();
+    }
+
+    writer.flush().await?;
+    Ok(written)
+}
+
+async fn write_all_vectored<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    batch: &mut [Bytes],
+) -> io::Result<u64> {
+    let mut start = 0;
+    let mut written = 0;
+
+    while start < batch.len() {
+        let slices: Vec<IoSlice> = batch[start..].iter().map(|b| IoSlice::new(b)).collect();
+        let mut n = writer.write_vectored(&slices).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        written += n as u64;
+
+        while n > 0 {
+            let len = batch[start].len();
+            if n >= len {
+                n -= len;
+                start += 1;
+            } else {
+                batch[start].advance(n);
+                n = 0;
+            }
+        }
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use tokio::sync::mpsc::channel;
+
+    async fn handshake(sent: &[u8]) -> anyhow::Result<HashMap<String, String>> {
+        let (mut client, server) = duplex(8192);
+        client.write_all(sent).await.unwrap();
+        drop(client);
+        let mut stream: Box<dyn RawStream> = Box::new(server);
+        read_handshake(&mut stream).await
+    }
+
+    #[test]
+    fn parses_handshake_params() {
+        let params =
+            parse_params("size=1gb&format=csv  locale=fr_FR\tpool.name=a=b.txt junk").unwrap();
+        assert_eq!(params.len(), 5);
+        assert_eq!(params["size"], "1gb");
+        assert_eq!(params["format"], "csv");
+        assert_eq!(params["locale"], "fr_FR");
+        assert_eq!(params["pool.name"], "a=b.txt");
+        assert_eq!(params["junk"], "");
+        assert!(parse_params("").unwrap().is_empty());
+
+        // Values are decoded as in a query string.
+        let params =
+            parse_params("time_format.datetime=%25Y-%25m-%25d%20%25H:%25M pool.city=my+cities.txt")
+                .unwrap();
+        assert_eq!(params["time_format.datetime"], "%Y-%m-%d %H:%M");
+        assert_eq!(params["pool.city"], "my cities.txt");
+    }
+
+    #[actix_web::test]
+    async fn reads_one_handshake_line() {
+        let params = handshake(b"size=1kb format=csv\r\nsize=2kb\n")
+            .await
+            .unwrap();
+        assert_eq!(params["size"], "1kb");
+        assert_eq!(params["format"], "csv");
+
+        assert!(handshake(b"").await.is_err());
+        let long = format!("size={}", "9".repeat(MAX_HANDSHAKE_LEN as usize));
+        assert!(handshake(long.as_bytes()).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn writes_every_chunk_in_order() {
+        let (tx, rx) = channel(16);
+        for chunk in ["ab", "", "cde", "f"] {
+            tx.send(Bytes::from(chunk)).await.unwrap();
+        }
+        drop(tx);
+        let mut written = Vec::new();
+        assert_eq!(write_body(&mut written, rx).await.unwrap(), 6);
+        assert_eq!(written, b"abcdef");
+    }
+}
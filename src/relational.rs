@@ -0,0 +1,701 @@
+use crate::archive::ZipWriter;
+use crate::numeric::{NumericOptions, RevenueBuffer, RevenueType};
+use crate::processing::{put_csv_field, put_json_string, DataPools, OutputFormat};
+use crate::temporal::{TimeBuffer, TimeFormat, TimeKind, TimeRange};
+use bytes::{BufMut, Bytes, BytesMut};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Rows drawn in parallel and written as one chunk, or one SQL `INSERT`.
+const ROWS_PER_CHUNK: u64 = 10_000;
+
+/// A table of a relational dataset, in the order tables are written: every
+/// table only references the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Table {
+    /// The usual business records, with an `id`.
+    Companies,
+    /// People working at a company, by `company_id`.
+    Employees,
+    /// Orders taken by an employee, by `employee_id`, for that employee's
+    /// company, by `company_id`.
+    Orders,
+}
+
+impl Table {
+    pub const ALL: [Table; 3] = [Table::Companies, Table::Employees, Table::Orders];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Table::Companies => "companies",
+            Table::Employees => "employees",
+            Table::Orders => "orders",
+        }
+    }
+
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            Table::Companies => &[
+                "id",
+                "name",
+                "industry",
+                "revenue",
+                "employees",
+                "city",
+                "state",
+                "country",
+            ],
+            Table::Employees => &["id", "company_id", "name", "email", "city"],
+            Table::Orders => &[
+                "id",
+                "company_id",
+                "employee_id",
+                "amount",
+                "quantity",
+                "ordered_at",
+            ],
+        }
+    }
+
+    /// The `CREATE TABLE` statement for this table, with its keys.
+    fn schema(&self, revenue_type: RevenueType) -> String {
+        let revenue = match revenue_type {
+            RevenueType::Decimal { places } => format!("NUMERIC(20, {})", places),
+            RevenueType::Cents => "BIGINT".to_owned(),
+            _ => "DOUBLE PRECISION".to_owned(),
+        };
+        let columns = match self {
+            Table::Companies => vec![
+                "id BIGINT PRIMARY KEY".to_owned(),
+                "name TEXT".to_owned(),
+                "industry TEXT".to_owned(),
+                format!("revenue {}", revenue),
+                "employees INTEGER".to_owned(),
+                "city TEXT".to_owned(),
+                "state TEXT".to_owned(),
+                "country TEXT".to_owned(),
+            ],
+            Table::Employees => vec![
+                "id BIGINT PRIMARY KEY".to_owned(),
+                "company_id BIGINT NOT NULL REFERENCES companies (id)".to_owned(),
+                "name TEXT".to_owned(),
+                "email TEXT".to_owned(),
+                "city TEXT".to_owned(),
+            ],
+            Table::Orders => vec![
+                "id BIGINT PRIMARY KEY".to_owned(),
+                "company_id BIGINT NOT NULL REFERENCES companies (id)".to_owned(),
+                "employee_id BIGINT NOT NULL REFERENCES employees (id)".to_owned(),
+                "amount NUMERIC(12, 2)".to_owned(),
+                "quantity INTEGER".to_owned(),
+                "ordered_at TIMESTAMP".to_owned(),
+            ],
+        };
+        format!(
+            "CREATE TABLE {} (\n  {}\n);\n",
+            self.as_str(),
+            columns.join(",\n  ")
+        )
+    }
+}
+
+impl FromStr for Table {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        Table::ALL
+            .into_iter()
+            .find(|table| table.as_str() == name)
+            .ok_or_else(|| format!("Unknown table '{}'", s))
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a set of tables is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableFormat {
+    /// A ZIP archive with a CSV file per table.
+    Csv,
+    /// A ZIP archive with a JSON array per table.
+    #[default]
+    Json,
+    /// One SQL script that creates the tables and fills them, parents first.
+    Sql,
+}
+
+impl TableFormat {
+    pub const ALL: [TableFormat; 3] = [TableFormat::Csv, TableFormat::Json, TableFormat::Sql];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Json => "json",
+            TableFormat::Sql => "sql",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            TableFormat::Csv | TableFormat::Json => "application/zip",
+            TableFormat::Sql => "application/sql",
+        }
+    }
+
+    /// Name of the file the output is saved as.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            TableFormat::Csv | TableFormat::Json => "tables.zip",
+            TableFormat::Sql => "tables.sql",
+        }
+    }
+
+    /// The record format the output is counted under in job status and
+    /// metrics. SQL values are written much as CSV ones are.
+    pub fn record_format(&self) -> OutputFormat {
+        match self {
+            TableFormat::Json => OutputFormat::JSON,
+            TableFormat::Csv | TableFormat::Sql => OutputFormat::CSV,
+        }
+    }
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        TableFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str() == name)
+            .ok_or_else(|| format!("Unknown table format '{}', expected csv, json or sql", s))
+    }
+}
+
+impl fmt::Display for TableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The size and shape of a relational dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TableOptions {
+    pub companies: u64,
+    /// Employee rows per company, on average.
+    pub employees_per_company: f64,
+    /// Order rows per company, on average.
+    pub orders_per_company: f64,
+    pub format: TableFormat,
+    /// How company revenue and headcounts are drawn and written.
+    pub numbers: NumericOptions,
+}
+
+impl TableOptions {
+    pub const DEFAULT_EMPLOYEES_PER_COMPANY: f64 = 10.0;
+    pub const DEFAULT_ORDERS_PER_COMPANY: f64 = 25.0;
+
+    pub fn new(companies: u64) -> Self {
+        Self {
+            companies,
+            employees_per_company: Self::DEFAULT_EMPLOYEES_PER_COMPANY,
+            orders_per_company: Self::DEFAULT_ORDERS_PER_COMPANY,
+            format: TableFormat::default(),
+            numbers: NumericOptions::default(),
+        }
+    }
+
+    /// Number of rows `table` gets.
+    pub fn rows(&self, table: Table) -> u64 {
+        match table {
+            Table::Companies => self.companies,
+            Table::Employees => (self.companies as f64 * self.employees_per_company).round() as u64,
+            Table::Orders => (self.companies as f64 * self.orders_per_company).round() as u64,
+        }
+    }
+
+    /// Checks that every row can reference an existing parent.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.companies == 0 {
+            return Err("A dataset needs at least one company".to_owned());
+        }
+        for (name, ratio) in [
+            ("employees", self.employees_per_company),
+            ("orders", self.orders_per_company),
+        ] {
+            if !(ratio.is_finite() && ratio >= 0.0) {
+                return Err(format!("{} per company must be 0 or more", name));
+            }
+        }
+        if self.rows(Table::Orders) > 0 && self.rows(Table::Employees) == 0 {
+            return Err("Orders need at least one employee to take them".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Rough size of the output in bytes, for enforcing size limits.
+    pub fn estimated_size(&self) -> u64 {
+        let row_bytes = [120, 80, 90];
+        Table::ALL
+            .into_iter()
+            .zip(row_bytes)
+            .map(|(table, bytes)| self.rows(table).saturating_mul(bytes))
+            .fold(0, u64::saturating_add)
+    }
+}
+
+struct CompanyRow<'a> {
+    id: u64,
+    name: &'a str,
+    industry: &'a str,
+    revenue: f64,
+    employees: u32,
+    city: &'a str,
+    state: &'a str,
+    country: &'a str,
+}
+
+struct EmployeeRow<'a> {
+    id: u64,
+    company_id: u64,
+    name: &'a str,
+    email: &'a str,
+    city: &'a str,
+}
+
+struct OrderRow {
+    id: u64,
+    company_id: u64,
+    employee_id: u64,
+    cents: u64,
+    quantity: u32,
+    ordered_at: i64,
+}
+
+/// A cell, written quoted or not as the format needs.
+enum Value<'a> {
+    Number(&'a str),
+    Text(&'a str),
+}
+
+/// Writes the rows of one table, keeping count of those in the current JSON
+/// file or SQL statement, which need separators between them.
+struct RowWriter {
+    format: TableFormat,
+    rows: u64,
+    revenue: RevenueBuffer,
+    time: TimeBuffer,
+    time_format: TimeFormat,
+}
+
+impl RowWriter {
+    fn new(format: TableFormat) -> Self {
+        Self {
+            format,
+            rows: 0,
+            revenue: RevenueBuffer::default(),
+            time: TimeBuffer::default(),
+            time_format: match format {
+                TableFormat::Sql => "%Y-%m-%d %H:%M:%S".parse().expect("a valid pattern"),
+                _ => TimeFormat::Rfc3339,
+            },
+        }
+    }
+}
+
+fn put_row(
+    format: TableFormat,
+    rows: &mut u64,
+    columns: &[&str],
+    values: &[Value],
+    buffer: &mut BytesMut,
+) {
+    match format {
+        TableFormat::Csv => {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    buffer.put_u8(b',');
+                }
+                match value {
+                    Value::Number(number) => buffer.put_slice(number.as_bytes()),
+                    Value::Text(text) => put_csv_field(buffer, text),
+                }
+            }
+            buffer.put_u8(b'\n');
+        }
+        TableFormat::Json => {
+            if *rows > 0 {
+                buffer.put_u8(b',');
+            }
+            buffer.put_u8(b'{');
+            for (i, (column, value)) in columns.iter().zip(values).enumerate() {
+                if i > 0 {
+                    buffer.put_u8(b',');
+                }
+                put_json_string(buffer, column);
+                buffer.put_u8(b':');
+                match value {
+                    Value::Number(number) => buffer.put_slice(number.as_bytes()),
+                    Value::Text(text) => put_json_string(buffer, text),
+                }
+            }
+            buffer.put_u8(b'}');
+        }
+        TableFormat::Sql => {
+            buffer.put_slice(if *rows > 0 { b",\n(" } else { b"(" });
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    buffer.put_slice(b", ");
+                }
+                match value {
+                    Value::Number(number) => buffer.put_slice(number.as_bytes()),
+                    Value::Text(text) => put_sql_string(buffer, text),
+                }
+            }
+            buffer.put_u8(b')');
+        }
+    }
+    *rows += 1;
+}
+
+/// Writes `value` as a single-quoted SQL string, doubling any quotes in it.
+fn put_sql_string(buffer: &mut BytesMut, value: &str) {
+    buffer.put_u8(b'\'');
+    for (i, part) in value.split('\'').enumerate() {
+        if i > 0 {
+            buffer.put_slice(b"''");
+        }
+        buffer.put_slice(part.as_bytes());
+    }
+    buffer.put_u8(b'\'');
+}
+
+/// A relational dataset: companies, their employees and the orders those
+/// employees take. Row N of each table is always drawn from the same stream
+/// of the seed, so rows can be drawn in parallel and any row's parents looked
+/// up again, and every foreign key points at a row that exists.
+pub struct Tables<'a> {
+    options: TableOptions,
+    pools: &'a DataPools,
+    rngs: [ChaCha8Rng; Table::ALL.len()],
+}
+
+impl<'a> Tables<'a> {
+    pub fn new(options: TableOptions, pools: &'a DataPools, seed: u64) -> Self {
+        Self {
+            options,
+            pools,
+            rngs: Table::ALL
+                .map(|table| ChaCha8Rng::seed_from_u64(seed.wrapping_add(table as u64))),
+        }
+    }
+
+    fn rng(&self, table: Table, index: u64) -> ChaCha8Rng {
+        let mut rng = self.rngs[table as usize].clone();
+        rng.set_stream(index);
+        rng
+    }
+
+    fn company(&self, index: u64) -> CompanyRow<'a> {
+        let mut rng = self.rng(Table::Companies, index);
+        let [name, industry, city, state, country]: [u32; 5] = rng.gen();
+        let (revenue, employees) = self.options.numbers.sample(&mut rng);
+        let pools = self.pools;
+        CompanyRow {
+            id: index + 1,
+            name: pools.names.pick(name),
+            industry: pools.industries.pick(industry),
+            revenue,
+            employees,
+            city: pools.cities.pick(city),
+            state: pools.states.pick(state),
+            country: pools.countries.pick(country),
+        }
+    }
+
+    /// The company of employee `index`, the first draw of its row.
+    fn employer(&self, rng: &mut ChaCha8Rng) -> u64 {
+        rng.gen_range(0..self.options.companies) + 1
+    }
+
+    fn employee(&self, index: u64) -> EmployeeRow<'a> {
+        let mut rng = self.rng(Table::Employees, index);
+        let company_id = self.employer(&mut rng);
+        let [name, email, city]: [u32; 3] = rng.gen();
+        let pools = self.pools;
+        EmployeeRow {
+            id: index + 1,
+            company_id,
+            name: pools.contacts.pick(name),
+            email: pools.emails.pick(email),
+            city: pools.cities.pick(city),
+        }
+    }
+
+    fn order(&self, index: u64) -> OrderRow {
+        let mut rng = self.rng(Table::Orders, index);
+        let employee = rng.gen_range(0..self.options.rows(Table::Employees));
+        let company_id = self.employer(&mut self.rng(Table::Employees, employee));
+        let range = TimeRange::default();
+        OrderRow {
+            id: index + 1,
+            company_id,
+            employee_id: employee + 1,
+            cents: rng.gen_range(500..=500_000),
+            quantity: rng.gen_range(1..=20),
+            ordered_at: rng.gen_range(range.start..range.end) / 1000 * 1000,
+        }
+    }
+
+    /// Rows `rows` of `table`, drawn in parallel and written in order.
+    fn chunk(&self, table: Table, rows: Range<u64>, writer: &mut RowWriter) -> Bytes {
+        let mut buffer = BytesMut::with_capacity((rows.end - rows.start) as usize * 100);
+        if writer.format == TableFormat::Sql {
+            writer.rows = 0;
+            buffer.put_slice(b"INSERT INTO ");
+            buffer.put_slice(table.as_str().as_bytes());
+            buffer.put_slice(b" (");
+            buffer.put_slice(table.columns().join(", ").as_bytes());
+            buffer.put_slice(b") VALUES\n");
+        }
+
+        let columns = table.columns();
+        let mut id = itoa::Buffer::new();
+        let mut company_id = itoa::Buffer::new();
+        let mut employee_id = itoa::Buffer::new();
+        let mut count = itoa::Buffer::new();
+        let RowWriter {
+            format,
+            rows: written,
+            revenue,
+            time,
+            time_format,
+        } = writer;
+        match table {
+            Table::Companies => {
+                let revenue_type = self.options.numbers.revenue_type;
+                let companies: Vec<_> = rows.into_par_iter().map(|i| self.company(i)).collect();
+                for company in &companies {
+                    let revenue = revenue.format(company.revenue, revenue_type);
+                    let values = [
+                        Value::Number(id.format(company.id)),
+                        Value::Text(company.name),
+                        Value::Text(company.industry),
+                        if *format == TableFormat::Json && revenue_type.is_string() {
+                            Value::Text(revenue)
+                        } else {
+                            Value::Number(revenue)
+                        },
+                        Value::Number(count.format(company.employees)),
+                        Value::Text(company.city),
+                        Value::Text(company.state),
+                        Value::Text(company.country),
+                    ];
+                    put_row(*format, written, columns, &values, &mut buffer);
+                }
+            }
+            Table::Employees => {
+                let employees: Vec<_> = rows.into_par_iter().map(|i| self.employee(i)).collect();
+                for employee in &employees {
+                    let values = [
+                        Value::Number(id.format(employee.id)),
+                        Value::Number(company_id.format(employee.company_id)),
+                        Value::Text(employee.name),
+                        Value::Text(employee.email),
+                        Value::Text(employee.city),
+                    ];
+                    put_row(*format, written, columns, &values, &mut buffer);
+                }
+            }
+            Table::Orders => {
+                let orders: Vec<_> = rows.into_par_iter().map(|i| self.order(i)).collect();
+                for order in &orders {
+                    let amount = revenue.format(
+                        order.cents as f64 / 100.0,
+                        RevenueType::Decimal { places: 2 },
+                    );
+                    let values = [
+                        Value::Number(id.format(order.id)),
+                        Value::Number(company_id.format(order.company_id)),
+                        Value::Number(employee_id.format(order.employee_id)),
+                        Value::Number(amount),
+                        Value::Number(count.format(order.quantity)),
+                        Value::Text(time.format(order.ordered_at, TimeKind::DateTime, time_format)),
+                    ];
+                    put_row(*format, written, columns, &values, &mut buffer);
+                }
+            }
+        }
+
+        if writer.format == TableFormat::Sql {
+            buffer.put_slice(b";\n");
+        }
+        buffer.freeze()
+    }
+
+    /// Writes the whole dataset, table by table, passing each chunk and the
+    /// number of rows in it to `send` until it returns `false`. Rows are drawn
+    /// on the current rayon pool. Returns whether everything was sent.
+    pub fn write(&self, send: impl FnMut(Bytes, u64) -> bool) -> bool {
+        let format = self.options.format;
+        let mut out = Output {
+            zip: (format != TableFormat::Sql).then(ZipWriter::new),
+            send,
+        };
+
+        if format == TableFormat::Sql {
+            let mut schema = String::from("BEGIN;\n\n");
+            for table in Table::ALL {
+                schema.push_str(&table.schema(self.options.numbers.revenue_type));
+                schema.push('\n');
+            }
+            if !out.data(Bytes::from(schema), 0) {
+                return false;
+            }
+        }
+
+        for table in Table::ALL {
+            let mut writer = RowWriter::new(format);
+            if let Some(zip) = &mut out.zip {
+                let header = zip.start(&format!("{}.{}", table, format));
+                if !(out.send)(header, 0) {
+                    return false;
+                }
+            }
+            let opening = match format {
+                TableFormat::Csv => format!("{}\n", table.columns().join(",")),
+                TableFormat::Json => "[".to_owned(),
+                TableFormat::Sql => String::new(),
+            };
+            if !opening.is_empty() && !out.data(Bytes::from(opening), 0) {
+                return false;
+            }
+
+            let rows = self.options.rows(table);
+            let mut first = 0;
+            while first < rows {
+                let last = (first + ROWS_PER_CHUNK).min(rows);
+                let chunk = self.chunk(table, first..last, &mut writer);
+                if !out.data(chunk, last - first) {
+                    return false;
+                }
+                first = last;
+            }
+
+            if format == TableFormat::Json && !out.data(Bytes::from_static(b"]"), 0) {
+                return false;
+            }
+            if let Some(zip) = &mut out.zip {
+                let descriptor = zip.finish_entry();
+                if !(out.send)(descriptor, 0) {
+                    return false;
+                }
+            }
+        }
+
+        match out.zip.take() {
+            Some(zip) => (out.send)(zip.finish(), 0),
+            None => out.data(Bytes::from_static(b"\nCOMMIT;\n"), 0),
+        }
+    }
+}
+
+/// Where the output goes: straight to the consumer, counted into the ZIP
+/// entry being written if the output is an archive.
+struct Output<F> {
+    zip: Option<ZipWriter>,
+    send: F,
+}
+
+impl<F: FnMut(Bytes, u64) -> bool> Output<F> {
+    fn data(&mut self, bytes: Bytes, rows: u64) -> bool {
+        if let Some(zip) = &mut self.zip {
+            zip.data(&bytes);
+        }
+        (self.send)(bytes, rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn foreign_keys_point_at_existing_rows() {
+        let pools = DataPools::with_seed(100, 1);
+        let mut options = TableOptions::new(7);
+        options.employees_per_company = 3.0;
+        let tables = Tables::new(options, &pools, 42);
+
+        for index in 0..options.rows(Table::Employees) {
+            let employee = tables.employee(index);
+            assert!((1..=7).contains(&employee.company_id));
+        }
+        for index in 0..options.rows(Table::Orders) {
+            let order = tables.order(index);
+            assert!(order.employee_id <= options.rows(Table::Employees));
+            let employee = tables.employee(order.employee_id - 1);
+            assert_eq!(order.company_id, employee.company_id);
+        }
+    }
+
+    #[test]
+    fn json_rows_are_separated_across_chunks() {
+        let pools = DataPools::with_seed(100, 1);
+        let tables = Tables::new(TableOptions::new(5), &pools, 42);
+        for table in Table::ALL {
+            let mut writer = RowWriter::new(TableFormat::Json);
+            let mut output = b"[".to_vec();
+            output.extend_from_slice(&tables.chunk(table, 0..3, &mut writer));
+            output.extend_from_slice(&tables.chunk(table, 3..5, &mut writer));
+            output.push(b']');
+
+            let rows: Vec<serde_json::Map<String, serde_json::Value>> =
+                serde_json::from_slice(&output).unwrap();
+            assert_eq!(rows.len(), 5);
+            assert_eq!(rows[4]["id"], 5);
+            assert!(rows.iter().all(|row| row.len() == table.columns().len()));
+        }
+    }
+
+    #[test]
+    fn sql_script_creates_then_fills_each_table() {
+        let pools = DataPools::with_seed(100, 1);
+        let mut options = TableOptions::new(3);
+        options.format = TableFormat::Sql;
+        let mut script = Vec::new();
+        let mut rows = 0;
+        assert!(Tables::new(options, &pools, 42).write(|chunk, count| {
+            script.extend_from_slice(&chunk);
+            rows += count;
+            true
+        }));
+        let script = String::from_utf8(script).unwrap();
+
+        assert_eq!(
+            rows,
+            Table::ALL
+                .iter()
+                .map(|&table| options.rows(table))
+                .sum::<u64>()
+        );
+        assert!(script.starts_with("BEGIN;\n"));
+        assert!(script.ends_with("COMMIT;\n"));
+        let mut at = 0;
+        for table in Table::ALL {
+            let create = script.find(&format!("CREATE TABLE {} (", table)).unwrap();
+            let insert = script.find(&format!("INSERT INTO {} (", table)).unwrap();
+            assert!(create < insert && at <= insert);
+            at = insert;
+        }
+    }
+}
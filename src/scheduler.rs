@@ -0,0 +1,219 @@
+use parking_lot::Mutex;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Why a generation request was not admitted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdmissionError {
+    /// The wait queue is already at `max_queued` requests.
+    QueueFull,
+    /// The request waited `queue_timeout` without a slot freeing up.
+    Timeout,
+}
+
+impl std::fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdmissionError::QueueFull => write!(f, "Generation queue is full"),
+            AdmissionError::Timeout => write!(f, "Timed out waiting for a generation slot"),
+        }
+    }
+}
+
+/// Server-wide admission control for generation requests.
+///
+/// At most `max_concurrent` generations run at once. Each running generation
+/// owns one of `max_concurrent` rayon pools, so the generation threads are split
+/// evenly between active requests instead of every request saturating one
+/// global pool. Requests beyond that wait in a bounded queue.
+///
+/// The split does not change with load, so a request running alone still only
+/// gets its slot's threads. Sizing each slot from `generation_threads` and
+/// `max_concurrent` keeps a request's speed the same however many others run.
+pub struct Scheduler {
+    permits: Arc<Semaphore>,
+    pools: Mutex<Vec<Arc<ThreadPool>>>,
+    waiting: AtomicUsize,
+    max_concurrent: usize,
+    max_queued: usize,
+    queue_timeout: Duration,
+    threads_per_slot: usize,
+}
+
+impl Scheduler {
+    pub fn new(
+        generation_threads: usize,
+        max_concurrent: usize,
+        max_queued: usize,
+        queue_timeout: Duration,
+    ) -> std::io::Result<Arc<Self>> {
+        let threads_per_slot = (generation_threads / max_concurrent).max(1);
+
+        let pools = (0..max_concurrent)
+            .map(|slot| {
+                ThreadPoolBuilder::new()
+                    .num_threads(threads_per_slot)
+                    .thread_name(move |i| format!("gen-{}-{}", slot, i))
+                    .build()
+                    .map(Arc::new)
+                    .map_err(std::io::Error::other)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Arc::new(Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            pools: Mutex::new(pools),
+            waiting: AtomicUsize::new(0),
+            max_concurrent,
+            max_queued,
+            queue_timeout,
+            threads_per_slot,
+        }))
+    }
+
+    pub fn threads_per_slot(&self) -> usize {
+        self.threads_per_slot
+    }
+
+    pub fn active(&self) -> usize {
+        self.max_concurrent - self.permits.available_permits()
+    }
+
+    pub fn queued(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    /// Waits for a free generation slot, or fails immediately if the queue is full.
+    pub async fn admit(self: &Arc<Self>) -> Result<Admission, AdmissionError> {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if self.waiting.fetch_add(1, Ordering::AcqRel) >= self.max_queued {
+                    self.waiting.fetch_sub(1, Ordering::AcqRel);
+                    return Err(AdmissionError::QueueFull);
+                }
+
+                let acquired =
+                    tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned())
+                        .await;
+                self.waiting.fetch_sub(1, Ordering::AcqRel);
+
+                match acquired {
+                    Ok(Ok(permit)) => permit,
+                    _ => return Err(AdmissionError::Timeout),
+                }
+            }
+        };
+
+        let pool = self
+            .pools
+            .lock()
+            .pop()
+            .expect("a held permit always has a free pool");
+
+        Ok(Admission {
+            pool: Some(pool),
+            scheduler: self.clone(),
+            _permit: permit,
+        })
+    }
+}
+
+/// A running generation's claim on a slot. Dropping it frees the slot.
+pub struct Admission {
+    pool: Option<Arc<ThreadPool>>,
+    scheduler: Arc<Scheduler>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Admission {
+    pub fn pool(&self) -> &ThreadPool {
+        self.pool.as_ref().unwrap()
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            self.scheduler.pools.lock().push(pool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::StartError;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    #[actix_web::test]
+    async fn requests_beyond_the_slots_queue_then_overflow() {
+        let scheduler = Scheduler::new(4, 2, 1, Duration::from_secs(5)).unwrap();
+        assert_eq!(scheduler.threads_per_slot(), 2);
+        let first = scheduler.admit().await.unwrap();
+        let _second = scheduler.admit().await.unwrap();
+        assert_eq!(scheduler.active(), 2);
+        assert_eq!(first.pool().current_num_threads(), 2);
+
+        let queued = {
+            let scheduler = scheduler.clone();
+            actix_web::rt::spawn(async move { scheduler.admit().await.map(|_| ()) })
+        };
+        while scheduler.queued() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(
+            scheduler.admit().await.err(),
+            Some(AdmissionError::QueueFull)
+        );
+        assert_eq!(scheduler.queued(), 1);
+
+        drop(first);
+        assert_eq!(queued.await.unwrap(), Ok(()));
+        assert_eq!(scheduler.queued(), 0);
+    }
+
+    #[actix_web::test]
+    async fn queued_requests_give_up_after_the_timeout() {
+        let scheduler = Scheduler::new(1, 1, 4, Duration::from_millis(20)).unwrap();
+        let _held = scheduler.admit().await.unwrap();
+        assert_eq!(scheduler.admit().await.err(), Some(AdmissionError::Timeout));
+        assert_eq!(scheduler.queued(), 0);
+    }
+
+    #[actix_web::test]
+    async fn dropped_admissions_give_back_their_pool() {
+        let scheduler = Scheduler::new(2, 2, 0, Duration::from_secs(1)).unwrap();
+        let admission = scheduler.admit().await.unwrap();
+        let pool = admission.pool.clone().unwrap();
+        assert_eq!(scheduler.pools.lock().len(), 1);
+
+        drop(admission);
+        assert_eq!(scheduler.active(), 0);
+        assert_eq!(scheduler.pools.lock().len(), 2);
+        assert!(scheduler
+            .pools
+            .lock()
+            .iter()
+            .any(|free| Arc::ptr_eq(free, &pool)));
+    }
+
+    #[test]
+    fn rejections_answer_429_with_retry_after() {
+        for reason in [AdmissionError::QueueFull, AdmissionError::Timeout] {
+            let error = StartError::NotAdmitted {
+                job_id: 7,
+                retry_after: 3,
+                reason,
+            };
+            let response = error.error_response();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers().get("Retry-After").unwrap(), "3");
+            assert_eq!(response.headers().get("X-Request-Id").unwrap(), "7");
+        }
+    }
+}
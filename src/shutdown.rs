@@ -0,0 +1,267 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Tracks in-flight generations so shutdown can let them finish, or cut them
+/// off at a record boundary once the grace period runs out.
+#[derive(Default)]
+pub struct ShutdownCoordinator {
+    shutting_down: AtomicBool,
+    cancelled: AtomicBool,
+    active: AtomicUsize,
+    idle: Notify,
+    started: Notify,
+}
+
+impl ShutdownCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True once shutdown has begun; new generations should be refused.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Acquire)
+    }
+
+    /// True once the grace period has expired; running generations should stop
+    /// at the next chunk and close their framing.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Counts a generation as in flight until the returned guard is dropped.
+    pub fn track(self: &Arc<Self>) -> GenerationGuard {
+        self.active.fetch_add(1, Ordering::AcqRel);
+        GenerationGuard {
+            coordinator: self.clone(),
+        }
+    }
+
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Release);
+        self.started.notify_waiters();
+    }
+
+    /// Resolves once shutdown has begun.
+    pub async fn shutdown_started(&self) {
+        loop {
+            let notified = self.started.notified();
+            if self.is_shutting_down() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Waits until no generation is in flight. Returns false on timeout.
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let notified = self.idle.notified();
+                if self.active() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    /// Runs the shutdown sequence: stop accepting, drain for `grace`, cancel
+    /// whatever is left, wait for it to close its framing, then stop the server.
+    pub async fn shutdown(&self, server: actix_web::dev::ServerHandle, grace: Duration) {
+        info!(
+            active = self.active(),
+            grace_seconds = grace.as_secs(),
+            "shutting down, draining in-flight generations"
+        );
+        server.pause().await;
+        self.begin_shutdown();
+
+        if !self.wait_idle(grace).await {
+            warn!(
+                active = self.active(),
+                "grace period expired, truncating in-flight generations"
+            );
+            self.cancel();
+            if !self.wait_idle(Duration::from_secs(10)).await {
+                warn!(active = self.active(), "generations did not stop in time");
+            }
+        }
+
+        server.stop(true).await;
+    }
+}
+
+pub struct GenerationGuard {
+    coordinator: Arc<ShutdownCoordinator>,
+}
+
+impl Drop for GenerationGuard {
+    fn drop(&mut self) {
+        if self.coordinator.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.coordinator.idle.notify_waiters();
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!(error = %e, "failed to listen for Ctrl-C");
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::jobs::{JobRegistry, JobState};
+    use crate::pipeline::{GenerationRequest, GenerationService};
+    use crate::processing::{Locale, OutputFormat, PoolCache};
+    use crate::scheduler::Scheduler;
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    async fn idle_once_every_guard_is_dropped() {
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let first = coordinator.track();
+        let second = coordinator.track();
+        assert_eq!(coordinator.active(), 2);
+
+        drop(first);
+        assert!(!coordinator.wait_idle(Duration::from_millis(20)).await);
+        let waiting = {
+            let coordinator = coordinator.clone();
+            actix_web::rt::spawn(async move { coordinator.wait_idle(Duration::from_secs(5)).await })
+        };
+        drop(second);
+        assert!(waiting.await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn shutdown_started_resolves_once_begun() {
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let started = {
+            let coordinator = coordinator.clone();
+            actix_web::rt::spawn(async move { coordinator.shutdown_started().await })
+        };
+        assert!(!coordinator.is_shutting_down());
+        coordinator.begin_shutdown();
+        tokio::time::timeout(Duration::from_secs(5), started)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(coordinator.is_shutting_down());
+        assert!(!coordinator.is_cancelled());
+        coordinator.cancel();
+        assert!(coordinator.is_cancelled());
+    }
+
+    #[actix_web::test]
+    async fn cancelled_generations_close_their_framing() {
+        let config = ServerConfig {
+            chunk_size: 64 * 1024,
+            channel_depth: 1,
+            ..ServerConfig::default()
+        };
+        let pools = PoolCache::ready(Locale::En).await;
+        let registry = Arc::new(JobRegistry::new());
+
+        let cases: [(OutputFormat, &[(&str, &str)]); 6] = [
+            (OutputFormat::JSON, &[]),
+            (OutputFormat::CSV, &[]),
+            (
+                OutputFormat::CSV,
+                &[("nest", "address"), ("flatten", "dotted")],
+            ),
+            (OutputFormat::CSV, &[("time", "date,datetime,timestamp")]),
+            (OutputFormat::CSV, &[("dataset", "users")]),
+            // Log lines come out as they are, whatever the format.
+            (OutputFormat::CSV, &[("dataset", "app_log")]),
+        ];
+        for (format, extra) in cases {
+            let coordinator = Arc::new(ShutdownCoordinator::new());
+            let service = GenerationService::new(
+                Arc::new(config.clone()),
+                Scheduler::new(1, 1, 0, Duration::from_secs(1)).unwrap(),
+                registry.clone(),
+                pools.clone(),
+                coordinator.clone(),
+            );
+            let mut params = HashMap::from([
+                ("format".to_owned(), format.to_string().to_owned()),
+                ("size".to_owned(), "100mb".to_owned()),
+            ]);
+            for (key, value) in extra {
+                params.insert(key.to_string(), value.to_string());
+            }
+            let request = GenerationRequest::from_query(&params, &config).unwrap();
+            let mut generation = service.start(request).await.unwrap();
+            let mut output = Vec::new();
+            // Cut in once at least one record is out.
+            let has_record = |output: &[u8]| match format {
+                OutputFormat::JSON => output.contains(&b'}'),
+                _ => output.iter().filter(|&&b| b == b'\n').count() > 1,
+            };
+            while !has_record(&output) {
+                output.extend_from_slice(&generation.body.recv().await.unwrap());
+            }
+            coordinator.cancel();
+            while let Some(chunk) = generation.body.recv().await {
+                output.extend_from_slice(&chunk);
+            }
+            assert!(coordinator.wait_idle(Duration::from_secs(5)).await);
+            let job = registry.get(generation.job_id).unwrap();
+            assert_eq!(job.state(), JobState::Truncated);
+            assert!(output.len() < 100 * 1024 * 1024);
+
+            let output = String::from_utf8(output).unwrap();
+            let log = extra.contains(&("dataset", "app_log"));
+            match format {
+                _ if log => {
+                    // No marker, just whole log lines up to the cut.
+                    assert!(output.ends_with('\n'));
+                    for line in output.lines() {
+                        let line: Value = serde_json::from_str(line).unwrap();
+                        assert!(line["level"].is_string());
+                    }
+                }
+                OutputFormat::JSON => {
+                    // Still one valid array, ending with the marker.
+                    let records: Vec<Value> = serde_json::from_str(&output).unwrap();
+                    assert_eq!(records.last().unwrap()["error"], "truncated");
+                }
+                _ => {
+                    let last = output.lines().last().unwrap();
+                    assert!(last.starts_with("ERROR,truncated: server shutting down"));
+                    let columns = output.lines().next().unwrap().split(',').count();
+                    assert_eq!(last.split(',').count(), columns);
+                }
+            }
+        }
+    }
+}
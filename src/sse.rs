@@ -0,0 +1,452 @@
+use crate::config::ServerConfig;
+use crate::datasets::Dataset;
+use crate::gaps::GapRates;
+use crate::jobs::{JobRegistry, JobState};
+use crate::metrics::METRICS;
+use crate::nesting::NestingOptions;
+use crate::numeric::NumericOptions;
+use crate::pipeline::{
+    gap_rates, nesting_options, numeric_options, parse_dataset, parse_format, parse_locale,
+    pool_options, time_options, unique_fields, GenerationService, StartError,
+};
+use crate::pools::PoolOptions;
+use crate::processing::{Locale, OutputFormat, PoolCache, StreamGenerator};
+use crate::shutdown::ShutdownCoordinator;
+use crate::temporal::TimeOptions;
+use crate::unique::{KeySpace, UniqueFields};
+use crate::util::{convert_error, rate_interval, tick, ProgressInfo};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::{anyhow, bail, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::StreamExt;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+
+const HEARTBEAT: &[u8] = b": heartbeat\n\n";
+
+struct SseParams {
+    format: OutputFormat,
+    seed: Option<u64>,
+    count: Option<u64>,
+    rate: f64,
+    heartbeat: Option<Duration>,
+    start: u64,
+    dataset: Dataset,
+    locale: Locale,
+    pools: PoolOptions,
+    numbers: NumericOptions,
+    gaps: GapRates,
+    nesting: NestingOptions,
+    times: TimeOptions,
+    unique: UniqueFields,
+}
+
+impl SseParams {
+    fn parse(
+        req: &HttpRequest,
+        params: &HashMap<String, String>,
+        config: &ServerConfig,
+    ) -> anyhow::Result<Self> {
+        let format = parse_format(params, config)?;
+        if format == OutputFormat::PROTOBUF {
+            bail!("Event streams carry text, use json or csv");
+        }
+        let heartbeat = parse_param::<f64>(params, "heartbeat")?
+            .filter(|seconds| *seconds > 0.0)
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .context("Invalid heartbeat")?;
+
+        // The browser resends the id of the last event it saw when it
+        // reconnects, so the stream picks up right after it.
+        let start = match req.headers().get("Last-Event-ID") {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|id| id.trim().parse::<u64>().ok())
+                .ok_or_else(|| anyhow!("Invalid Last-Event-ID header"))?
+                .saturating_add(1),
+            None => 0,
+        };
+
+        let gaps = gap_rates(params, config)?;
+        let nesting = nesting_options(params)?;
+        Ok(Self {
+            format,
+            seed: parse_param(params, "seed")?,
+            count: parse_param(params, "count")?,
+            rate: parse_param(params, "rate")?.unwrap_or(0.0),
+            heartbeat,
+            start,
+            dataset: parse_dataset(params, format)?,
+            locale: parse_locale(params, config)?,
+            pools: pool_options(params, config)?,
+            numbers: numeric_options(params, config)?,
+            unique: unique_fields(params, &gaps, &nesting)?,
+            gaps,
+            nesting,
+            times: time_options(params)?,
+        })
+    }
+}
+
+fn parse_param<T: FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> anyhow::Result<Option<T>> {
+    params
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| anyhow!("Invalid {} '{}'", name, value))
+        })
+        .transpose()
+}
+
+/// Streams records as Server-Sent Events, one `data:` event per record with
+/// the record's index as its `id:`.
+///
+/// With a `seed`, record N is always the same, on any instance, so a client reconnecting with
+/// `Last-Event-ID` continues exactly where it left off. Each stream holds a
+/// generation slot while it is open and draws its records on the slot's pool,
+/// and ends once it has sent `max_request_size` bytes of records.
+#[tracing::instrument(
+    name = "sse_generate",
+    skip_all,
+    fields(job_id = tracing::field::Empty, format = tracing::field::Empty)
+)]
+pub async fn sse_generate(
+    req: HttpRequest,
+    service: web::Data<GenerationService>,
+    registry: web::Data<JobRegistry>,
+    pools: web::Data<PoolCache>,
+    coordinator: web::Data<ShutdownCoordinator>,
+    web::Query(params): web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let config = service.config();
+    let params = {
+        let _parse = info_span!("parse_params").entered();
+        SseParams::parse(&req, &params, config).map_err(convert_error)?
+    };
+
+    if coordinator.is_shutting_down() {
+        return Err(StartError::ShuttingDown.into());
+    }
+    // 204 tells EventSource not to reconnect once everything has been sent.
+    if params.count.is_some_and(|count| params.start >= count) {
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
+    let SseParams {
+        format,
+        seed,
+        count,
+        rate,
+        heartbeat,
+        start,
+        dataset,
+        locale,
+        pools: options,
+        numbers,
+        gaps,
+        nesting,
+        times,
+        unique,
+    } = params;
+    let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+
+    let job_id = registry.next_id();
+    let span = Span::current();
+    span.record("job_id", job_id);
+    span.record("format", format.to_string());
+
+    let progress = Arc::new(ProgressInfo::new(job_id, 0, 1, "b".to_owned(), format));
+    registry.insert(progress.clone());
+
+    let admission = service.admit(job_id).await?;
+    progress.set_state(JobState::Running);
+
+    let registry = registry.into_inner();
+    let coordinator = coordinator.into_inner();
+    let pools = pools.into_inner();
+    let guard = coordinator.track();
+    info!(seed, start, "event stream started");
+
+    let (tx, rx) = channel::<Bytes>(config.channel_depth);
+    let max_bytes = config.max_request_size;
+
+    tokio::spawn(
+        async move {
+            let _guard = guard;
+            let _active = METRICS.stream_started(format);
+
+            // Records are drawn one ahead of the stream, off the async
+            // worker; dropping `records` stops the drawing thread.
+            let (record_tx, mut records) = channel::<Bytes>(1);
+            let drawing_span = Span::current();
+            let cancel = coordinator.clone();
+            std::thread::spawn(move || {
+                let _span = drawing_span.entered();
+                // The pools are sampled from the seed too, rather than taken
+                // from the cache, so record N is the same on every instance.
+                let data_pools = pools.seeded(locale, seed);
+                let data_pools = if options.is_empty() {
+                    data_pools
+                } else {
+                    data_pools.customize(&options)
+                };
+                let mut generator = StreamGenerator::new(
+                    ChaCha8Rng::seed_from_u64(seed),
+                    &data_pools,
+                    false,
+                    format,
+                    u64::MAX,
+                )
+                .with_numbers(numbers)
+                .with_gaps(gaps)
+                .with_nesting(nesting)
+                .with_times(times)
+                .with_dataset(dataset)
+                .with_unique(unique, KeySpace::default());
+                let mut drawn = 0u64;
+                admission.pool().install(|| {
+                    for index in start..count.unwrap_or(u64::MAX) {
+                        let record = generator.record_at(index);
+                        drawn += record.len() as u64;
+                        if drawn > max_bytes || cancel.is_shutting_down() {
+                            break;
+                        }
+                        if record_tx.blocking_send(record).is_err() {
+                            break;
+                        }
+                    }
+                });
+            });
+
+            let mut rate = rate_interval(rate);
+            let mut heartbeat = heartbeat.map(|period| {
+                let mut heartbeat = interval_at(Instant::now() + period, period);
+                heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                heartbeat
+            });
+            let mut index = start;
+
+            let final_state = loop {
+                // Clients resume elsewhere with Last-Event-ID, so there is no
+                // point holding the stream open for the whole grace period.
+                if coordinator.is_shutting_down() {
+                    warn!("event stream closed by server shutdown");
+                    break JobState::Truncated;
+                }
+
+                let event = tokio::select! {
+                    _ = next_heartbeat(&mut heartbeat) => Bytes::from_static(HEARTBEAT),
+                    record = next_record(&mut rate, &mut records) => {
+                        // The drawing thread stops after `count` records or
+                        // `max_request_size` bytes.
+                        let Some(record) = record else {
+                            break JobState::Completed;
+                        };
+                        progress.update(record.len());
+                        progress.update_records(1);
+                        let event = event(index, &record);
+                        index += 1;
+                        event
+                    }
+                };
+
+                progress.update_streamed(event.len());
+                if tx.send(event).await.is_err() {
+                    METRICS.client_aborts.fetch_add(1, Ordering::Relaxed);
+                    debug!("client disconnected from the event stream");
+                    break JobState::Aborted;
+                }
+            };
+
+            progress.finish();
+            registry.finish(job_id, final_state);
+
+            let status = progress.status();
+            info!(
+                state = ?final_state,
+                last_event_id = index.checked_sub(1),
+                records = status.records,
+                elapsed_seconds = status.elapsed_seconds,
+                "event stream finished"
+            );
+        }
+        .in_current_span(),
+    );
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/event-stream"))
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Request-Id", job_id.to_string()))
+        .insert_header(("X-Seed", seed.to_string()))
+        .streaming(ReceiverStream::new(rx).map(Ok::<_, actix_web::Error>)))
+}
+
+fn event(id: u64, record: &[u8]) -> Bytes {
+    let mut buffer = BytesMut::with_capacity(record.len() + 32);
+    buffer.put_slice(b"id: ");
+    buffer.put_slice(itoa::Buffer::new().format(id).as_bytes());
+    buffer.put_slice(b"\n");
+    // A CSV record may hold quoted line breaks; each line needs its own
+    // `data:` field, which the client joins back together with newlines.
+    for line in record.split(|&byte| byte == b'\n') {
+        buffer.put_slice(b"data: ");
+        buffer.put_slice(line);
+        buffer.put_u8(b'\n');
+    }
+    buffer.put_u8(b'\n');
+    buffer.freeze()
+}
+
+async fn next_record(rate: &mut Option<Interval>, records: &mut Receiver<Bytes>) -> Option<Bytes> {
+    tick(rate).await;
+    records.recv().await
+}
+
+async fn next_heartbeat(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(heartbeat) => {
+            heartbeat.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Scheduler;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+
+    fn parse(last_event_id: Option<&str>) -> anyhow::Result<SseParams> {
+        let mut req = TestRequest::default();
+        if let Some(id) = last_event_id {
+            req = req.insert_header(("Last-Event-ID", id));
+        }
+        let params = HashMap::from([("seed".to_owned(), "42".to_owned())]);
+        SseParams::parse(&req.to_http_request(), &params, &ServerConfig::default())
+    }
+
+    #[test]
+    fn last_event_id_resumes_after_it() {
+        assert_eq!(parse(None).unwrap().start, 0);
+        assert_eq!(parse(Some("99")).unwrap().start, 100);
+        assert_eq!(parse(Some(" 7 ")).unwrap().start, 8);
+        assert!(parse(Some("seven")).is_err());
+    }
+
+    #[test]
+    fn resumed_events_match_the_ones_missed() {
+        // The resumed stream may be served by another instance, whose pools
+        // were built separately.
+        let first_pools = PoolCache::new(100, PoolOptions::default()).seeded(Locale::En, 42);
+        let resumed_pools = PoolCache::new(100, PoolOptions::default()).seeded(Locale::En, 42);
+        for format in [OutputFormat::JSON, OutputFormat::CSV] {
+            let generator = |pools| {
+                StreamGenerator::new(
+                    ChaCha8Rng::seed_from_u64(42),
+                    pools,
+                    false,
+                    format,
+                    u64::MAX,
+                )
+            };
+            let mut first = generator(&first_pools);
+            let sent: Vec<_> = (0..10).map(|index| first.record_at(index)).collect();
+            let mut resumed = generator(&resumed_pools);
+            for index in 5..10 {
+                assert_eq!(resumed.record_at(index), sent[index as usize]);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn streams_hold_a_slot_and_stop_at_the_size_limit() {
+        let pools = Arc::new(PoolCache::new(100, PoolOptions::default()));
+        let config = ServerConfig {
+            max_request_size: 2000,
+            ..ServerConfig::default()
+        };
+        let scheduler = Scheduler::new(1, 1, 0, Duration::from_secs(1)).unwrap();
+        let registry = Arc::new(JobRegistry::new());
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let service = GenerationService::new(
+            Arc::new(config),
+            scheduler.clone(),
+            registry.clone(),
+            pools.clone(),
+            coordinator.clone(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(service))
+                .app_data(web::Data::from(registry))
+                .app_data(web::Data::from(pools))
+                .app_data(web::Data::from(coordinator))
+                .route("/sse/generate", web::get().to(sse_generate)),
+        )
+        .await;
+
+        let slow = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/sse/generate?seed=1&rate=1")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(slow.status(), StatusCode::OK);
+        assert_eq!(scheduler.active(), 1);
+        let refused = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/sse/generate").to_request(),
+        )
+        .await;
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        drop(slow);
+        while scheduler.active() > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // Without a count, the stream ends before 2000 bytes of records.
+        let response = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/sse/generate?seed=1")
+                .to_request(),
+        )
+        .await;
+        let body = test::read_body(response).await;
+        let records: usize = body
+            .split(|&byte| byte == b'\n')
+            .filter_map(|line| line.strip_prefix(b"data: "))
+            .map(<[u8]>::len)
+            .sum();
+        assert!(records > 1000 && records <= 2000, "{}", records);
+    }
+
+    #[test]
+    fn events_carry_the_id_and_record() {
+        assert_eq!(event(3, b"{\"a\":1}"), "id: 3\ndata: {\"a\":1}\n\n");
+    }
+
+    #[test]
+    fn quoted_line_breaks_get_a_data_field_each() {
+        assert_eq!(event(3, b"\"a\nb\",1"), "id: 3\ndata: \"a\ndata: b\",1\n\n");
+    }
+}
@@ -0,0 +1,502 @@
+use crate::processing::RecordField;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, Utc};
+use rand::Rng;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const MILLIS_PER_SECOND: i64 = 1000;
+const MILLIS_PER_DAY: i64 = 86_400 * MILLIS_PER_SECOND;
+
+/// A kind of time field records can carry, each under its own key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeKind {
+    /// A calendar day, written `2024-03-01` by default.
+    Date,
+    /// A time to the second, written `2024-03-01T12:30:05Z` by default.
+    DateTime,
+    /// An instant to the millisecond, written as epoch milliseconds by
+    /// default. This is the field a time series drives.
+    Timestamp,
+}
+
+impl TimeKind {
+    pub const ALL: [TimeKind; 3] = [TimeKind::Date, TimeKind::DateTime, TimeKind::Timestamp];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeKind::Date => "date",
+            TimeKind::DateTime => "datetime",
+            TimeKind::Timestamp => "timestamp",
+        }
+    }
+
+    /// The record field this kind is written as, for gaps.
+    pub fn field(self) -> RecordField {
+        match self {
+            TimeKind::Date => RecordField::Date,
+            TimeKind::DateTime => RecordField::DateTime,
+            TimeKind::Timestamp => RecordField::Timestamp,
+        }
+    }
+
+    /// Drawn values are rounded down to this many milliseconds.
+    fn unit(self) -> i64 {
+        match self {
+            TimeKind::Date => MILLIS_PER_DAY,
+            TimeKind::DateTime => MILLIS_PER_SECOND,
+            TimeKind::Timestamp => 1,
+        }
+    }
+}
+
+impl FromStr for TimeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        TimeKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+            .ok_or_else(|| format!("Unknown time field '{}'", s))
+    }
+}
+
+impl fmt::Display for TimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Times from `start` up to, but not including, `end`, in milliseconds since
+/// the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Default for TimeRange {
+    /// 2020 to 2024, inclusive.
+    fn default() -> Self {
+        Self {
+            start: 1_577_836_800_000,
+            end: 1_735_689_600_000,
+        }
+    }
+}
+
+impl TimeRange {
+    pub fn new(start: i64, end: i64) -> Result<Self, String> {
+        if start >= end {
+            return Err("A time range must end after it starts".to_owned());
+        }
+        if DateTime::from_timestamp_millis(start).is_none()
+            || DateTime::from_timestamp_millis(end).is_none()
+        {
+            return Err("A time range must fit in the years 1 to 9999".to_owned());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Reads one end of a range: an RFC 3339 time, a `YYYY-MM-DD` date taken as
+/// midnight UTC, or whole seconds since the epoch.
+fn parse_instant(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<i64>() {
+        return seconds
+            .checked_mul(MILLIS_PER_SECOND)
+            .ok_or_else(|| format!("Time '{}' is out of range", s));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date
+            .and_time(Default::default())
+            .and_utc()
+            .timestamp_millis());
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|time| time.timestamp_millis())
+        .map_err(|_| {
+            format!(
+                "Invalid time '{}', expected RFC 3339, YYYY-MM-DD or epoch seconds",
+                s
+            )
+        })
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    /// Reads `start..end`, e.g. `2024-01-01..2024-02-01`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Invalid time range '{}', expected start..end", s))?;
+        Self::new(parse_instant(start)?, parse_instant(end)?)
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut start = String::new();
+        let mut end = String::new();
+        write_rfc3339(self.start, TimeKind::Timestamp, &mut start);
+        write_rfc3339(self.end, TimeKind::Timestamp, &mut end);
+        write!(f, "{}..{}", start, end)
+    }
+}
+
+/// A custom `strftime` pattern, checked and parsed once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    source: Arc<str>,
+    items: Arc<[Item<'static>]>,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items = StrftimeItems::new(s)
+            .parse_to_owned()
+            .map_err(|_| format!("Invalid strftime pattern '{}'", s))?;
+        Ok(Self {
+            source: s.into(),
+            items: items.into(),
+        })
+    }
+}
+
+/// How a time field is written.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum TimeFormat {
+    /// `2024-03-01` for dates, `2024-03-01T12:30:05Z` for datetimes and
+    /// `2024-03-01T12:30:05.250Z` for timestamps.
+    #[default]
+    Rfc3339,
+    /// Whole seconds since the Unix epoch, as a number.
+    EpochSeconds,
+    /// Milliseconds since the Unix epoch, as a number.
+    EpochMillis,
+    /// A custom `strftime` pattern, e.g. `%d/%m/%Y %H:%M`, always in UTC.
+    Strftime(Pattern),
+}
+
+impl TimeFormat {
+    /// Whether JSON quotes the value, as it does everything but epoch numbers.
+    pub fn is_string(&self) -> bool {
+        !matches!(self, TimeFormat::EpochSeconds | TimeFormat::EpochMillis)
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    /// Reads `rfc3339`, `epoch`, `epoch_ms` or any pattern with a `%`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            "epoch" | "epoch_s" => Ok(TimeFormat::EpochSeconds),
+            "epoch_ms" => Ok(TimeFormat::EpochMillis),
+            _ if s.contains('%') => s.parse().map(TimeFormat::Strftime),
+            _ => Err(format!(
+                "Unknown time format '{}', expected rfc3339, epoch, epoch_ms or a strftime pattern",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeFormat::Rfc3339 => f.write_str("rfc3339"),
+            TimeFormat::EpochSeconds => f.write_str("epoch"),
+            TimeFormat::EpochMillis => f.write_str("epoch_ms"),
+            TimeFormat::Strftime(pattern) => f.write_str(&pattern.source),
+        }
+    }
+}
+
+/// The range a time field is drawn from and how it is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeField {
+    pub range: TimeRange,
+    pub format: TimeFormat,
+}
+
+impl TimeField {
+    /// The default field of `kind`: anywhere in 2020 to 2024, written as
+    /// RFC 3339, except timestamps, which are epoch milliseconds.
+    pub fn new(kind: TimeKind) -> Self {
+        Self {
+            range: TimeRange::default(),
+            format: match kind {
+                TimeKind::Timestamp => TimeFormat::EpochMillis,
+                _ => TimeFormat::Rfc3339,
+            },
+        }
+    }
+}
+
+/// Makes `timestamp` rise through the stream: record N is stamped
+/// `N / rate` seconds after the start of the timestamp's range, plus up to
+/// `jitter` of the gap to the next record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeSeries {
+    /// Records per second of simulated time.
+    pub rate: f64,
+    /// Fraction of the gap between records each timestamp may be late by,
+    /// from 0 to 1, so timestamps never go backwards.
+    pub jitter: f64,
+}
+
+impl TimeSeries {
+    pub fn new(rate: f64, jitter: f64) -> Result<Self, String> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(format!("series rate must be above 0, got {}", rate));
+        }
+        if !(0.0..=1.0).contains(&jitter) {
+            return Err(format!("jitter must be 0 to 1, got {}", jitter));
+        }
+        Ok(Self { rate, jitter })
+    }
+
+    fn at(&self, start: i64, index: u64, jitter: f64) -> i64 {
+        let offset = (index as f64 + self.jitter * jitter) * 1000.0 / self.rate;
+        start.saturating_add(offset as i64)
+    }
+}
+
+/// Which time fields records carry. None by default.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TimeOptions {
+    pub date: Option<TimeField>,
+    pub datetime: Option<TimeField>,
+    pub timestamp: Option<TimeField>,
+    /// Makes `timestamp` a time series, which needs records to come out in
+    /// order.
+    pub series: Option<TimeSeries>,
+}
+
+impl TimeOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.kinds().next().is_some()
+    }
+
+    pub fn field(&self, kind: TimeKind) -> Option<&TimeField> {
+        match kind {
+            TimeKind::Date => self.date.as_ref(),
+            TimeKind::DateTime => self.datetime.as_ref(),
+            TimeKind::Timestamp => self.timestamp.as_ref(),
+        }
+    }
+
+    /// Adds `kind` with its defaults, keeping it as it is if already there.
+    pub fn enable(&mut self, kind: TimeKind) -> &mut TimeField {
+        let field = match kind {
+            TimeKind::Date => &mut self.date,
+            TimeKind::DateTime => &mut self.datetime,
+            TimeKind::Timestamp => &mut self.timestamp,
+        };
+        field.get_or_insert_with(|| TimeField::new(kind))
+    }
+
+    /// Makes `timestamp` a time series, adding it if needed.
+    pub fn set_series(&mut self, series: TimeSeries) {
+        self.enable(TimeKind::Timestamp);
+        self.series = Some(series);
+    }
+
+    /// The kinds records carry, in the order they are written.
+    pub fn kinds(&self) -> impl Iterator<Item = TimeKind> + '_ {
+        TimeKind::ALL
+            .into_iter()
+            .filter(|kind| self.field(*kind).is_some())
+    }
+
+    /// Draws the time fields of record `index`, in milliseconds since the
+    /// epoch. Records without time fields use no randomness.
+    pub(crate) fn draw<R: Rng>(&self, index: u64, rng: &mut R) -> TimeValues {
+        let mut values = TimeValues::default();
+        for kind in self.kinds() {
+            let field = self.field(kind).expect("kinds are enabled");
+            let range = field.range;
+            let value = match self.series {
+                Some(series) if kind == TimeKind::Timestamp => {
+                    let jitter = if series.jitter > 0.0 { rng.gen() } else { 0.0 };
+                    series.at(range.start, index, jitter)
+                }
+                _ => rng.gen_range(range.start..range.end),
+            };
+            values[kind as usize] = value.div_euclid(kind.unit()) * kind.unit();
+        }
+        values
+    }
+
+    /// The instant a built-in dataset stamps record `index` with, in
+    /// milliseconds: from the `timestamp` range, 2020 to 2024 by default, and
+    /// rising through the stream in a `series`, usually this one's.
+    pub(crate) fn instant<R: Rng>(
+        &self,
+        series: Option<TimeSeries>,
+        index: u64,
+        rng: &mut R,
+    ) -> i64 {
+        let range = self
+            .timestamp
+            .as_ref()
+            .map_or_else(TimeRange::default, |field| field.range);
+        match series {
+            Some(series) => {
+                let jitter = if series.jitter > 0.0 { rng.gen() } else { 0.0 };
+                series.at(range.start, index, jitter)
+            }
+            None => rng.gen_range(range.start..range.end),
+        }
+    }
+}
+
+/// One record's time fields, indexed by `TimeKind`.
+pub(crate) type TimeValues = [i64; TimeKind::ALL.len()];
+
+fn write_rfc3339(millis: i64, kind: TimeKind, out: &mut String) {
+    let time = DateTime::<Utc>::from_timestamp_millis(millis).unwrap_or_default();
+    let pattern = match kind {
+        TimeKind::Date => "%Y-%m-%d",
+        TimeKind::DateTime => "%Y-%m-%dT%H:%M:%SZ",
+        TimeKind::Timestamp => "%Y-%m-%dT%H:%M:%S%.3fZ",
+    };
+    write!(out, "{}", time.format(pattern)).expect("writing to a String");
+}
+
+/// Formats time fields as their `TimeFormat` asks, reusing one buffer.
+#[derive(Default)]
+pub(crate) struct TimeBuffer {
+    text: String,
+    integer: itoa::Buffer,
+}
+
+impl TimeBuffer {
+    pub(crate) fn format(&mut self, millis: i64, kind: TimeKind, format: &TimeFormat) -> &str {
+        match format {
+            TimeFormat::EpochSeconds => self.integer.format(millis.div_euclid(MILLIS_PER_SECOND)),
+            TimeFormat::EpochMillis => self.integer.format(millis),
+            TimeFormat::Rfc3339 => {
+                self.text.clear();
+                write_rfc3339(millis, kind, &mut self.text);
+                &self.text
+            }
+            TimeFormat::Strftime(pattern) => {
+                self.text.clear();
+                let time = DateTime::<Utc>::from_timestamp_millis(millis).unwrap_or_default();
+                write!(
+                    self.text,
+                    "{}",
+                    time.format_with_items(pattern.items.iter())
+                )
+                .expect("patterns are checked when parsed");
+                &self.text
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn ranges_take_dates_times_and_epoch_seconds() {
+        let range: TimeRange = "2024-01-01..2024-01-02T00:00:00Z".parse().unwrap();
+        assert_eq!(range.end - range.start, MILLIS_PER_DAY);
+        assert_eq!(
+            "0..60".parse(),
+            Ok(TimeRange {
+                start: 0,
+                end: 60_000
+            })
+        );
+        assert_eq!(
+            range.to_string(),
+            "2024-01-01T00:00:00.000Z..2024-01-02T00:00:00.000Z"
+        );
+        for spec in ["2024-01-02..2024-01-01", "2024-01-01", "soon..later"] {
+            assert!(spec.parse::<TimeRange>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn every_format_writes_the_same_instant() {
+        let millis = 1_709_296_205_250; // 2024-03-01T12:30:05.250Z
+        let mut buffer = TimeBuffer::default();
+        let mut format = |kind, format: &str| {
+            buffer
+                .format(millis, kind, &format.parse().unwrap())
+                .to_owned()
+        };
+        assert_eq!(format(TimeKind::Date, "rfc3339"), "2024-03-01");
+        assert_eq!(
+            format(TimeKind::DateTime, "rfc3339"),
+            "2024-03-01T12:30:05Z"
+        );
+        assert_eq!(
+            format(TimeKind::Timestamp, "rfc3339"),
+            "2024-03-01T12:30:05.250Z"
+        );
+        assert_eq!(format(TimeKind::Timestamp, "epoch"), "1709296205");
+        assert_eq!(format(TimeKind::Timestamp, "epoch_ms"), "1709296205250");
+        assert_eq!(
+            format(TimeKind::DateTime, "%d/%m/%Y %H:%M"),
+            "01/03/2024 12:30"
+        );
+        assert!("%Q".parse::<TimeFormat>().is_err());
+        assert!("iso".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn draws_stay_in_range_at_their_precision() {
+        let mut times = TimeOptions::default();
+        for kind in TimeKind::ALL {
+            times.enable(kind).range = "2024-01-01..2024-01-08".parse().unwrap();
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for index in 0..1000 {
+            let values = times.draw(index, &mut rng);
+            for kind in TimeKind::ALL {
+                let value = values[kind as usize];
+                assert!((1_704_067_200_000..1_704_672_000_000).contains(&value));
+                assert_eq!(value % kind.unit(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn series_timestamps_rise_at_the_rate() {
+        let mut times = TimeOptions::default();
+        times.set_series(TimeSeries::new(4.0, 0.9).unwrap());
+        let start = TimeRange::default().start;
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let stamps: Vec<i64> = (0..1000)
+            .map(|index| times.draw(index, &mut rng)[TimeKind::Timestamp as usize])
+            .collect();
+        assert!(stamps.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((start..start + 250).contains(&stamps[0]));
+        assert!((start + 249_750..start + 250_000).contains(&stamps[999]));
+
+        assert!(TimeSeries::new(0.0, 0.0).is_err());
+        assert!(TimeSeries::new(1.0, 1.5).is_err());
+    }
+
+    #[test]
+    fn times_off_draw_no_randomness() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut untouched = rng.clone();
+        assert_eq!(TimeOptions::default().draw(7, &mut rng), [0; 3]);
+        assert_eq!(rng.gen::<u64>(), untouched.gen::<u64>());
+    }
+}
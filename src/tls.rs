@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::ServerConfig;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+/// ALPN protocols offered to clients, most preferred first.
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Builds a rustls server config from PEM certificate chain and key files.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig> {
+    let cert_file = std::fs::File::open(cert_path)
+        .with_context(|| format!("Failed to open TLS certificate {}", cert_path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse TLS certificate {}", cert_path.display()))?;
+
+    let key_file = std::fs::File::open(key_path)
+        .with_context(|| format!("Failed to open TLS key {}", key_path.display()))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .with_context(|| format!("Failed to parse TLS key {}", key_path.display()))?
+        .with_context(|| format!("No private key found in {}", key_path.display()))?;
+
+    build_server_config(certs, key)
+}
+
+/// Generates a throwaway certificate for `localhost`, `127.0.0.1` and `::1`.
+///
+/// The certificate PEM is written to the temp directory so local clients can
+/// trust it, e.g. `curl --cacert <path>`.
+pub fn self_signed_config() -> Result<(ServerConfig, PathBuf)> {
+    let names = vec![
+        "localhost".to_owned(),
+        "127.0.0.1".to_owned(),
+        "::1".to_owned(),
+    ];
+    let certified = rcgen::generate_simple_self_signed(names)
+        .context("Failed to generate a self-signed certificate")?;
+
+    let pem_path = std::env::temp_dir().join("fast-json-gen-self-signed.pem");
+    std::fs::write(&pem_path, certified.cert.pem())
+        .with_context(|| format!("Failed to write {}", pem_path.display()))?;
+    info!(path = %pem_path.display(), "generated self-signed certificate");
+
+    let cert = certified.cert.der().clone();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+
+    Ok((build_server_config(vec![cert], key)?, pem_path))
+}
+
+fn build_server_config(
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConnection};
+
+    /// Writes a new certificate for `localhost` and its key as PEM files.
+    fn pem_files(name: &str) -> (PathBuf, PathBuf) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let dir = std::env::temp_dir();
+        let prefix = format!("fast-json-gen-tls-{}-{}", name, std::process::id());
+        let cert = dir.join(format!("{}.crt", prefix));
+        let key = dir.join(format!("{}.key", prefix));
+        std::fs::write(&cert, certified.cert.pem()).unwrap();
+        std::fs::write(&key, certified.key_pair.serialize_pem()).unwrap();
+        (cert, key)
+    }
+
+    /// Runs a handshake in memory against `server`, trusting `cert`, and
+    /// returns the protocol the two agreed on.
+    fn negotiate(server: ServerConfig, cert: &Path, offered: &[&[u8]]) -> Option<Vec<u8>> {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(std::fs::File::open(cert).unwrap())) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut client = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.alpn_protocols = offered.iter().map(|p| p.to_vec()).collect();
+
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut client = ClientConnection::new(Arc::new(client), name).unwrap();
+        let mut server = ServerConnection::new(Arc::new(server)).unwrap();
+        while client.is_handshaking() || server.is_handshaking() {
+            let mut bytes = Vec::new();
+            client.write_tls(&mut bytes).unwrap();
+            server.read_tls(&mut bytes.as_slice()).unwrap();
+            server.process_new_packets().unwrap();
+
+            let mut bytes = Vec::new();
+            server.write_tls(&mut bytes).unwrap();
+            client.read_tls(&mut bytes.as_slice()).unwrap();
+            client.process_new_packets().unwrap();
+        }
+        client.alpn_protocol().map(<[u8]>::to_vec)
+    }
+
+    #[test]
+    fn pem_files_load_and_prefer_h2() {
+        let (cert, key) = pem_files("load");
+        let config = load_server_config(&cert, &key).unwrap();
+        assert_eq!(
+            config.alpn_protocols,
+            [b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+
+        let config = || load_server_config(&cert, &key).unwrap();
+        assert_eq!(
+            negotiate(config(), &cert, &[b"http/1.1", b"h2"]).as_deref(),
+            Some(&b"h2"[..])
+        );
+        assert_eq!(
+            negotiate(config(), &cert, &[b"http/1.1"]).as_deref(),
+            Some(&b"http/1.1"[..])
+        );
+        std::fs::remove_file(cert).unwrap();
+        std::fs::remove_file(key).unwrap();
+    }
+
+    #[test]
+    fn bad_files_are_reported() {
+        let (cert, key) = pem_files("bad");
+        let (_, other_key) = pem_files("other");
+        let error =
+            |cert: &Path, key: &Path| format!("{:#}", load_server_config(cert, key).unwrap_err());
+
+        assert!(error(&cert, Path::new("/nonexistent/key.pem")).contains("Failed to open TLS key"));
+        assert!(error(&cert, &cert).contains("No private key found"));
+        assert!(error(&cert, &other_key).contains("Invalid TLS certificate or key"));
+        for path in [cert, key, other_key] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn self_signed_certificates_are_trusted_through_their_pem() {
+        let (config, pem) = self_signed_config().unwrap();
+        assert_eq!(
+            negotiate(config, &pem, &[b"h2"]).as_deref(),
+            Some(&b"h2"[..])
+        );
+    }
+}
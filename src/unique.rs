@@ -0,0 +1,212 @@
+use crate::gaps::{Gap, GapRates};
+use crate::nesting::{NestingOptions, MAX_ARRAY_LENGTH};
+use crate::pools::PoolField;
+use crate::processing::RecordField;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// The pooled fields whose values never repeat. Each value of a unique field
+/// gets a numeric suffix from its record's key, so values stay unique however
+/// small the pool is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UniqueFields {
+    fields: [bool; PoolField::ALL.len()],
+}
+
+impl UniqueFields {
+    pub fn insert(&mut self, field: PoolField) {
+        self.fields[field as usize] = true;
+    }
+
+    pub fn contains(&self, field: PoolField) -> bool {
+        self.fields[field as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.fields.contains(&true)
+    }
+
+    /// Adds each field of a comma-separated list such as `name,email`.
+    pub fn set_spec(&mut self, spec: &str) -> Result<(), String> {
+        for name in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let field = name.parse::<PoolField>().map_err(|_| {
+                let fields: Vec<_> = PoolField::ALL.iter().map(PoolField::as_str).collect();
+                format!(
+                    "Only pooled fields can be unique, one of {}; got '{}'",
+                    fields.join(", "),
+                    name
+                )
+            })?;
+            self.insert(field);
+        }
+        Ok(())
+    }
+
+    /// Checks that every unique field is written, and never as a null or
+    /// empty value, which would repeat.
+    pub fn validate(&self, gaps: &GapRates, nesting: &NestingOptions) -> Result<(), String> {
+        for field in PoolField::ALL
+            .into_iter()
+            .filter(|&field| self.contains(field))
+        {
+            let record_field = match field {
+                PoolField::Name => RecordField::Name,
+                PoolField::Industry => RecordField::Industry,
+                PoolField::City => RecordField::City,
+                PoolField::State => RecordField::State,
+                PoolField::Country => RecordField::Country,
+                PoolField::Contact if nesting.contacts.is_none() || nesting.depth < 2 => {
+                    return Err(
+                        "A unique contact needs contacts with a name, at depth 2 or more"
+                            .to_owned(),
+                    )
+                }
+                PoolField::Email if nesting.contacts.is_none() => {
+                    return Err("A unique email needs contacts".to_owned())
+                }
+                PoolField::Tag if nesting.tags.is_none() => {
+                    return Err("A unique tag needs tags".to_owned())
+                }
+                // An array left out or empty adds no values to repeat.
+                PoolField::Contact | PoolField::Email | PoolField::Tag => continue,
+            };
+            for gap in [Gap::Null, Gap::Empty] {
+                if gaps.rate(record_field, gap) > 0.0 {
+                    return Err(format!(
+                        "A unique {} can't also be {}, as those values repeat",
+                        field, gap
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `value` of `field`, made unique with `key` if the field is unique.
+    pub(crate) fn value<'a>(&self, field: PoolField, value: &'a str, key: u64) -> Cow<'a, str> {
+        if !self.contains(field) {
+            return Cow::Borrowed(value);
+        }
+        let mut unique = String::with_capacity(value.len() + 8);
+        // An email keeps its domain: `ann@example.com` becomes
+        // `ann.42@example.com`. Anything else ends in ` 42`. As the suffix has
+        // no separator in it, values with different keys never match.
+        match (field, value.rfind('@')) {
+            (PoolField::Email, Some(at)) => {
+                let _ = write!(unique, "{}.{}{}", &value[..at], key, &value[at..]);
+            }
+            (PoolField::Email, None) => {
+                let _ = write!(unique, "{}.{}", value, key);
+            }
+            _ => {
+                let _ = write!(unique, "{} {}", value, key);
+            }
+        }
+        Cow::Owned(unique)
+    }
+}
+
+/// Where one generator's records sit among all the generators of an output.
+/// Record `index` of generator `stream` gets key `index * streams + stream`,
+/// so the generators of one output can run in parallel, each knowing only its
+/// own records, and no two records get the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySpace {
+    pub stream: u64,
+    pub streams: u64,
+}
+
+impl Default for KeySpace {
+    fn default() -> Self {
+        Self {
+            stream: 0,
+            streams: 1,
+        }
+    }
+}
+
+impl KeySpace {
+    pub fn new(stream: u64, streams: u64) -> Self {
+        assert!(stream < streams, "stream {} of {}", stream, streams);
+        Self { stream, streams }
+    }
+
+    /// Key of record `index` of this generator.
+    pub fn key(&self, index: u64) -> u64 {
+        index * self.streams + self.stream
+    }
+
+    /// Key of entry `position` of an array in the record with key `key`.
+    pub(crate) fn entry_key(key: u64, position: usize) -> u64 {
+        key * MAX_ARRAY_LENGTH as u64 + position as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nesting::{Lengths, Nested};
+    use std::collections::HashSet;
+
+    #[test]
+    fn keys_of_parallel_generators_never_meet() {
+        let streams = 5;
+        let mut keys = HashSet::new();
+        let mut entry_keys = HashSet::new();
+        for stream in 0..streams {
+            let space = KeySpace::new(stream, streams);
+            for index in 0..1000 {
+                let key = space.key(index);
+                assert!(keys.insert(key));
+                for position in 0..MAX_ARRAY_LENGTH as usize {
+                    assert!(entry_keys.insert(KeySpace::entry_key(key, position)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn suffixes_keep_emails_addresses() {
+        let mut unique = UniqueFields::default();
+        unique.set_spec("name, email").unwrap();
+        assert_eq!(
+            unique.value(PoolField::Name, "Wolf Group", 7),
+            "Wolf Group 7"
+        );
+        assert_eq!(
+            unique.value(PoolField::Email, "ann@example.com", 7),
+            "ann.7@example.com"
+        );
+        assert!(matches!(
+            unique.value(PoolField::City, "Berlin", 7),
+            Cow::Borrowed("Berlin")
+        ));
+        assert!(unique.set_spec("revenue").is_err());
+    }
+
+    #[test]
+    fn unsatisfiable_fields_are_refused() {
+        let mut unique = UniqueFields::default();
+        unique.insert(PoolField::Name);
+        let mut nesting = NestingOptions::default();
+        let mut gaps = GapRates::default();
+        gaps.set(RecordField::Name, Gap::Missing, 0.5).unwrap();
+        assert!(unique.validate(&gaps, &nesting).is_ok());
+        gaps.set(RecordField::Name, Gap::Null, 0.1).unwrap();
+        assert!(unique.validate(&gaps, &nesting).is_err());
+
+        let mut unique = UniqueFields::default();
+        unique.insert(PoolField::Email);
+        assert!(unique.validate(&GapRates::default(), &nesting).is_err());
+        nesting.enable(Nested::Contacts);
+        assert!(unique.validate(&GapRates::default(), &nesting).is_ok());
+        unique.insert(PoolField::Tag);
+        assert!(unique.validate(&GapRates::default(), &nesting).is_err());
+        nesting.tags = Some(Lengths::DEFAULT_TAGS);
+        assert!(unique.validate(&GapRates::default(), &nesting).is_ok());
+    }
+}
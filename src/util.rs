@@ -1,6 +1,11 @@
+#[cfg(feature = "progress-tui")]
+use crate::jobs::JobRegistry;
+use crate::jobs::{JobState, JobStatus};
+use crate::metrics::METRICS;
 use crate::processing::OutputFormat;
 use anyhow::{Context, Result};
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+#[cfg(feature = "progress-tui")]
 use crossterm::{
     cursor,
     style::Print,
@@ -8,66 +13,151 @@ use crossterm::{
     QueueableCommand,
 };
 use parking_lot::Mutex;
+#[cfg(feature = "progress-tui")]
 use std::io::{stdout, Write};
-use std::time::Instant;
+#[cfg(feature = "progress-tui")]
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tracing::warn;
 pub struct ProgressInfo {
+    id: u64,
+    state: AtomicU8,
     current_bytes: AtomicU64,
     target_bytes: u64,
     streamed_bytes: AtomicU64,
-    last_printed: Mutex<Instant>,
     byte_size: u64,
     format: String,
+    output_format: OutputFormat,
+    records: AtomicU64,
+    started: Instant,
+    elapsed_at_finish: Mutex<Option<Duration>>,
 }
 
 impl ProgressInfo {
-    pub fn new(target_size_bytes: u64, factor: u64, format: String) -> Self {
+    pub fn new(
+        id: u64,
+        target_size_bytes: u64,
+        factor: u64,
+        format: String,
+        output_format: OutputFormat,
+    ) -> Self {
         Self {
+            id,
+            state: AtomicU8::new(JobState::Queued as u8),
             current_bytes: AtomicU64::new(0),
             target_bytes: target_size_bytes,
             streamed_bytes: AtomicU64::new(0),
-            last_printed: Mutex::new(Instant::now()),
             byte_size: factor,
             format: format.to_uppercase(),
+            output_format,
+            records: AtomicU64::new(0),
+            started: Instant::now(),
+            elapsed_at_finish: Mutex::new(None),
         }
     }
 
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     pub fn total_size(&self) -> u64 {
         self.target_bytes
     }
 
+    pub fn state(&self) -> JobState {
+        JobState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    pub fn set_state(&self, state: JobState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+        if state.is_finished() {
+            self.elapsed_at_finish
+                .lock()
+                .get_or_insert_with(|| self.started.elapsed());
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at_finish
+            .lock()
+            .unwrap_or_else(|| self.started.elapsed())
+    }
+
     pub fn update(&self, chunk_size: usize) {
         self.current_bytes
             .fetch_add(chunk_size as u64, Ordering::Relaxed);
+        METRICS
+            .format(self.output_format)
+            .bytes_generated
+            .fetch_add(chunk_size as u64, Ordering::Relaxed);
     }
 
     pub fn update_streamed(&self, chunk_size: usize) {
         self.streamed_bytes
             .fetch_add(chunk_size as u64, Ordering::Relaxed);
+        METRICS
+            .format(self.output_format)
+            .bytes_streamed
+            .fetch_add(chunk_size as u64, Ordering::Relaxed);
     }
 
-    pub fn print_header(&self, type_item: OutputFormat) {
-        print!("\x1B[2J\x1B[1;1H");
-        println!("Starting new streaming data generation request:");
-        println!("------------------------------------");
-        println!(
-            "Requested size: {}{}",
-            self.target_bytes / self.byte_size,
-            self.format
-        );
-        println!("Format: {}", type_item.to_string());
+    pub fn update_records(&self, records: u64) {
+        self.records.fetch_add(records, Ordering::Relaxed);
+        METRICS
+            .format(self.output_format)
+            .records
+            .fetch_add(records, Ordering::Relaxed);
     }
 
-    pub fn print_progress(&self) {
-        let mut last_printed = self.last_printed.lock();
-        let now = Instant::now();
-        if now.duration_since(*last_printed).as_millis() < 252 {
-            return;
+    /// Records this request's generation throughput in the metrics histogram.
+    pub fn finish(&self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            let generated = self.current_bytes.load(Ordering::Relaxed) as f64;
+            METRICS.throughput.observe(generated / elapsed);
         }
-        *last_printed = now;
+    }
+
+    pub fn status(&self) -> JobStatus {
+        let generated = self.current_bytes.load(Ordering::Relaxed);
+        let streamed = self.streamed_bytes.load(Ordering::Relaxed);
+        let target = self.target_bytes.max(1) as f64;
+        let elapsed = self.elapsed().as_secs_f64();
+        let state = self.state();
+
+        let rate = if elapsed > 0.0 {
+            streamed as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if state == JobState::Running && rate > 0.0 {
+            Some(self.target_bytes.saturating_sub(streamed) as f64 / rate)
+        } else {
+            None
+        };
+
+        JobStatus {
+            id: self.id,
+            state,
+            format: self.output_format.to_string().to_owned(),
+            target_bytes: self.target_bytes,
+            bytes_generated: generated,
+            bytes_streamed: streamed,
+            records: self.records.load(Ordering::Relaxed),
+            generated_percent: (generated as f64 / target * 100.0).min(100.0),
+            streamed_percent: (streamed as f64 / target * 100.0).min(100.0),
+            elapsed_seconds: elapsed,
+            rate_bytes_per_second: rate,
+            eta_seconds: eta,
+        }
+    }
 
+    /// The job's header and its generation and streaming bars, one per line.
+    pub fn progress_lines(&self) -> [String; 3] {
         let current = self.current_bytes.load(Ordering::Relaxed) as f64;
         let streamed = self.streamed_bytes.load(Ordering::Relaxed) as f64;
-        let target = self.target_bytes as f64;
+        let target = self.target_bytes.max(1) as f64;
 
         let gen_percentage = (current / target).min(1.0);
         let stream_percentage = (streamed / target).min(1.0);
@@ -79,11 +169,19 @@ impl ProgressInfo {
         let streamed_mb = streamed / self.byte_size as f64;
         let target_mb = target / self.byte_size as f64;
 
-        let mut stdout = stdout();
         let format = self.format.clone();
 
+        let header = format!(
+            "Job #{} [{:?}] {} {}{}",
+            self.id,
+            self.state(),
+            self.output_format.to_string(),
+            self.target_bytes / self.byte_size,
+            self.format
+        );
+
         let gen_line = format!(
-            "Generating data: [{}{}] {:.2}{format}/{:.2}{format} ({:.2}%)",
+            "  Generating data: [{}{}] {:.2}{format}/{:.2}{format} ({:.2}%)",
             "=".repeat(gen_filled),
             ".".repeat(50 - gen_filled),
             current_mb,
@@ -92,7 +190,7 @@ impl ProgressInfo {
         );
 
         let stream_line = format!(
-            "Streaming data:  [{}{}] {:.2}{format}/{:.2}{format} ({:.2}%)",
+            "  Streaming data:  [{}{}] {:.2}{format}/{:.2}{format} ({:.2}%)",
             "=".repeat(stream_filled),
             ".".repeat(50 - stream_filled),
             streamed_mb,
@@ -100,35 +198,59 @@ impl ProgressInfo {
             stream_percentage * 100.0
         );
 
+        [header, gen_line, stream_line]
+    }
+}
+
+/// Terminal view listing every active job, redrawn on a fixed interval from a
+/// background thread.
+#[cfg(feature = "progress-tui")]
+pub struct Dashboard;
+
+#[cfg(feature = "progress-tui")]
+impl Dashboard {
+    pub fn spawn(registry: Arc<JobRegistry>, interval: Duration) {
+        std::thread::Builder::new()
+            .name("dashboard".into())
+            .spawn(move || {
+                let mut drawn_empty = false;
+                loop {
+                    std::thread::sleep(interval);
+
+                    let jobs = registry.active();
+                    if jobs.is_empty() && drawn_empty {
+                        continue;
+                    }
+                    drawn_empty = jobs.is_empty();
+
+                    if let Err(e) = Self::draw(&jobs) {
+                        warn!(error = %e, "failed to draw the dashboard");
+                    }
+                }
+            })
+            .expect("failed to spawn dashboard thread");
+    }
+
+    fn draw(jobs: &[Arc<ProgressInfo>]) -> std::io::Result<()> {
+        let mut stdout = stdout();
+
         stdout
-            .queue(cursor::SavePosition)
-            .unwrap()
-            .queue(cursor::MoveTo(0, 5))
-            .unwrap()
-            .queue(Clear(ClearType::FromCursorDown))
-            .unwrap()
-            .queue(Print(&gen_line))
-            .unwrap()
-            .queue(cursor::MoveToNextLine(1))
-            .unwrap()
-            .queue(Print(&stream_line))
-            .unwrap();
-
-        if streamed_mb >= target_mb {
-            stdout
-                .queue(cursor::MoveToNextLine(1))
-                .unwrap()
-                .queue(Clear(ClearType::CurrentLine))
-                .unwrap()
-                .queue(Print("Generation and streaming complete!\n"))
-                .unwrap();
+            .queue(cursor::MoveTo(0, 0))?
+            .queue(Clear(ClearType::All))?
+            .queue(Print(format!("Active generation jobs: {}", jobs.len())))?
+            .queue(cursor::MoveToNextLine(1))?
+            .queue(Print("------------------------------------"))?
+            .queue(cursor::MoveToNextLine(1))?;
+
+        for job in jobs {
+            for line in job.progress_lines() {
+                stdout
+                    .queue(Print(line))?
+                    .queue(cursor::MoveToNextLine(1))?;
+            }
         }
 
-        stdout
-            .queue(cursor::RestorePosition)
-            .unwrap()
-            .flush()
-            .unwrap();
+        stdout.flush()
     }
 }
 
@@ -139,10 +261,23 @@ pub struct SizeInfo {
     pub unit: String,
 }
 
+/// Units accepted by `parse_size`, with their multipliers.
+pub const SIZE_UNITS: [(&str, u64); 5] = [
+    ("b", 8),
+    ("kb", 1024_u64.pow(1)),
+    ("mb", 1024_u64.pow(2)),
+    ("gb", 1024_u64.pow(3)),
+    ("tb", 1024_u64.pow(4)),
+    // ("pb", 1024_u64.pow(5)),
+    // ("eb", 1024_u64.pow(6)),
+    // ("zb", 1024_u64.pow(7)),
+    // ("yb", 1024_u64.pow(8)),
+];
+
 pub fn parse_size(size_str: &str) -> Result<SizeInfo, String> {
     let size_str = size_str.to_lowercase();
     let (number_str, unit) = size_str
-        .find(|c: char| !c.is_digit(10))
+        .find(|c: char| !c.is_ascii_digit())
         .map(|i| size_str.split_at(i))
         .ok_or_else(|| "Invalid format".to_string())?;
 
@@ -150,21 +285,18 @@ pub fn parse_size(size_str: &str) -> Result<SizeInfo, String> {
         .parse()
         .map_err(|_| "Invalid number".to_string())?;
 
-    let multiplier = match unit {
-        "b" => 8,
-        "kb" => 1024_u64.pow(1),
-        "mb" => 1024_u64.pow(2),
-        "gb" => 1024_u64.pow(3),
-        "tb" => 1024_u64.pow(4),
-        // "pb" => 1024_u64.pow(5),
-        // "eb" => 1024_u64.pow(6),
-        // "zb" => 1024_u64.pow(7),
-        // "yb" => 1024_u64.pow(8),
-        _ => return Err("Invalid unit".to_string()),
-    };
+    let multiplier = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, multiplier)| *multiplier)
+        .ok_or_else(|| "Invalid unit".to_string())?;
+
+    let total_size = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| "Size too large".to_string())?;
 
     Ok(SizeInfo {
-        total_size: number * multiplier,
+        total_size,
         multiplier,
         unit: unit.to_owned(),
     })
@@ -180,5 +312,48 @@ pub fn get_size_info(size: Option<&String>) -> Result<SizeInfo> {
 }
 
 pub fn convert_error(err: anyhow::Error) -> actix_web::Error {
-    actix_web::error::ErrorBadRequest(err.to_string())
+    let message = format!("{:#}", err);
+    warn!(error = %message, "rejecting bad request");
+    actix_web::error::ErrorBadRequest(message)
+}
+
+/// An interval ticking `per_second` times a second, or `None` for no limit.
+pub fn rate_interval(per_second: f64) -> Option<Interval> {
+    if per_second <= 0.0 || !per_second.is_finite() {
+        return None;
+    }
+    let mut interval = interval(Duration::from_secs_f64(1.0 / per_second));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    Some(interval)
+}
+
+/// Waits for the next tick, or returns immediately when there is no limit.
+pub async fn tick(interval: &mut Option<Interval>) {
+    if let Some(interval) = interval {
+        interval.tick().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_scale_by_their_unit() {
+        let size = parse_size("3MB").unwrap();
+        assert_eq!(size.total_size, 3 * 1024 * 1024);
+        assert_eq!(size.unit, "mb");
+        assert_eq!(parse_size("10").unwrap_err(), "Invalid format");
+        assert_eq!(parse_size("10pb").unwrap_err(), "Invalid unit");
+    }
+
+    #[test]
+    fn overflowing_sizes_are_refused() {
+        assert_eq!(parse_size("16777216tb").unwrap_err(), "Size too large");
+        assert_eq!(parse_size("16777217tb").unwrap_err(), "Size too large");
+        assert_eq!(
+            parse_size("16777215tb").unwrap().total_size,
+            16777215 * 1024_u64.pow(4)
+        );
+    }
 }
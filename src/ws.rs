@@ -0,0 +1,277 @@
+use crate::pipeline::{Framing, GenerationRequest, GenerationService};
+use crate::processing::OutputFormat;
+use crate::util::{convert_error, rate_interval, tick};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use bytes::Bytes;
+use bytestring::ByteString;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use tokio::time::Interval;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+/// What each WebSocket message carries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    /// One record per message.
+    Record,
+    /// One pipeline chunk per message, records separated by newlines.
+    Chunk,
+}
+
+/// Control messages a client may send as JSON text frames, e.g.
+/// `{"action":"rate","per_second":50}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Control {
+    Pause,
+    Resume,
+    /// Messages per second; zero or less removes the limit.
+    Rate {
+        per_second: f64,
+    },
+    Stop,
+}
+
+/// Streams a generation over a WebSocket.
+///
+/// Takes the `/generate` parameters plus `unit` (`record` or `chunk`), `binary`
+/// and an initial `rate`. Records are newline-delimited, so JSON arrives as one
+/// object per record rather than as an array.
+#[tracing::instrument(
+    name = "ws_generate",
+    skip_all,
+    fields(job_id = tracing::field::Empty, format = tracing::field::Empty, size = tracing::field::Empty)
+)]
+pub async fn ws_generate(
+    req: HttpRequest,
+    body: web::Payload,
+    service: web::Data<GenerationService>,
+    web::Query(params): web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (request, unit, binary, rate) = {
+        let _parse = info_span!("parse_params").entered();
+
+        let request = GenerationRequest::from_query(&params, service.config())
+            .map_err(convert_error)?
+            .with_framing(Framing::Lines);
+        // Protobuf records are not newline-delimited, so they can only be
+        // sent a chunk at a time; each chunk is an encoded `RecordBatch`.
+        let protobuf = request.format == OutputFormat::PROTOBUF;
+        let unit = match params.get("unit").map(String::as_str) {
+            None if protobuf => Unit::Chunk,
+            None | Some("record") => Unit::Record,
+            Some("chunk") => Unit::Chunk,
+            Some(other) => {
+                return Err(convert_error(anyhow::anyhow!(
+                    "Unknown unit '{}', expected record or chunk",
+                    other
+                )))
+            }
+        };
+        if protobuf && unit == Unit::Record {
+            return Err(convert_error(anyhow::anyhow!(
+                "unit=record is not supported for protobuf, use unit=chunk"
+            )));
+        }
+        let binary = protobuf || params.get("binary").is_some_and(|v| v == "true");
+        let rate = match params.get("rate") {
+            Some(rate) => rate
+                .parse::<f64>()
+                .map_err(|_| convert_error(anyhow::anyhow!("Invalid rate '{}'", rate)))?,
+            None => 0.0,
+        };
+
+        (request, unit, binary, rate)
+    };
+
+    // Check the handshake before taking a generation slot. Nothing is sent
+    // until the response is returned, so a rejected generation still gets
+    // its error status instead of the upgrade.
+    let (mut response, session, messages) = actix_ws::handle(&req, body)?;
+    let generation = service.start(request).await?;
+
+    response.headers_mut().insert(
+        HeaderName::from_static("x-request-id"),
+        HeaderValue::from(generation.job_id),
+    );
+
+    let mut socket = Socket {
+        session,
+        binary,
+        unit,
+        paused: false,
+        interval: rate_interval(rate),
+        pending: VecDeque::new(),
+    };
+    actix_web::rt::spawn(
+        async move {
+            let reason = socket.run(generation.body, messages).await;
+            let _ = socket.session.close(reason).await;
+        }
+        .in_current_span(),
+    );
+
+    Ok(response)
+}
+
+struct Socket {
+    session: Session,
+    binary: bool,
+    unit: Unit,
+    paused: bool,
+    interval: Option<Interval>,
+    pending: VecDeque<Bytes>,
+}
+
+impl Socket {
+    /// Pumps generated data to the client until the generation ends, the
+    /// client stops it, or the socket closes. Returns the close reason to send.
+    ///
+    /// Only one message is in flight at a time and the pipeline is only polled
+    /// once `pending` drains, so a slow socket or a pause holds back generation
+    /// instead of buffering it.
+    async fn run(
+        &mut self,
+        mut body: tokio::sync::mpsc::Receiver<Bytes>,
+        mut messages: MessageStream,
+    ) -> Option<CloseReason> {
+        let mut finished = false;
+
+        loop {
+            if finished && self.pending.is_empty() {
+                info!("websocket generation complete");
+                return Some(CloseCode::Normal.into());
+            }
+            let can_send = !self.paused && !self.pending.is_empty();
+            let needs_data = !self.paused && !finished && self.pending.is_empty();
+
+            tokio::select! {
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                        Ok(control) => {
+                            if !self.apply(control) {
+                                return Some(CloseCode::Normal.into());
+                            }
+                        }
+                        Err(e) => debug!(error = %e, "ignoring unknown control message"),
+                    },
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if self.session.pong(&bytes).await.is_err() {
+                            return None;
+                        }
+                    }
+                    Some(Ok(Message::Close(reason))) => {
+                        debug!(?reason, "client closed the websocket");
+                        return None;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        debug!(error = %e, "websocket closed with an error");
+                        return Some(CloseCode::Protocol.into());
+                    }
+                    None => return None,
+                },
+                chunk = body.recv(), if needs_data => match chunk {
+                    Some(chunk) => self.enqueue(chunk),
+                    None => finished = true,
+                },
+                _ = tick(&mut self.interval), if can_send => {
+                    let message = self.pending.pop_front().expect("checked non-empty");
+                    if self.send(message).await.is_err() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies a control message. Returns false when the client asked to stop.
+    fn apply(&mut self, control: Control) -> bool {
+        debug!(?control, "websocket control message");
+        match control {
+            Control::Pause => self.paused = true,
+            Control::Resume => self.paused = false,
+            Control::Rate { per_second } => self.interval = rate_interval(per_second),
+            Control::Stop => {
+                info!("client stopped the websocket generation");
+                return false;
+            }
+        }
+        true
+    }
+
+    fn enqueue(&mut self, chunk: Bytes) {
+        match self.unit {
+            Unit::Chunk => self.pending.push_back(chunk),
+            Unit::Record => {
+                let mut chunk = chunk;
+                while let Some(end) = chunk.iter().position(|&b| b == b'\n') {
+                    let mut record = chunk.split_to(end + 1);
+                    record.truncate(end);
+                    self.pending.push_back(record);
+                }
+                if !chunk.is_empty() {
+                    self.pending.push_back(chunk);
+                }
+            }
+        }
+    }
+
+    async fn send(&mut self, message: Bytes) -> Result<(), actix_ws::Closed> {
+        if self.binary {
+            return self.session.binary(message).await;
+        }
+        match ByteString::try_from(message.clone()) {
+            Ok(text) => self.session.text(text).await,
+            Err(e) => {
+                warn!(error = %e, "generated data is not valid UTF-8, sending as binary");
+                self.session.binary(message).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::jobs::JobRegistry;
+    use crate::processing::{Locale, PoolCache};
+    use crate::scheduler::Scheduler;
+    use crate::shutdown::ShutdownCoordinator;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn failed_handshakes_take_no_generation_slot() {
+        let pools = PoolCache::ready(Locale::En).await;
+        let scheduler = Scheduler::new(1, 1, 0, Duration::from_secs(1)).unwrap();
+        let registry = Arc::new(JobRegistry::new());
+        let service = GenerationService::new(
+            Arc::new(ServerConfig::default()),
+            scheduler.clone(),
+            registry.clone(),
+            pools,
+            Arc::new(ShutdownCoordinator::new()),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(service))
+                .route("/ws/generate", web::get().to(ws_generate)),
+        )
+        .await;
+
+        // A plain GET, without the upgrade headers.
+        let request = test::TestRequest::get()
+            .uri("/ws/generate?size=1mb")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(scheduler.active(), 0);
+        assert!(registry.statuses().is_empty());
+    }
+}